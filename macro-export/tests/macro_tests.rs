@@ -5,6 +5,7 @@ use misc_lib::EnumCycle;
 mod tests {
     use super::*;
 
+    #[allow(clippy::upper_case_acronyms)]
     #[derive(Debug, Clone, Copy, EnumCycle, PartialEq, Eq, PartialOrd, Ord, Default)]
     enum RGB {
         #[default]
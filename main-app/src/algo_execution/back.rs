@@ -5,13 +5,40 @@ use crate::{
         state_init::PureCircuitResource,
     },
 };
-use bevy::prelude::*;
+use bevy::{
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task, block_on, futures_lite::future},
+};
 use itertools::Itertools;
-use pure_circuit_lib::{gates::Value, solution_finders::backtracking::BacktrackAlgorithm};
+use pure_circuit_lib::{
+    gates::Value,
+    solution_finders::{
+        backtracking::BacktrackAlgorithm,
+        ranking::{RankedSolutions, bot_count},
+        solver_error::SolverError,
+        solver_trait::Budget,
+    },
+};
+use std::time::Duration;
 
 pub(super) struct BacktrackPlugin;
 
-const MAX_LIMIT: usize = 20;
+/// Number of solutions pulled from the search at a time
+const BATCH_SIZE: usize = 20;
+
+/// Budget of a single batch. Every node may add a partial assignment to the frontier of the
+/// search, and a batch that runs out leaves the search where it stopped
+fn batch_budget() -> Budget {
+    Budget {
+        max_nodes: Some(100_000),
+        time_limit: Some(Duration::from_secs(2)),
+        ..Default::default()
+    }
+}
+
+/// Shown once a batch ran out of its budget
+const INTERRUPTED: &str =
+    "Backtracking ran out of its budget, step past the last solution to resume the search";
 
 type SolSetType = Option<Vec<Vec<Option<Value>>>>;
 
@@ -21,10 +48,65 @@ pub(crate) struct SolutionIndex(pub Option<usize>);
 #[derive(Debug, Resource, Default, PartialEq)]
 pub struct SolutionSet(pub SolSetType);
 
+/// Paused search of the backtracking algorithm, purest solutions first.
+/// `None` once every solution has been found
+#[derive(Debug, Resource, Default)]
+pub struct SolutionStream(pub Option<RankedSolutions>);
+
+/// Solutions pulled from the search at once
+struct Batch {
+    solutions: Vec<Vec<Option<Value>>>,
+    /// The search, unless it has no solutions left
+    rest: Option<RankedSolutions>,
+    interrupted: bool,
+}
+
+/// Pull the next batch of solutions from the search within a fresh `batch_budget`
+fn next_batch(search: RankedSolutions) -> Result<Batch, SolverError> {
+    let mut search = search.with_budget(&batch_budget());
+    let solutions = search
+        .by_ref()
+        .take(BATCH_SIZE)
+        .map(|sol| sol.map(|sol| sol.solution))
+        .collect::<Result<Vec<_>, _>>()?;
+    let interrupted = search.is_interrupted();
+    let rest = (solutions.len() == BATCH_SIZE || interrupted).then_some(search);
+    Ok(Batch {
+        solutions,
+        rest,
+        interrupted,
+    })
+}
+
+/// Outcome of a batch
+struct BatchRun {
+    /// The batch continues the solution set instead of replacing it
+    extend: bool,
+    result: Result<Batch, SolverError>,
+}
+
+/// Batch running on the async compute pool, so that the UI stays responsive
+#[derive(Resource, Default)]
+pub struct RunningBatch(Option<Task<BatchRun>>);
+
+impl RunningBatch {
+    /// Pull the next batch from `search` off the main thread
+    fn spawn(&mut self, search: RankedSolutions, extend: bool) {
+        self.0 = Some(AsyncComputeTaskPool::get().spawn(async move {
+            BatchRun {
+                extend,
+                result: next_batch(search),
+            }
+        }));
+    }
+}
+
 impl Plugin for BacktrackPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<SolutionIndex>()
             .init_resource::<SolutionSet>()
+            .init_resource::<SolutionStream>()
+            .init_resource::<RunningBatch>()
             .add_systems(
                 Update,
                 execute_backtrack_handler.run_if(resource_equals(IsAlgoCurrentlyRunning(false))),
@@ -38,48 +120,97 @@ impl Plugin for BacktrackPlugin {
                         .and(not(resource_equals(SolutionSet(None))))
                         .and(resource_equals(IsAlgoCurrentlyRunning(false))),
                 ),
+            )
+            .add_systems(
+                Update,
+                finish_batch.run_if(resource_equals(IsAlgoCurrentlyRunning(true))),
             );
     }
 }
 
-/// Event handler for executing the backtracking algorithm
+/// Event handler for starting the backtracking algorithm
 pub(super) fn execute_backtrack_handler(
     mut event_back: EventReader<BacktrackEvent>,
     mut sol_set: ResMut<SolutionSet>,
+    mut sol_stream: ResMut<SolutionStream>,
     mut sol_index: ResMut<SolutionIndex>,
     mut algo_handle: ResMut<IsAlgoCurrentlyRunning>,
+    mut running: ResMut<RunningBatch>,
     pc_resource: Res<PureCircuitResource>,
     mut err_message: ResMut<ErrorMessage>,
 ) {
-    for _ in event_back.read() {
-        if pc_resource.0.get_value_count() > MAX_LIMIT || pc_resource.0.get_value_count() == 0 {
-            continue;
+    // Clicks in the same frame start a single run
+    if event_back.read().last().is_none() || pc_resource.0.get_value_count() == 0 {
+        return;
+    }
+    match BacktrackAlgorithm.ranked_solutions(&pc_resource.0, bot_count) {
+        Ok(search) => {
+            running.spawn(search, false);
+            algo_handle.0 = true;
+        }
+        Err(e) => {
+            err_message.set(&format!("Unable to run backtrack method: {e}"));
+            error!("{}", e.to_string());
+            sol_set.0 = None;
+            sol_stream.0 = None;
+            sol_index.0 = None;
         }
-        algo_handle.0 = true;
-        match BacktrackAlgorithm.calculate(&pc_resource.0) {
-            Ok(v) => {
+    }
+}
+
+/// Store the solutions of the batch once it finished
+fn finish_batch(
+    mut running: ResMut<RunningBatch>,
+    mut sol_set: ResMut<SolutionSet>,
+    mut sol_stream: ResMut<SolutionStream>,
+    mut sol_index: ResMut<SolutionIndex>,
+    mut algo_handle: ResMut<IsAlgoCurrentlyRunning>,
+    mut err_message: ResMut<ErrorMessage>,
+) {
+    let Some(task) = running.0.as_mut() else {
+        return;
+    };
+    let Some(BatchRun { extend, result }) = block_on(future::poll_once(task)) else {
+        return;
+    };
+    running.0 = None;
+    match result {
+        Ok(batch) => {
+            if !extend {
                 err_message.reset();
-                sol_set.0 = Some(v);
+                sol_set.0 = Some(vec![]);
                 sol_index.0 = None;
             }
-            Err(e) => {
-                err_message.set(
-                    "Unable to run backtrack method. Check if there are any invalid arity gates",
-                );
-                error!("{}", e.to_string());
+            if batch.interrupted {
+                err_message.set(INTERRUPTED);
+            }
+            // None if the solutions were reset while the batch ran
+            if let Some(solutions) = sol_set.0.as_mut() {
+                solutions.extend(batch.solutions);
+                sol_stream.0 = batch.rest;
+            }
+        }
+        Err(e) => {
+            err_message.set(&format!("Unable to run backtrack method: {e}"));
+            error!("{}", e.to_string());
+            if !extend {
                 sol_set.0 = None;
                 sol_index.0 = None;
             }
         }
-
-        algo_handle.0 = false;
     }
+
+    algo_handle.0 = false;
 }
 
-/// Event handler for enumerating through found solutions
+/// Event handler for enumerating through found solutions.
+/// Reaching the last found solution resumes the search for the next batch in the background
 fn modify_index(
     sol_index: Res<SolutionIndex>,
     sol_set: Res<SolutionSet>,
+    mut sol_stream: ResMut<SolutionStream>,
+    mut algo_handle: ResMut<IsAlgoCurrentlyRunning>,
+    mut running: ResMut<RunningBatch>,
     mut pc_resource: ResMut<PureCircuitResource>,
     mut event_writer_status: EventWriter<NodeUpdate>,
     mut event_writer: EventWriter<NodeStatusUpdate>,
@@ -91,6 +222,12 @@ fn modify_index(
         .0
         .as_ref()
         .expect("Should be safe by the system conditions");
+    if sol_index == sol_set.len() {
+        if let Some(search) = sol_stream.0.take() {
+            running.spawn(search, true);
+            algo_handle.0 = true;
+        }
+    }
     let Some(sol) = sol_set.get(sol_index - 1) else {
         warn!("Solution index does not match solution set");
         return;
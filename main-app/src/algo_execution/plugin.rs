@@ -1,9 +1,14 @@
-use bevy::{prelude::*, tasks::AsyncComputeTaskPool};
+use bevy::{
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task, block_on, futures_lite::future},
+};
 use itertools::Itertools;
 use pure_circuit_lib::solution_finders::{
-    self,
-    evo_search::{Build, EvoParamSet, HillParamSet, SolverEvo, SolverHillClimb},
-    solver_trait::SolverTrait,
+    evo_search::{
+        Build, EvoParamSet, HillParamSet, Instance, SolutionReturn, SolverEvo, SolverHillClimb,
+    },
+    solver_error::SolverError,
+    solver_trait::{Budget, CancellationToken, SolverTrait},
 };
 
 use crate::{
@@ -42,6 +47,7 @@ impl Plugin for AlgoPlugin {
             .init_resource::<IsAlgoCurrentlyRunning>()
             .init_resource::<HillParam>()
             .init_resource::<EvoParam>()
+            .init_resource::<RunningSolver>()
             .add_systems(
                 Update,
                 execute_evo_climbing.run_if(resource_equals(IsAlgoCurrentlyRunning(false))),
@@ -49,6 +55,10 @@ impl Plugin for AlgoPlugin {
             .add_systems(
                 Update,
                 execute_hill_climbing.run_if(resource_equals(IsAlgoCurrentlyRunning(false))),
+            )
+            .add_systems(
+                Update,
+                finish_solver.run_if(resource_equals(IsAlgoCurrentlyRunning(true))),
             );
     }
 }
@@ -57,71 +67,126 @@ impl Plugin for AlgoPlugin {
 /// Resource to prevent multiple algorithms for running
 pub struct IsAlgoCurrentlyRunning(pub bool);
 
-/// Event handler for executing the hill climbing algorihtm 
-#[allow(clippy::too_many_arguments)]
+/// Outcome of a meta-heuristic run
+struct SolverRun {
+    /// Name of the algorithm in the messages
+    name: &'static str,
+    result: Result<SolutionReturn, SolverError>,
+}
+
+/// Meta-heuristic running on the async compute pool, so that the UI stays responsive
+#[derive(Resource, Default)]
+pub struct RunningSolver {
+    task: Option<Task<SolverRun>>,
+    /// Budget of the running algorithm, the Cancel button cancels its token
+    budget: Option<Budget>,
+}
+
+impl RunningSolver {
+    /// Run `solver` off the main thread. `budget` has to be the budget of `param_set`
+    fn spawn<S>(&mut self, name: &'static str, solver: S, param_set: S::ParamSet, budget: Budget)
+    where
+        S: SolverTrait<Solution = SolutionReturn> + Send + 'static,
+        S::ParamSet: Send + 'static,
+    {
+        self.budget = Some(budget);
+        self.task = Some(AsyncComputeTaskPool::get().spawn(async move {
+            SolverRun {
+                name,
+                result: solver.find_solution(param_set),
+            }
+        }));
+    }
+
+    pub(crate) fn is_running(&self) -> bool {
+        self.task.is_some()
+    }
+
+    /// Stop the running algorithm, it returns the best assignment found so far
+    pub(crate) fn cancel(&self) {
+        if let Some(budget) = &self.budget {
+            budget.cancellation.cancel();
+        }
+    }
+}
+
+/// Budget of a new run. The token of the parameters is shared by every run, hence each run
+/// gets a fresh one so that cancelling it does not cancel the next runs
+fn run_budget(budget: &Budget) -> Budget {
+    Budget {
+        cancellation: CancellationToken::new(),
+        ..budget.clone()
+    }
+}
+
+/// Event handler for starting the hill climbing algorithm
 fn execute_hill_climbing(
     hill_params: Res<HillParam>,
     mut event_reader_hill: EventReader<ButtonHillEvent>,
-    mut pc_resource: ResMut<PureCircuitResource>,
+    pc_resource: Res<PureCircuitResource>,
     mut err_message: ResMut<ErrorMessage>,
     mut algo_handle: ResMut<IsAlgoCurrentlyRunning>,
-    mut event_writer_status: EventWriter<NodeUpdate>,
-    mut event_writer: EventWriter<NodeStatusUpdate>,
-    mut event_idx_writer: EventWriter<IndexReset>,
+    mut running: ResMut<RunningSolver>,
 ) {
-    let solver = SolverHillClimb::default();
-    for _ in event_reader_hill.read() {
-        algo_handle.0 = true;
-        let Some(func) = pc_resource.0.to_fitness_function() else {
-            err_message.set(
-                "Unable to create fitness function. Check if there are any invalid arity gates",
-            );
-            algo_handle.0 = false;
+    // Clicks in the same frame start a single run
+    if event_reader_hill.read().last().is_none() {
+        return;
+    }
+    let func = match pc_resource.0.to_fitness_function() {
+        Ok(func) => func,
+        Err(e) => {
+            err_message.set(&format!("Unable to create fitness function: {e}"));
             return;
-        };
-        let count = pc_resource.0.count_values();
-        let param_set = hill_params
-            .0
-            .build(solution_finders::evo_search::Instance::new(func, count));
-        match solver.find_solution(param_set) {
-            Ok(e) => {
-                if pc_resource.0.from_chromosone(&e.chromosone).is_none() {
-                    error!("Failed to import chromosone");
-                } else {
-                    info!("PC has been successfully imported");
-                    event_writer_status.write_batch(
-                        pc_resource
-                            .0
-                            .graph
-                            .node_indices()
-                            .map(NodeUpdate)
-                            .collect_vec(),
-                    );
-                    event_writer.write_batch(
-                        pc_resource
-                            .0
-                            .graph
-                            .node_indices()
-                            .filter(|p| pc_resource.0.graph[*p].into_node().is_gate())
-                            .map(NodeStatusUpdate)
-                            .collect_vec(),
-                    );
-                }
-                err_message.reset();
-            }
-            Err(e) => error!("{}", e.to_string()),
         }
-
-        event_idx_writer.write_default();
-        algo_handle.0 = false;
-    }
+    };
+    let params = HillParamSet {
+        budget: run_budget(&hill_params.0.budget),
+        ..hill_params.0.clone()
+    };
+    let budget = params.budget.clone();
+    let param_set = params.build(Instance::new(func, pc_resource.0.count_values()));
+    running.spawn(
+        "Hill climbing",
+        SolverHillClimb::default(),
+        param_set,
+        budget,
+    );
+    algo_handle.0 = true;
 }
 
-/// Event handler for executing the genetic algorithm algorihtm 
-#[allow(clippy::too_many_arguments)]
+/// Event handler for starting the genetic algorithm
 fn execute_evo_climbing(
     evo_params: Res<EvoParam>,
-    mut event_reader_hill: EventReader<ButtonEvoEvent>,
+    mut event_reader_evo: EventReader<ButtonEvoEvent>,
+    pc_resource: Res<PureCircuitResource>,
+    mut err_message: ResMut<ErrorMessage>,
+    mut algo_handle: ResMut<IsAlgoCurrentlyRunning>,
+    mut running: ResMut<RunningSolver>,
+) {
+    // Clicks in the same frame start a single run
+    if event_reader_evo.read().last().is_none() {
+        return;
+    }
+    let func = match pc_resource.0.to_fitness_function() {
+        Ok(func) => func,
+        Err(e) => {
+            err_message.set(&format!("Unable to create fitness function: {e}"));
+            return;
+        }
+    };
+    let params = EvoParamSet {
+        budget: run_budget(&evo_params.0.budget),
+        ..evo_params.0.clone()
+    };
+    let budget = params.budget.clone();
+    let param_set = params.build(Instance::new(func, pc_resource.0.count_values()));
+    running.spawn("Genetic algorithm", SolverEvo::default(), param_set, budget);
+    algo_handle.0 = true;
+}
+
+/// Import the assignment of the meta-heuristic once it finished
+fn finish_solver(
+    mut running: ResMut<RunningSolver>,
     mut pc_resource: ResMut<PureCircuitResource>,
     mut err_message: ResMut<ErrorMessage>,
     mut algo_handle: ResMut<IsAlgoCurrentlyRunning>,
@@ -129,52 +194,57 @@ fn execute_evo_climbing(
     mut event_writer: EventWriter<NodeStatusUpdate>,
     mut event_idx_writer: EventWriter<IndexReset>,
 ) {
-    let solver = SolverEvo::default();
-    for _ in event_reader_hill.read() {
-        algo_handle.0 = true;
-        let Some(func) = pc_resource.0.to_fitness_function() else {
-            err_message.set(
-                "Unable to create fitness function. Check if there are any invalid arity gates",
-            );
-            algo_handle.0 = false;
-            continue;
-        };
-        let count = pc_resource.0.count_values();
-        let param_set = evo_params
-            .0
-            .build(solution_finders::evo_search::Instance::new(func, count));
-        let res = AsyncComputeTaskPool::get()
-            .scope(|s| s.spawn(async { solver.find_solution(param_set) }));
-        match &res[0] {
-            Ok(e) => {
-                if pc_resource.0.from_chromosone(&e.chromosone).is_none() {
-                    error!("Failed to import chromosone");
-                } else {
-                    info!("PC has been successfully imported");
-                    event_writer_status.write_batch(
-                        pc_resource
-                            .0
-                            .graph
-                            .node_indices()
-                            .map(NodeUpdate)
-                            .collect_vec(),
-                    );
-                    event_writer.write_batch(
-                        pc_resource
-                            .0
-                            .graph
-                            .node_indices()
-                            .filter(|p| pc_resource.0.graph[*p].into_node().is_gate())
-                            .map(NodeStatusUpdate)
-                            .collect_vec(),
-                    );
-                    err_message.reset();
-                }
+    let Some(task) = running.task.as_mut() else {
+        return;
+    };
+    let Some(SolverRun { name, result }) = block_on(future::poll_once(task)) else {
+        return;
+    };
+    running.task = None;
+    let cancelled = running
+        .budget
+        .take()
+        .is_some_and(|budget| budget.cancellation.is_cancelled());
+    match result {
+        Ok(e) => {
+            if let Err(err) = pc_resource.0.from_chromosone(&e.chromosone) {
+                error!("Failed to import chromosone: {err}");
+            } else {
+                info!("PC has been successfully imported");
+                event_writer_status.write_batch(
+                    pc_resource
+                        .0
+                        .graph
+                        .node_indices()
+                        .map(NodeUpdate)
+                        .collect_vec(),
+                );
+                event_writer.write_batch(
+                    pc_resource
+                        .0
+                        .graph
+                        .node_indices()
+                        .filter(|p| pc_resource.0.graph[*p].into_node().is_gate())
+                        .map(NodeStatusUpdate)
+                        .collect_vec(),
+                );
+            }
+            match (e.interrupted, cancelled) {
+                (true, true) => err_message.set(&format!(
+                    "{name} was cancelled, showing the best assignment"
+                )),
+                (true, false) => err_message.set(&format!(
+                    "{name} ran out of time, showing the best assignment"
+                )),
+                (false, _) => err_message.reset(),
             }
-            Err(e) => error!("{}", e.to_string()),
         }
-
-        event_idx_writer.write_default();
-        algo_handle.0 = false;
+        Err(e) => {
+            err_message.set(&format!("Unable to run {}: {e}", name.to_lowercase()));
+            error!("{}", e.to_string())
+        }
     }
+
+    event_idx_writer.write_default();
+    algo_handle.0 = false;
 }
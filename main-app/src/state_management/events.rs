@@ -3,7 +3,7 @@ use petgraph::prelude::*;
 use pure_circuit_lib::gates::{GraphStruct, NodeValue};
 
 use crate::{
-    algo_execution::back::{SolutionIndex, SolutionSet},
+    algo_execution::back::{SolutionIndex, SolutionSet, SolutionStream},
     drawing_plugin::{ErrorCircle, GateStatusComponent, value_spawner},
     state_management::{node_addition_state::ValueComponent, state_init::PureCircuitResource},
 };
@@ -112,10 +112,12 @@ pub fn manage_solution_reset(
     mut event_reader: EventReader<SolutionReset>,
     mut sol_indx: ResMut<SolutionIndex>,
     mut sol_val: ResMut<SolutionSet>,
+    mut sol_stream: ResMut<SolutionStream>,
 ) {
     for _ in event_reader.read() {
         sol_indx.0 = None;
         sol_val.0 = None;
+        sol_stream.0 = None;
     }
 }
 
@@ -1,5 +1,7 @@
 use crate::algo_execution::back::{SolutionIndex, SolutionSet};
-use crate::algo_execution::plugin::{ErrorMessage, EvoParam, HillParam, IsAlgoCurrentlyRunning};
+use crate::algo_execution::plugin::{
+    ErrorMessage, EvoParam, HillParam, IsAlgoCurrentlyRunning, RunningSolver,
+};
 use crate::state_management::edge_management::EdgeManagementState;
 use crate::state_management::events::{BacktrackEvent, ButtonEvoEvent, ButtonHillEvent};
 use crate::state_management::node_addition_state::{GateMode, ValueState};
@@ -15,6 +17,8 @@ use genetic_algorithm::select::{SelectElite, SelectTournament, SelectWrapper};
 use genetic_algorithm::strategy::prelude::HillClimbVariant;
 use pure_circuit_lib::gates::{Gate, Value};
 use pure_circuit_lib::solution_finders::evo_search::NewType;
+use pure_circuit_lib::solution_finders::solver_trait::Budget;
+use std::time::Duration;
 
 pub struct UiPlugin;
 
@@ -53,7 +57,8 @@ fn render_ui_window(
     solution_set: Res<SolutionSet>,
     pc_resource: Res<PureCircuitResource>,
     err_message: Res<ErrorMessage>,
-    algo_status: Res<IsAlgoCurrentlyRunning>,
+    // Paired, as systems take at most 16 parameters
+    (algo_status, running_solver): (Res<IsAlgoCurrentlyRunning>, Res<RunningSolver>),
     mut hill_params: ResMut<HillParam>,
     mut evo_params: ResMut<EvoParam>,
     mut contexts: EguiContexts,
@@ -178,6 +183,9 @@ fn render_ui_window(
                         "Not Running"
                     };
                     ui.label(label);
+                    if running_solver.is_running() && ui.button("Cancel").clicked() {
+                        running_solver.cancel();
+                    }
                 });
                 ui.end_row();
                 ui.separator();
@@ -210,6 +218,7 @@ fn hill_climb_ui(
         ui.label("With parallelisation:");
         ui.checkbox(&mut hill_params.0.with_parallel,"");
         ui.end_row();
+        budget_ui(ui, &mut hill_params.0.budget);
         ui.label("Population Size size:");
         ui.add(egui::DragValue::new(&mut hill_params.0.population_size).range(10..=500usize));
         ui.end_row();
@@ -248,6 +257,7 @@ fn genetic_algorithm_ui(
         ui.label("With parallelisation:");
         ui.checkbox(&mut evo_params.0.with_parallel,"");
         ui.end_row();
+        budget_ui(ui, &mut evo_params.0.budget);
         ui.label("Population Size size:");
         ui.add(egui::DragValue::new(&mut evo_params.0.population_size).range(10..=500usize));
         ui.end_row();
@@ -258,6 +268,18 @@ fn genetic_algorithm_ui(
     ui.end_row();
 }
 
+fn budget_ui(ui: &mut Ui, budget: &mut Budget) {
+    ui.label("Time limit in seconds (0 for no limit):");
+    let mut secs = budget.time_limit.map_or(0, |t| t.as_secs());
+    if ui
+        .add(egui::DragValue::new(&mut secs).range(0..=600u64))
+        .changed()
+    {
+        budget.time_limit = (secs > 0).then(|| Duration::from_secs(secs));
+    }
+    ui.end_row();
+}
+
 fn selection_ui(ui: &mut Ui, evo_params: &mut EvoParam) {
     ui.label("Genetic algorithm Selection Strategy: ");
     ui.vertical(|ui| {
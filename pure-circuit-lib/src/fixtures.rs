@@ -0,0 +1,139 @@
+//! Small circuits with known solutions, shared by the tests of the solvers.
+use petgraph::graph::NodeIndex;
+
+use crate::{
+    gates::{Gate, NodeUnitialised, NodeValue, Value},
+    graph::PureCircuitGraph,
+};
+
+/// Not gates in a ring, only the all-⊥ assignment satisfies a ring of odd length
+pub fn not_ring(len: usize) -> PureCircuitGraph {
+    let mut pc = PureCircuitGraph::new();
+    let nodes = (0..len)
+        .map(|_| pc.add_node(NodeUnitialised::from_value(Value::Bot), ()))
+        .collect::<Vec<_>>();
+    for (i, n) in nodes.iter().enumerate() {
+        let g = pc.add_node(NodeUnitialised::from_gate(Gate::Not), ());
+        pc.add_edge(*n, g, ()).unwrap();
+        pc.add_edge(g, nodes[(i + 1) % len], ()).unwrap();
+    }
+    pc
+}
+
+/// Purify gate, its input feeds back through a Copy from the first output
+pub fn purify_loop() -> PureCircuitGraph {
+    let mut pc = PureCircuitGraph::new();
+    let input = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+    let out_1 = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+    let out_2 = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+    let purify = pc.add_node(NodeUnitialised::from_gate(Gate::Purify), ());
+    let copy = pc.add_node(NodeUnitialised::from_gate(Gate::Copy), ());
+    pc.add_edge(input, purify, ()).unwrap();
+    pc.add_edge(purify, out_1, ()).unwrap();
+    pc.add_edge(purify, out_2, ()).unwrap();
+    pc.add_edge(out_1, copy, ()).unwrap();
+    pc.add_edge(copy, input, ()).unwrap();
+    pc
+}
+
+/// Two Purify gates in a line, the second one fed by the second output of the first. 7 solutions
+pub fn purify_chain() -> PureCircuitGraph {
+    let mut pc = PureCircuitGraph::new();
+    let v1 = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+    let v2 = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+    let v3 = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+    let g1 = pc.add_node(NodeUnitialised::from_gate(Gate::Purify), ());
+    let v4 = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+    let v5 = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+    let g2 = pc.add_node(NodeUnitialised::from_gate(Gate::Purify), ());
+    pc.add_edge(v1, g1, ()).unwrap();
+    pc.add_edge(g1, v2, ()).unwrap();
+    pc.add_edge(g1, v3, ()).unwrap();
+    pc.add_edge(v3, g2, ()).unwrap();
+    pc.add_edge(g2, v4, ()).unwrap();
+    pc.add_edge(g2, v5, ()).unwrap();
+    pc
+}
+
+/// Copy gates in a line, 3 solutions
+pub fn copy_chain(len: usize) -> PureCircuitGraph {
+    let mut pc = PureCircuitGraph::new();
+    let mut prev = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+    for _ in 0..len {
+        let next = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+        let g = pc.add_node(NodeUnitialised::from_gate(Gate::Copy), ());
+        pc.add_edge(prev, g, ()).unwrap();
+        pc.add_edge(g, next, ()).unwrap();
+        prev = next;
+    }
+    pc
+}
+
+/// Not gates in a line, 3 solutions
+pub fn not_chain(len: usize) -> PureCircuitGraph {
+    let mut pc = PureCircuitGraph::new();
+    let mut prev = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+    for _ in 0..len {
+        let next = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+        let g = pc.add_node(NodeUnitialised::from_gate(Gate::Not), ());
+        pc.add_edge(prev, g, ()).unwrap();
+        pc.add_edge(g, next, ()).unwrap();
+        prev = next;
+    }
+    pc
+}
+
+/// Disjoint Copy gates, node `3i` copies into node `3i + 1` through the gate at node `3i + 2`.
+/// In the order of the chromosome these are the positions `2i` and `2i + 1`. 3^count solutions
+pub fn copy_pairs(count: usize) -> PureCircuitGraph {
+    let mut pc = PureCircuitGraph::new();
+    for _ in 0..count {
+        let a = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+        let b = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+        let g = pc.add_node(NodeUnitialised::from_gate(Gate::Copy), ());
+        pc.add_edge(a, g, ()).unwrap();
+        pc.add_edge(g, b, ()).unwrap();
+    }
+    pc
+}
+
+/// Not gate looping on node 0, which forces it to ⊥, feeding a Purify whose outputs both go to
+/// node 2. Purify cannot output ⊥ twice, hence no solutions
+pub fn dead_loop() -> PureCircuitGraph {
+    let mut pc = PureCircuitGraph::new();
+    let v1 = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+    let g = pc.add_node(NodeUnitialised::from_gate(Gate::Not), ());
+    pc.add_edge(v1, g, ()).unwrap();
+    pc.add_edge(g, v1, ()).unwrap();
+    let v2 = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+    let g = pc.add_node(NodeUnitialised::from_gate(Gate::Purify), ());
+    pc.add_edge(v1, g, ()).unwrap();
+    pc.add_edge(g, v2, ()).unwrap();
+    pc.add_edge(g, v2, ()).unwrap();
+    pc
+}
+
+/// Circuits side by side, the nodes of each part are numbered after those of the previous ones
+pub fn disjoint(parts: impl IntoIterator<Item = PureCircuitGraph>) -> PureCircuitGraph {
+    let mut pc = PureCircuitGraph::new();
+    for part in parts {
+        let offset = pc.graph.node_count();
+        for weight in part.graph.node_weights() {
+            let node = match weight.node {
+                NodeValue::ValueNode(v) => NodeUnitialised::from_value(v),
+                NodeValue::GateNode { gate, .. } => NodeUnitialised::from_gate(gate),
+            };
+            pc.add_node(node, ());
+        }
+        // Edges in the order they were added, so the gates keep their ports
+        for (src, dst, _) in part.get_edges() {
+            pc.add_edge(
+                NodeIndex::new(offset + src.index()),
+                NodeIndex::new(offset + dst.index()),
+                (),
+            )
+            .unwrap();
+        }
+    }
+    pc
+}
@@ -3,9 +3,10 @@ use macro_export::EnumCycle;
 use misc_lib::EnumCycle;
 use std::fmt::{Debug, Display};
 use std::hash::Hash;
-use strum_macros::EnumIter;
+use strum_macros::{Display, EnumIter};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Rand, Default, EnumIter, Hash, EnumCycle)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Value {
     #[default]
     Bot,
@@ -47,8 +48,8 @@ impl PartialOrd for InformationOrdering {
     }
 }
 
+#[allow(clippy::non_canonical_partial_ord_impl)]
 impl PartialOrd for VoltageOrdering {
-    #[allow(clippy::non_canonical_partial_ord_impl)]
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         let fst = match self.0 {
             Value::Zero => 0,
@@ -80,6 +81,26 @@ pub enum ConversionError {
 }
 
 impl Value {
+    /// Every value in the order of `Value::index`, `Value::ALL[value.index()] == value`
+    pub const ALL: [Value; 3] = [Value::Bot, Value::Zero, Value::One];
+
+    /// Position of the value in `Value::ALL`, for tables indexed by value.
+    /// Unlike `usize::from`, which orders the values for drawing, ⊥ comes first
+    /// # Example
+    /// ```
+    /// use pure_circuit_lib::gates::Value;
+    /// for value in Value::ALL {
+    ///     assert_eq!(Value::ALL[value.index()], value);
+    /// }
+    /// ```
+    pub const fn index(self) -> usize {
+        match self {
+            Self::Bot => 0,
+            Self::Zero => 1,
+            Self::One => 2,
+        }
+    }
+
     pub fn inverse(self) -> Self {
         match self {
             Self::One => Self::Zero,
@@ -90,6 +111,7 @@ impl Value {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Rand, Hash, EnumCycle, Default, EnumIter)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Gate {
     #[default]
     Copy,
@@ -107,12 +129,14 @@ impl Display for Gate {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
 pub enum GateError {
     ArityError,
     NonDeterminsticGate,
 }
 
+impl std::error::Error for GateError {}
+
 impl Gate {
     pub fn arity(&self) -> (usize, usize) {
         match self {
@@ -133,7 +157,7 @@ impl Gate {
     /// Error if we are applying on purify since it is a non-deterministic gate
     /// Error if the arity of thegate is not satisfied
     pub(crate) fn apply(self, in_vals: &[Value]) -> Result<Value, GateError> {
-        if in_vals.len() != self.arity().0 && self.arity().1 > 1 {
+        if in_vals.len() != self.arity().0 {
             return Err(GateError::ArityError);
         }
         match self {
@@ -170,14 +194,14 @@ impl Gate {
                     .contains(&(out_vals[0], out_vals[1])),
                     b => out_vals == [b, b],
                 }),
-                b if out_vals.len() == 1 => b.apply(in_vals).map(|val| val == out_vals[0]),
-                b => unimplemented!("Gate {b:?} not implemented"),
+                b => b.apply(in_vals).map(|val| out_vals == [val]),
             }
         }
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum GateStatus {
     Valid,
     InvalidArity,
@@ -185,6 +209,7 @@ pub enum GateStatus {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum NodeType {
     Value,
     Gate,
@@ -193,12 +218,14 @@ pub enum NodeType {
 pub trait NodeStateTrait: Debug + Clone + Copy + PartialEq + Eq + Hash {}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NewNode;
 
 impl NodeStateTrait for NewNode {}
 impl NodeStateTrait for GateStatus {}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum NodeValue<I: NodeStateTrait> {
     ValueNode(Value),
     GateNode { gate: Gate, state_type: I },
@@ -219,6 +246,7 @@ pub type NodeUnitialised = NodeValue<NewNode>;
 pub type GraphNode = NodeValue<GateStatus>;
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GraphStruct<T> {
     pub node: GraphNode,
     pub additional_info: T,
@@ -291,7 +319,7 @@ impl<T: NodeStateTrait> NodeValue<T> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::test_utils::enum_strategy;
+    use crate::strategies::enum_strategy;
     use itertools::Itertools;
     use proptest::prelude::*;
     use strum::IntoEnumIterator;
@@ -386,8 +414,6 @@ mod tests {
     }
 
     mod binary_gates {
-        use mockall::predicate::str::contains;
-
         #[allow(unused_imports)]
         use super::*;
 
@@ -9,6 +9,7 @@ use crate::gates::{
 pub type BoxArray<T> = Box<[T]>;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PureCircuitGraph<T = (), G = ()> {
     pub graph: StableDiGraph<GraphStruct<T>, (u64, G)>,
 }
@@ -197,9 +198,7 @@ impl<T: Copy, G: Copy> PureCircuitGraph<T, G> {
                 }
             }
             Err(GateError::ArityError) => *state_type = GateStatus::InvalidArity,
-            Err(GateError::NonDeterminsticGate) => {
-                unreachable!("Check should not generate such error")
-            }
+            Err(GateError::NonDeterminsticGate) => return Err(GraphError::InvalidUpdate),
         };
         Ok(NodeValue::<GateStatus>::GateNode {
             gate,
@@ -334,7 +333,7 @@ impl<T: Copy, G: Copy> PureCircuitGraph<T, G> {
             .edges_connecting(src_idx, dest_idx)
             .map(|e| e.id())
             .collect::<Box<[_]>>();
-        if edges.len() == 0 {
+        if edges.is_empty() {
             return Err(GraphError::NotExistentEdge);
         }
 
@@ -412,7 +411,7 @@ mod tests {
     use super::*;
     use crate::gates::Gate;
     use crate::gates::Value;
-    use crate::test_utils::enum_strategy;
+    use crate::strategies::enum_strategy;
     use anyhow::Result as ARes;
     use itertools::Itertools;
     use proptest::prelude::*;
@@ -480,6 +479,7 @@ mod tests {
             })
         }
 
+        #[allow(clippy::type_complexity)]
         fn generate_heterogeneous_graph() -> impl Strategy<
             Value = (
                 Box<[(bool, usize, usize)]>,
@@ -578,11 +578,11 @@ mod tests {
                 let (edges, array) = s;
                 let node_indxes = graph.add_nodes(array.iter().cloned().map(|x| (x, ()))).collect_vec();
                 for (src, dst) in edges {
-                    let flag = match (array[src], array[dst]) {
+                    let flag = matches!(
+                        (array[src], array[dst]),
                         (NodeUnitialised::GateNode { .. }, NodeUnitialised::ValueNode(_))
-                        | (NodeUnitialised::ValueNode(_), NodeUnitialised::GateNode { .. }) => true,
-                        _ => false,
-                    };
+                            | (NodeUnitialised::ValueNode(_), NodeUnitialised::GateNode { .. })
+                    );
                     let result = graph.add_edge(node_indxes[src], node_indxes[dst], ());
                     prop_assert!(
                         flag == result.is_ok()
@@ -658,4 +658,145 @@ mod tests {
 
         }
     }
+
+    mod fuzz_tests {
+        use super::*;
+        use crate::gates::NodeUnitialised;
+        use proptest::sample::Index;
+
+        /// Operations of the public graph API. Node indices are resolved against every node
+        /// created so far, hence operations also target removed nodes.
+        #[derive(Debug, Clone)]
+        enum Op {
+            AddNode(NodeUnitialised),
+            AddEdge(Index, Index),
+            RemoveEdge(Index, Index),
+            RemoveNode(Index),
+            UpdateNode(Index, NodeUnitialised),
+            UpdateStatus(Index),
+            FromChromosone(Vec<Value>),
+        }
+
+        fn node_strategy() -> impl Strategy<Value = NodeUnitialised> {
+            prop_oneof![
+                enum_strategy::<Value>().prop_map(NodeUnitialised::from_value),
+                enum_strategy::<Gate>().prop_map(NodeUnitialised::from_gate)
+            ]
+        }
+
+        fn op_strategy() -> impl Strategy<Value = Op> {
+            prop_oneof![
+                3 => node_strategy().prop_map(Op::AddNode),
+                4 => (any::<Index>(), any::<Index>()).prop_map(|(a, b)| Op::AddEdge(a, b)),
+                1 => (any::<Index>(), any::<Index>()).prop_map(|(a, b)| Op::RemoveEdge(a, b)),
+                1 => any::<Index>().prop_map(Op::RemoveNode),
+                1 => (any::<Index>(), node_strategy()).prop_map(|(a, n)| Op::UpdateNode(a, n)),
+                1 => any::<Index>().prop_map(Op::UpdateStatus),
+                1 => prop::collection::vec(enum_strategy::<Value>(), 0..6).prop_map(Op::FromChromosone),
+            ]
+        }
+
+        fn resolve(nodes: &[NodeIndex], i: &Index) -> NodeIndex {
+            if nodes.is_empty() {
+                NodeIndex::new(0)
+            } else {
+                nodes[i.index(nodes.len())]
+            }
+        }
+
+        fn apply(pc: &mut PureCircuitGraph, nodes: &mut Vec<NodeIndex>, op: &Op) {
+            match op {
+                Op::AddNode(n) => nodes.push(pc.add_node(*n, ())),
+                Op::AddEdge(a, b) => {
+                    let _ = pc.add_edge(resolve(nodes, a), resolve(nodes, b), ());
+                }
+                Op::RemoveEdge(a, b) => {
+                    let _ = pc.remove_edge(resolve(nodes, a), resolve(nodes, b));
+                }
+                Op::RemoveNode(a) => {
+                    if let Ok(neigh) = pc.remove_node(resolve(nodes, a)) {
+                        for n in neigh {
+                            let _ = pc.update_node_status(n);
+                        }
+                    }
+                }
+                Op::UpdateNode(a, n) => {
+                    let _ = pc.update_node(resolve(nodes, a), *n);
+                }
+                Op::UpdateStatus(a) => {
+                    let _ = pc.update_node_status(resolve(nodes, a));
+                }
+                Op::FromChromosone(c) => {
+                    let _ = pc.from_chromosone(c);
+                }
+            }
+        }
+
+        /// Query the read-only API. Every call must return instead of panicking.
+        fn query(pc: &PureCircuitGraph, nodes: &[NodeIndex]) -> Result<(), TestCaseError> {
+            for n in nodes {
+                let _ = pc.get_all_neigh(*n);
+            }
+            let chromosome = pc.to_chromosone();
+            prop_assert_eq!(chromosome.len(), pc.count_values());
+            let _ = pc.get_error_gates().count();
+            let valid = pc.validate_arity();
+            let fitness = pc.to_fitness_function();
+            prop_assert_eq!(valid.is_ok(), fitness.is_ok());
+            if let Ok(fitness) = fitness {
+                prop_assert!(fitness.evaluate(&chromosome).is_ok());
+                let short = &chromosome[..chromosome.len().saturating_sub(1)];
+                prop_assert_eq!(
+                    fitness.evaluate(short).is_ok(),
+                    short.len() >= fitness.required_len()
+                );
+            }
+            #[cfg(feature = "backtrack")]
+            {
+                use crate::solution_finders::backtracking::BacktrackAlgorithm;
+                if let Ok(sols) = BacktrackAlgorithm.calculate(pc) {
+                    for sol in sols {
+                        let mut other = pc.clone();
+                        prop_assert!(other.from_backtrack_sol(&sol).is_ok());
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        proptest! {
+            #![proptest_config(ProptestConfig::with_cases(128))]
+            #[test]
+            fn operation_sequences(ops in prop::collection::vec(op_strategy(), 0..40)) {
+                let mut pc = PureCircuitGraph::new();
+                let mut nodes = vec![];
+                query(&pc, &nodes)?;
+                for op in &ops {
+                    apply(&mut pc, &mut nodes, op);
+                    // Keep the exhaustive solver cheap
+                    if pc.count_values() <= 6 {
+                        query(&pc, &nodes)?;
+                    }
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    mod serde_tests {
+        use super::*;
+
+        proptest! {
+            #[test]
+            fn round_trip(pc in any::<PureCircuitGraph>()) {
+                let json = serde_json::to_string(&pc).unwrap();
+                let other: PureCircuitGraph = serde_json::from_str(&json).unwrap();
+                prop_assert_eq!(
+                    pc.graph.node_weights().map(GraphStruct::into_node).collect_vec(),
+                    other.graph.node_weights().map(GraphStruct::into_node).collect_vec()
+                );
+                prop_assert_eq!(pc.get_edges().collect_vec(), other.get_edges().collect_vec());
+            }
+        }
+    }
 }
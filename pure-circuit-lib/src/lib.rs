@@ -2,8 +2,11 @@ pub mod gates;
 pub mod graph;
 pub mod solution_finders;
 
+#[cfg(any(test, feature = "proptest"))]
+pub mod strategies;
+
 #[cfg(test)]
-mod test_utils;
+pub mod fixtures;
 
 
 pub use macro_export::EnumCycle;
@@ -0,0 +1,440 @@
+use std::time::Instant;
+
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use strum::IntoEnumIterator;
+
+use crate::{
+    gates::Value,
+    solution_finders::{
+        base_finder::FitnessPureCircuit,
+        evo_search::{Build, Instance, SolutionReturn, SolverStruct},
+        observer::{SharedObserver, SolverEvent},
+        solver_error::SolverError,
+        solver_trait::{Budget, BudgetMonitor, SolveStats, SolverTrait},
+    },
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+/// Proxy type for the simulated annealing algorithm
+pub struct AnnealingAlgorithm;
+
+pub type SolverAnnealing = SolverStruct<AnnealingAlgorithm>;
+
+/// How the temperature decreases between levels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CoolingSchedule {
+    /// Multiply the temperature by `alpha` in `(0, 1)`
+    Geometric { alpha: f64 },
+    /// Subtract `decrement` from the temperature
+    Linear { decrement: f64 },
+    /// Geometric cooling that reheats to `reheat` times the initial temperature, once the best
+    /// score did not improve for `patience` levels or the temperature froze
+    Adaptive {
+        alpha: f64,
+        patience: usize,
+        reheat: f64,
+    },
+}
+
+impl Default for CoolingSchedule {
+    fn default() -> Self {
+        Self::Geometric { alpha: 0.95 }
+    }
+}
+
+impl CoolingSchedule {
+    /// Temperature of the next level, None once it falls below `min`
+    /// * `stale` - Levels since the best score last improved
+    fn next(&self, temperature: f64, initial: f64, min: f64, stale: usize) -> Option<f64> {
+        let next = match *self {
+            Self::Geometric { alpha } => temperature * alpha,
+            Self::Linear { decrement } => temperature - decrement,
+            Self::Adaptive {
+                alpha,
+                patience,
+                reheat,
+            } => {
+                let cooled = temperature * alpha;
+                if cooled < min || (stale > 0 && stale.checked_rem(patience) == Some(0)) {
+                    initial * reheat
+                } else {
+                    cooled
+                }
+            }
+        };
+        (next >= min).then_some(next)
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Parameter set of the simulated annealing algorithm
+pub struct AnnealingParamSet<T> {
+    pub param_type: T,
+    pub schedule: CoolingSchedule,
+    pub initial_temperature: f64,
+    /// The schedule stops once the temperature falls below this
+    pub min_temperature: f64,
+    /// Moves tried at each temperature level. Every level counts as a generation
+    pub moves_per_level: usize,
+    /// Stop after this many levels without improving the best score
+    pub stale_generations: usize,
+    /// Seed of the random moves, None draws one from the OS
+    pub seed: Option<u64>,
+    pub budget: Budget,
+    pub observer: Option<SharedObserver>,
+}
+
+impl Default for AnnealingParamSet<Build> {
+    fn default() -> Self {
+        Self {
+            param_type: Build,
+            schedule: CoolingSchedule::default(),
+            initial_temperature: 2.0,
+            min_temperature: 1e-3,
+            moves_per_level: 100,
+            stale_generations: 1000,
+            seed: None,
+            budget: Budget::default(),
+            observer: None,
+        }
+    }
+}
+
+impl AnnealingParamSet<Build> {
+    pub fn build(
+        &self,
+        instance: Instance<FitnessPureCircuit>,
+    ) -> AnnealingParamSet<Instance<FitnessPureCircuit>> {
+        AnnealingParamSet {
+            param_type: instance,
+            schedule: self.schedule,
+            initial_temperature: self.initial_temperature,
+            min_temperature: self.min_temperature,
+            moves_per_level: self.moves_per_level,
+            stale_generations: self.stale_generations,
+            seed: self.seed,
+            budget: self.budget.clone(),
+            observer: self.observer.clone(),
+        }
+    }
+}
+
+/// Current and best assignments of an annealing run
+struct Annealer {
+    fitness: FitnessPureCircuit,
+    monitor: BudgetMonitor,
+    rng: StdRng,
+    current: Vec<Value>,
+    errors: usize,
+    best: Option<(Vec<Value>, usize)>,
+}
+
+impl Annealer {
+    /// Score the current assignment, None once the budget runs out
+    /// # Parameters
+    /// * `changed` - Genes changed since `errors` was scored with their previous value,
+    ///   None to score from scratch
+    fn evaluate(
+        &mut self,
+        changed: Option<&[(usize, Value)]>,
+    ) -> Result<Option<usize>, SolverError> {
+        if self.monitor.tick() {
+            return Ok(None);
+        }
+        let errors = match changed {
+            Some(changed) => self
+                .fitness
+                .evaluate_delta(self.errors, changed, &self.current)?,
+            None => self.fitness.evaluate(&self.current)?,
+        };
+        if self.best.as_ref().is_none_or(|(_, b)| errors < *b) {
+            self.best = Some((self.current.clone(), errors));
+        }
+        Ok(Some(errors))
+    }
+
+    /// Change the value of a random value node, keeping the change with the Metropolis rule
+    /// # Returns
+    /// false once the budget runs out
+    fn step(&mut self, temperature: f64) -> Result<bool, SolverError> {
+        let gene = self.rng.random_range(0..self.current.len());
+        let old = self.current[gene];
+        let offset = self.rng.random_range(1..3);
+        let new = Value::iter().cycle().skip_while(|v| *v != old).nth(offset);
+        self.current[gene] = new.unwrap_or(old);
+        // Only the gates next to the gene can change
+        let Some(errors) = self.evaluate(Some(&[(gene, old)]))? else {
+            self.current[gene] = old;
+            return Ok(false);
+        };
+        let delta = errors as f64 - self.errors as f64;
+        if delta <= 0.0 || self.rng.random::<f64>() < (-delta / temperature).exp() {
+            self.errors = errors;
+        } else {
+            self.current[gene] = old;
+        }
+        Ok(true)
+    }
+
+    fn best_errors(&self) -> Option<usize> {
+        self.best.as_ref().map(|(_, b)| *b)
+    }
+}
+
+impl SolverTrait for SolverAnnealing {
+    type ParamSet = AnnealingParamSet<Instance<FitnessPureCircuit>>;
+
+    type Solution = SolutionReturn;
+
+    // Simulated annealing over the assignments, one value node changes per move
+    fn find_solution(&self, param_set: Self::ParamSet) -> Result<Self::Solution, SolverError> {
+        let started = Instant::now();
+        let mut rng = match param_set.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+        let values = Value::iter().collect::<Vec<_>>();
+        let current = (0..param_set.param_type.size)
+            .map(|_| values[rng.random_range(0..values.len())])
+            .collect::<Vec<_>>();
+        let mut annealer = Annealer {
+            fitness: param_set.param_type.func,
+            monitor: param_set.budget.start(),
+            rng,
+            current,
+            errors: 0,
+            best: None,
+        };
+        let notify = |event| {
+            if let Some(observer) = &param_set.observer {
+                observer.on_event(event);
+            }
+        };
+
+        if let Some(errors) = annealer.evaluate(None)? {
+            annealer.errors = errors;
+        }
+        let mut temperature = param_set.initial_temperature;
+        let (mut generation, mut stale) = (0, 0);
+        let mut generation_capped = false;
+        while !annealer.current.is_empty() && annealer.best_errors().is_some_and(|b| b > 0) {
+            if param_set
+                .budget
+                .max_generations
+                .is_some_and(|m| generation >= m)
+            {
+                generation_capped = true;
+                break;
+            }
+            let before = annealer.best_errors();
+            let mut running = true;
+            for _ in 0..param_set.moves_per_level {
+                running = annealer.step(temperature)?;
+                if !running || annealer.best_errors() == Some(0) {
+                    break;
+                }
+            }
+            generation += 1;
+            notify(SolverEvent::Generation {
+                generation,
+                best_fitness: annealer
+                    .best_errors()
+                    .map(|b| isize::try_from(b).unwrap_or(isize::MAX)),
+            });
+            if !running {
+                break;
+            }
+            stale = match annealer.best_errors() < before {
+                true => 0,
+                false => stale + 1,
+            };
+            if stale >= param_set.stale_generations {
+                break;
+            }
+            let next = param_set.schedule.next(
+                temperature,
+                param_set.initial_temperature,
+                param_set.min_temperature,
+                stale,
+            );
+            let Some(next) = next else {
+                break;
+            };
+            temperature = next;
+        }
+
+        let stats = SolveStats {
+            wall_time: started.elapsed(),
+            generations: generation,
+            fitness_evaluations: annealer.monitor.nodes(),
+            ..Default::default()
+        };
+        let interrupted = annealer.monitor.was_interrupted();
+        let solution = match annealer.best {
+            Some((chromosone, errors)) => Ok(SolutionReturn {
+                chromosone,
+                errors,
+                interrupted: interrupted || (generation_capped && errors > 0),
+                stats,
+            }),
+            None => Err(SolverError::Cancelled),
+        };
+        notify(SolverEvent::Finished {
+            interrupted: solution.as_ref().is_ok_and(|s| s.interrupted),
+        });
+        solution
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::not_ring, graph::PureCircuitGraph, solution_finders::observer::ChannelObserver,
+    };
+    use rstest::rstest;
+
+    fn params(
+        pc: &PureCircuitGraph,
+        set: AnnealingParamSet<Build>,
+    ) -> AnnealingParamSet<Instance<FitnessPureCircuit>> {
+        let instance = Instance::new(pc.to_fitness_function().unwrap(), pc.count_values());
+        set.build(instance)
+    }
+
+    #[rstest]
+    #[case(CoolingSchedule::Geometric { alpha: 0.95 })]
+    #[case(CoolingSchedule::Linear { decrement: 0.01 })]
+    #[case(CoolingSchedule::Adaptive { alpha: 0.9, patience: 5, reheat: 0.5 })]
+    fn test_solves_ring(#[case] schedule: CoolingSchedule) {
+        let pc = not_ring(9);
+        let sol = SolverAnnealing::default()
+            .find_solution(params(
+                &pc,
+                AnnealingParamSet {
+                    schedule,
+                    seed: Some(3),
+                    ..Default::default()
+                },
+            ))
+            .unwrap();
+        assert_eq!(sol.errors, 0);
+        assert_eq!(sol.chromosone, vec![Value::Bot; 9]);
+        assert!(!sol.interrupted);
+        assert!(sol.stats.fitness_evaluations > 0);
+    }
+
+    #[test]
+    fn test_schedules() {
+        let geometric = CoolingSchedule::Geometric { alpha: 0.5 };
+        assert_eq!(geometric.next(2.0, 2.0, 0.1, 0), Some(1.0));
+        assert_eq!(geometric.next(0.15, 2.0, 0.1, 0), None);
+        let linear = CoolingSchedule::Linear { decrement: 0.5 };
+        assert_eq!(linear.next(2.0, 2.0, 0.1, 0), Some(1.5));
+        assert_eq!(linear.next(0.5, 2.0, 0.1, 0), None);
+        let adaptive = CoolingSchedule::Adaptive {
+            alpha: 0.5,
+            patience: 3,
+            reheat: 0.75,
+        };
+        assert_eq!(adaptive.next(1.0, 2.0, 0.1, 2), Some(0.5));
+        // Stuck for `patience` levels
+        assert_eq!(adaptive.next(1.0, 2.0, 0.1, 3), Some(1.5));
+        // Frozen
+        assert_eq!(adaptive.next(0.15, 2.0, 0.1, 1), Some(1.5));
+    }
+
+    #[test]
+    fn test_seeded() {
+        let pc = not_ring(15);
+        let run = |seed| {
+            SolverAnnealing::default()
+                .find_solution(params(
+                    &pc,
+                    AnnealingParamSet {
+                        seed: Some(seed),
+                        budget: Budget {
+                            max_nodes: Some(500),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                ))
+                .unwrap()
+        };
+        let (a, b) = (run(1), run(1));
+        assert_eq!(a.chromosone, b.chromosone);
+        assert_eq!(a.stats.fitness_evaluations, b.stats.fitness_evaluations);
+    }
+
+    #[test]
+    fn test_budget() {
+        let pc = not_ring(9);
+        let budget = Budget::default();
+        budget.cancellation.cancel();
+        assert_eq!(
+            SolverAnnealing::default()
+                .find_solution(params(
+                    &pc,
+                    AnnealingParamSet {
+                        budget,
+                        ..Default::default()
+                    }
+                ))
+                .unwrap_err(),
+            SolverError::Cancelled
+        );
+
+        let sol = SolverAnnealing::default()
+            .find_solution(params(
+                &pc,
+                AnnealingParamSet {
+                    moves_per_level: 1,
+                    seed: Some(0),
+                    budget: Budget {
+                        max_generations: Some(2),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            ))
+            .unwrap();
+        assert!(sol.stats.generations <= 2);
+        assert!(sol.interrupted || sol.errors == 0);
+        assert_eq!(
+            pc.to_fitness_function().unwrap().evaluate(&sol.chromosone),
+            Ok(sol.errors)
+        );
+    }
+
+    #[test]
+    fn test_observer() {
+        let (observer, receiver) = ChannelObserver::new();
+        let sol = SolverAnnealing::default()
+            .find_solution(params(
+                &not_ring(9),
+                AnnealingParamSet {
+                    seed: Some(5),
+                    observer: Some(observer.shared()),
+                    ..Default::default()
+                },
+            ))
+            .unwrap();
+        let events = receiver.try_iter().collect::<Vec<_>>();
+        assert_eq!(
+            events.last(),
+            Some(&SolverEvent::Finished {
+                interrupted: sol.interrupted
+            })
+        );
+        let scores = events
+            .iter()
+            .filter_map(|e| match e {
+                SolverEvent::Generation { best_fitness, .. } => *best_fitness,
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(scores.len(), sol.stats.generations);
+        assert!(scores.windows(2).all(|w| w[0] >= w[1]));
+    }
+}
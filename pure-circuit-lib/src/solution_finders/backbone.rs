@@ -0,0 +1,313 @@
+use std::time::Instant;
+
+use petgraph::graph::NodeIndex;
+
+use crate::{
+    gates::Value,
+    graph::PureCircuitGraph,
+    solution_finders::{
+        backtracking::{BacktrackAlgorithm, BitString, CircuitTable, SolutionIter},
+        mdd::SolutionDiagram,
+        solver_error::SolverError,
+        solver_trait::{Budget, BudgetMonitor, SolveStats},
+    },
+};
+
+/// Parameter set of `BacktrackAlgorithm::backbone`
+#[derive(Debug, Clone)]
+pub struct BackboneParamSet {
+    /// Also count how often each value occurs, by compiling the decision diagram of the
+    /// solutions
+    pub frequencies: bool,
+    /// Nodes the decision diagram may take to compile, out of the budget left by the search.
+    /// Larger circuits report no frequencies
+    pub count_limit: usize,
+    pub budget: Budget,
+}
+
+impl Default for BackboneParamSet {
+    fn default() -> Self {
+        Self {
+            frequencies: false,
+            count_limit: 100_000,
+            budget: Budget::default(),
+        }
+    }
+}
+
+/// Analysis returned by `BacktrackAlgorithm::backbone`
+#[derive(Debug, Clone)]
+pub struct BackboneAnalysis {
+    /// Values each value node takes across all solutions, indexed like the solutions.
+    /// None for gates
+    pub values: Vec<Option<BitString>>,
+    /// Number of solutions in which each value node takes each value, indexed like the
+    /// solutions and then by value. None if not requested or the diagram was too large
+    pub frequencies: Option<Vec<Option<[u128; 3]>>>,
+    /// The budget ran out before every value was checked, or before the frequencies were
+    /// counted. `values` then only holds the values found so far, hence the backbone may be
+    /// too large
+    pub interrupted: bool,
+    pub stats: SolveStats,
+}
+
+impl BackboneAnalysis {
+    /// Value nodes that take the same value in every solution, with their value.
+    /// Empty if the circuit has no solution
+    pub fn backbone(&self) -> Vec<(NodeIndex, Value)> {
+        self.values
+            .iter()
+            .enumerate()
+            .filter_map(|(n, values)| match values.map(BitString::to_value_iter) {
+                Some(mut iter) => match (iter.next(), iter.next()) {
+                    (Some(value), None) => Some((NodeIndex::new(n), value)),
+                    _ => None,
+                },
+                None => None,
+            })
+            .collect()
+    }
+}
+
+/// Solve `search` under `assumptions` with the shared `monitor`
+fn solve_under(
+    search: &SolutionIter,
+    assumptions: &[(NodeIndex, Value)],
+    monitor: &BudgetMonitor,
+    stats: &mut SolveStats,
+) -> Result<Option<Vec<Option<Value>>>, SolverError> {
+    let mut search = search
+        .clone()
+        .with_monitor(monitor.clone())
+        .with_assumptions(assumptions)?;
+    let solution = search.next().transpose()?;
+    let run = search.stats();
+    stats.propagations += run.propagations;
+    stats.decisions += run.decisions;
+    stats.conflicts += run.conflicts;
+    stats.restarts += run.restarts;
+    stats.max_depth = stats.max_depth.max(run.max_depth);
+    Ok(solution)
+}
+
+impl BacktrackAlgorithm {
+    /// Find the values every value node takes across all solutions, without enumerating them.
+    ///
+    /// Every value not seen in a solution yet is assumed on its node and solved for: either
+    /// the search finds a solution, whose values are all seen from then on, or it proves that
+    /// no solution takes it. Nodes left with a single value form the backbone.
+    ///
+    /// # Parameters
+    /// * `budget` - Stop once the budget runs out and return the values found so far.
+    ///   Every branch, and every node of the decision diagram, counts as a node
+    ///
+    /// # Errors
+    /// * InvalidArity: Exists gate with invalid arity
+    /// * EmptyGraph: Circuit does not contain any value nodes
+    /// * Cancelled: The budget ran out before the first solution was found
+    pub fn backbone<T, G>(
+        &self,
+        pc_instance: &PureCircuitGraph<T, G>,
+        param_set: &BackboneParamSet,
+    ) -> Result<BackboneAnalysis, SolverError> {
+        let started = Instant::now();
+        let search = self.solutions(pc_instance)?;
+        let monitor = param_set.budget.start();
+        let mut stats = SolveStats::default();
+        let mut values = match solve_under(&search, &[], &monitor, &mut stats)? {
+            Some(first) => first
+                .iter()
+                .map(|value| value.map(BitString::from))
+                .collect::<Vec<_>>(),
+            None if monitor.was_interrupted() => return Err(SolverError::Cancelled),
+            // No solution, every value node is left without values
+            None => CircuitTable::new(pc_instance)?
+                .1
+                .value_map
+                .iter()
+                .map(|domain| domain.map(|_| BitString::default()))
+                .collect(),
+        };
+
+        let mut interrupted = false;
+        'nodes: for n in 0..values.len() {
+            for value in Value::ALL {
+                match values[n] {
+                    Some(seen) if !seen.is_empty() && !seen.contains(value) => (),
+                    _ => continue,
+                }
+                let assumption = [(NodeIndex::new(n), value)];
+                match solve_under(&search, &assumption, &monitor, &mut stats)? {
+                    Some(solution) => {
+                        for (seen, value) in values.iter_mut().zip(solution) {
+                            *seen = seen.zip(value).map(|(seen, value)| seen.insert(value));
+                        }
+                    }
+                    None if monitor.was_interrupted() => {
+                        interrupted = true;
+                        break 'nodes;
+                    }
+                    None => (),
+                }
+            }
+        }
+
+        // The diagram takes its nodes from the same budget as the search
+        let diagram = match param_set.frequencies && !interrupted {
+            true => SolutionDiagram::compile_within(pc_instance, &monitor, param_set.count_limit),
+            false => Ok(None),
+        };
+        let frequencies = match diagram {
+            Ok(diagram) => diagram.map(|diagram| diagram.marginals()),
+            Err(SolverError::Cancelled) => {
+                interrupted = true;
+                None
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(BackboneAnalysis {
+            values,
+            frequencies,
+            interrupted,
+            stats: SolveStats {
+                wall_time: started.elapsed(),
+                ..stats
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{copy_pairs, dead_loop, disjoint, not_ring},
+        strategies::{CircuitParams, circuit_strategy},
+    };
+    use proptest::prelude::*;
+
+    /// Odd Not ring on nodes 0 to 2, which forces every node to ⊥, next to a free Copy from
+    /// node 6 into node 7
+    const RING: [usize; 3] = [0, 1, 2];
+    const COPY: [usize; 2] = [6, 7];
+
+    #[test]
+    fn test_backbone() {
+        let pc = disjoint([not_ring(3), copy_pairs(1)]);
+        let analysis = BacktrackAlgorithm
+            .backbone(&pc, &BackboneParamSet::default())
+            .unwrap();
+        assert!(!analysis.interrupted);
+        assert_eq!(analysis.frequencies, None);
+        assert_eq!(
+            analysis.backbone(),
+            RING.map(|n| (NodeIndex::new(n), Value::Bot))
+        );
+        for n in COPY {
+            assert_eq!(analysis.values[n], Some(BitString::all()));
+        }
+    }
+
+    #[test]
+    fn test_frequencies() {
+        let pc = disjoint([not_ring(3), copy_pairs(1)]);
+        let params = BackboneParamSet {
+            frequencies: true,
+            ..Default::default()
+        };
+        let frequencies = BacktrackAlgorithm
+            .backbone(&pc, &params)
+            .unwrap()
+            .frequencies
+            .unwrap();
+        for n in RING {
+            assert_eq!(frequencies[n], Some([3, 0, 0]));
+        }
+        for n in COPY {
+            assert_eq!(frequencies[n], Some([1, 1, 1]));
+        }
+
+        let params = BackboneParamSet {
+            count_limit: 0,
+            ..params
+        };
+        let analysis = BacktrackAlgorithm.backbone(&pc, &params).unwrap();
+        assert_eq!(analysis.frequencies, None);
+        assert!(!analysis.interrupted);
+    }
+
+    #[test]
+    fn test_shared_budget() {
+        let pc = disjoint([not_ring(3), copy_pairs(1)]);
+        let params = |max_nodes, frequencies| BackboneParamSet {
+            frequencies,
+            budget: Budget {
+                max_nodes: Some(max_nodes),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        // Smallest budget that checks every value, the diagram has no nodes left
+        let max_nodes = (1..1000)
+            .find(|m| {
+                BacktrackAlgorithm
+                    .backbone(&pc, &params(*m, false))
+                    .is_ok_and(|analysis| !analysis.interrupted)
+            })
+            .unwrap();
+        let analysis = BacktrackAlgorithm
+            .backbone(&pc, &params(max_nodes, true))
+            .unwrap();
+        assert!(analysis.interrupted);
+        assert_eq!(analysis.frequencies, None);
+        assert_eq!(analysis.backbone().len(), 3);
+    }
+
+    #[test]
+    fn test_no_solution() {
+        let pc = dead_loop();
+        let analysis = BacktrackAlgorithm
+            .backbone(&pc, &BackboneParamSet::default())
+            .unwrap();
+        assert_eq!(analysis.values[0], Some(BitString::default()));
+        assert_eq!(analysis.values[2], Some(BitString::default()));
+        assert!(analysis.backbone().is_empty());
+    }
+
+    #[test]
+    fn test_budget() {
+        let pc = disjoint([not_ring(3), copy_pairs(1)]);
+        let params = BackboneParamSet::default();
+        params.budget.cancellation.cancel();
+        assert_eq!(
+            BacktrackAlgorithm.backbone(&pc, &params).unwrap_err(),
+            SolverError::Cancelled
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn matches_enumeration(pc in circuit_strategy(CircuitParams::new(1..=8, 0..=10))) {
+            let params = BackboneParamSet {
+                frequencies: true,
+                ..Default::default()
+            };
+            let analysis = BacktrackAlgorithm.backbone(&pc, &params).unwrap();
+            let solutions = BacktrackAlgorithm.calculate(&pc).unwrap();
+            let frequencies = analysis.frequencies.unwrap();
+            for (n, values) in analysis.values.iter().enumerate() {
+                let Some(values) = values else {
+                    continue;
+                };
+                let mut expected = [0u128; 3];
+                for sol in solutions.iter() {
+                    expected[sol[n].unwrap().index()] += 1;
+                }
+                prop_assert_eq!(frequencies[n], Some(expected));
+                for value in Value::ALL {
+                    prop_assert_eq!(values.contains(value), expected[value.index()] > 0);
+                }
+            }
+        }
+    }
+}
@@ -1,13 +1,19 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, iter::FusedIterator, time::Instant};
 
 use crate::{
-    gates::{GraphNode, NodeUnitialised, NodeValue, Value, VoltageOrdering},
-    graph::PureCircuitGraph,
-    solution_finders::base_finder::MAX_DEGREE,
+    gates::{ConversionError, Gate, GraphNode, NodeUnitialised, NodeValue, Value, VoltageOrdering},
+    graph::{GraphError, PureCircuitGraph},
+    solution_finders::{
+        base_finder::MAX_DEGREE,
+        heuristics::{BranchView, BranchingHeuristic, DomainDegree, complete_order},
+        nogood::{Nogood, NogoodStore},
+        observer::{SharedObserver, SolverEvent},
+        solver_error::SolverError,
+        solver_trait::{Budget, BudgetMonitor, SolveStats, SolverTrait},
+    },
 };
-use anyhow::{Result as ARes, anyhow};
 use itertools::Itertools;
-use petgraph::{Direction, prelude::NodeIndex, visit::EdgeRef};
+use petgraph::{Direction, prelude::NodeIndex};
 use priority_queue::PriorityQueue;
 use strum::IntoEnumIterator;
 
@@ -18,6 +24,7 @@ pub struct BacktrackAlgorithm;
 /// (bool for Zero, bool for Bot, bool for One)
 /// The library contains an extensive API for any possible set operation
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BitString(pub(crate) bool, pub(crate) bool, pub(crate) bool);
 
 impl Debug for BitString {
@@ -28,7 +35,7 @@ impl Debug for BitString {
 }
 
 impl TryFrom<u8> for BitString {
-    type Error = anyhow::Error;
+    type Error = ConversionError;
     fn try_from(value: u8) -> Result<Self, Self::Error> {
         if value < 8 {
             Ok(BitString(
@@ -37,7 +44,7 @@ impl TryFrom<u8> for BitString {
                 (value & 0b100) == 4,
             ))
         } else {
-            ARes::Err(anyhow!("Cannot convert to bistring"))
+            Err(ConversionError::OutOfRange)
         }
     }
 }
@@ -83,6 +90,19 @@ impl BitString {
         self.len() == 0
     }
 
+    /// Check if `value` belongs to the set
+    /// # Example
+    /// ```
+    /// use pure_circuit_lib::solution_finders::backtracking::BitString;
+    /// use pure_circuit_lib::gates::Value;
+    /// let set = BitString::all().remove(Value::Bot);
+    /// assert!(set.contains(Value::One));
+    /// assert!(!set.contains(Value::Bot));
+    /// ```
+    pub fn contains(self, value: Value) -> bool {
+        !self.op_inter(value.into()).is_empty()
+    }
+
     /// Create an iterator of the elements
     /// # Example
     /// ```
@@ -245,8 +265,9 @@ impl<T: Copy, G: Copy> PureCircuitGraph<T, G> {
     /// # Returns
     /// Unit if the extraction was successful otherwise the function yields an error.
     /// # Errors
-    /// * If the array is missing the index or the value has not been assigned
-    pub fn from_backtrack_sol(&mut self, v: &[Option<Value>]) -> ARes<()> {
+    /// * MissingAssignment: If the array is missing the index or the value has not been assigned
+    /// * Graph: If the node could not be updated
+    pub fn from_backtrack_sol(&mut self, v: &[Option<Value>]) -> Result<(), SolverError> {
         for n in self.graph.node_indices().collect_vec() {
             if self
                 .graph
@@ -257,7 +278,7 @@ impl<T: Copy, G: Copy> PureCircuitGraph<T, G> {
                 continue;
             }
             let Some(Some(new_val)) = v.get(n.index()) else {
-                return Err(anyhow!("Index missmatch backtrack"));
+                return Err(SolverError::MissingAssignment { node_index: n });
             };
             self.update_node(n, NodeUnitialised::from_value(*new_val))?;
         }
@@ -278,14 +299,14 @@ impl<T: Copy, G: Copy> PureCircuitGraph<T, G> {
 /// * In ascending on number of neighbours
 /// 
 /// Since the PQ is max-based, will always prio few remaining, with in-degree = 0, and high neighbour count
-struct BacktrackKey {
-    value_len: usize,
-    is_start: bool,
-    neighbours: usize,
+pub(crate) struct BacktrackKey {
+    pub(crate) value_len: usize,
+    pub(crate) is_start: bool,
+    pub(crate) neighbours: usize,
 }
 
+#[allow(clippy::non_canonical_partial_ord_impl)]
 impl PartialOrd for BacktrackKey {
-    #[allow(clippy::non_canonical_partial_ord_impl)]
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(
             (
@@ -308,14 +329,14 @@ impl Ord for BacktrackKey {
     }
 }
 
-type BacktrackPQ = PriorityQueue<NodeIndex, BacktrackKey>;
+pub(crate) type BacktrackPQ = PriorityQueue<NodeIndex, BacktrackKey>;
 
 impl<T, G> PureCircuitGraph<T, G> {
     /// Create a sparse vector such that each index denotes empty or a tuple with the:
     /// * NodeIndex
     /// * Whether its a start node
     /// * Neighbour size
-    fn extract_node_graph(&self) -> Vec<Option<(NodeIndex, bool, usize)>> {
+    pub(crate) fn extract_node_graph(&self) -> Vec<Option<(NodeIndex, bool, usize)>> {
         let v = self
             .graph
             .node_indices()
@@ -339,7 +360,9 @@ impl<T, G> PureCircuitGraph<T, G> {
                 ))
             })
             .collect_vec();
-        let max_ind = v.iter().map(|i| i.0.index()).max().unwrap();
+        let Some(max_ind) = v.iter().map(|i| i.0.index()).max() else {
+            return vec![];
+        };
         let mut ret = vec![None; max_ind + 1];
         for el in v {
             ret[el.0.index()] = Some(el);
@@ -352,11 +375,479 @@ impl BacktrackAlgorithm {
     /// Apply the backtracking algorithm
     /// # Return
     /// Ok(*): Vector of all assignments
-    /// Err(*): Errors on invalid indexes or transformations
+    /// Err(*): `SolverError` on invalid indexes or transformations
     pub fn calculate<T, G>(
         &self,
         pc_instance: &PureCircuitGraph<T, G>,
-    ) -> ARes<Vec<Vec<Option<Value>>>> {
+    ) -> Result<Vec<Vec<Option<Value>>>, SolverError> {
+        self.calculate_with_stats(pc_instance)
+            .map(|sol| sol.solutions)
+    }
+
+    /// Apply the backtracking algorithm, keeping the counters of the search
+    pub fn calculate_with_stats<T, G>(
+        &self,
+        pc_instance: &PureCircuitGraph<T, G>,
+    ) -> Result<BacktrackSolution, SolverError> {
+        let mut search = self.solutions(pc_instance)?;
+        let solutions = search.by_ref().collect::<Result<_, _>>()?;
+        Ok(BacktrackSolution {
+            solutions,
+            interrupted: false,
+            stats: search.stats(),
+        })
+    }
+
+    /// Lazily enumerate the assignments of the circuit.
+    /// Solutions are yielded in the same order as `calculate`. The search can be stopped at any
+    /// point (e.g. with `take(n)`) and resumed by pulling from the iterator again.
+    /// # Errors
+    /// * InvalidArity: Exists gate with invalid arity
+    /// * EmptyGraph: Circuit does not contain any value nodes
+    pub fn solutions<T, G>(
+        &self,
+        pc_instance: &PureCircuitGraph<T, G>,
+    ) -> Result<SolutionIter, SolverError> {
+        SolutionIter::new(pc_instance)
+    }
+
+    /// Lazily enumerate the assignments of the circuit, after reducing the domains to their
+    /// generalised arc consistent fixpoint (see `gac::propagate`)
+    /// # Errors
+    /// * InvalidArity: Exists gate with invalid arity
+    /// * EmptyGraph: Circuit does not contain any value nodes
+    pub fn consistent_solutions<T, G>(
+        &self,
+        pc_instance: &PureCircuitGraph<T, G>,
+    ) -> Result<SolutionIter, SolverError> {
+        SolutionIter::consistent(pc_instance)
+    }
+
+    /// Find a single assignment, stopping the search as soon as it is found
+    /// # Return
+    /// Ok(None): Circuit has no solution
+    pub fn first_solution<T, G>(
+        &self,
+        pc_instance: &PureCircuitGraph<T, G>,
+    ) -> Result<Option<Vec<Option<Value>>>, SolverError> {
+        self.solutions(pc_instance)?.next().transpose()
+    }
+}
+
+/// Parameter set of the backtracking algorithm
+#[derive(Debug, Clone)]
+pub struct BacktrackParamSet {
+    pub search: SolutionIter,
+    /// Stop after finding this many solutions
+    pub max_solutions: Option<usize>,
+    pub budget: Budget,
+    pub observer: Option<SharedObserver>,
+}
+
+impl BacktrackParamSet {
+    /// Search over all the solutions of the circuit, without limits
+    pub fn new<T, G>(pc_instance: &PureCircuitGraph<T, G>) -> Result<Self, SolverError> {
+        Ok(Self {
+            search: SolutionIter::new(pc_instance)?,
+            max_solutions: None,
+            budget: Budget::default(),
+            observer: None,
+        })
+    }
+}
+
+/// Solutions found by the backtracking algorithm
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BacktrackSolution {
+    pub solutions: Vec<Vec<Option<Value>>>,
+    /// The budget ran out before the search space was exhausted
+    pub interrupted: bool,
+    pub stats: SolveStats,
+}
+
+impl SolverTrait for BacktrackAlgorithm {
+    type ParamSet = BacktrackParamSet;
+
+    type Solution = BacktrackSolution;
+
+    fn find_solution(&self, param_set: Self::ParamSet) -> Result<Self::Solution, SolverError> {
+        let mut search = param_set.search.with_budget(&param_set.budget);
+        if let Some(observer) = param_set.observer {
+            search = search.with_observer(observer);
+        }
+        let solutions = search
+            .by_ref()
+            .take(param_set.max_solutions.unwrap_or(usize::MAX))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(BacktrackSolution {
+            solutions,
+            interrupted: search.is_interrupted(),
+            stats: search.stats(),
+        })
+    }
+}
+
+/// Gate with the solution indexes of its input and output ports
+type GatePorts = (
+    Gate,
+    [Option<usize>; MAX_DEGREE],
+    [Option<usize>; MAX_DEGREE],
+);
+
+/// Assignment, domains and branching order of a search branch
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SearchState {
+    pub(crate) sol_map: Vec<Option<Value>>,
+    pub(crate) value_map: Vec<Option<BitString>>,
+    pub(crate) queue: BacktrackPQ,
+    /// Number of decisions taken in the branch
+    pub(crate) level: usize,
+    /// Number of assigned value nodes
+    pub(crate) assigned: usize,
+    /// Why each value node holds its domain, used by conflict analysis
+    implications: Vec<Implication>,
+}
+
+/// Constraint that reduced the domain of a node, with the number of nodes assigned at that
+/// point. The nodes of the constraint assigned before then explain the reduction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Reason {
+    /// Position of the gate in `CircuitTable::gates`
+    Gate { pos: usize, assigned: usize },
+    /// Learned nogood
+    Nogood { id: usize, assigned: usize },
+}
+
+/// Origin of the domain of a value node
+#[derive(Debug, Clone, Copy, Default)]
+struct Implication {
+    /// Decision level the node was assigned at
+    level: usize,
+    /// Position of the node in the assignment order, None while unassigned
+    order: Option<usize>,
+    /// The node was assigned by branching rather than by propagation
+    decision: bool,
+    /// Reductions of the domain. Each one removes a value, hence there are at most three
+    reasons: [Option<Reason>; 3],
+}
+
+/// Outcome of unit propagation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Propagation {
+    Consistent,
+    /// Assigned nodes that together violate a gate or a learned nogood.
+    /// `gate` is the position of the violated gate, if a gate rather than a nogood failed
+    Conflict {
+        nodes: Vec<usize>,
+        gate: Option<usize>,
+    },
+}
+
+/// Branching point of the search.
+/// Holds the state before `node` was assigned and the values of `node` left to explore
+#[derive(Debug, Clone)]
+struct SearchFrame {
+    node: NodeIndex,
+    remaining: BitString,
+    /// Order the values of `node` are tried in
+    order: [Value; 3],
+    state: SearchState,
+    /// Assigned nodes explaining why the values of `node` tried so far failed
+    conflict: Vec<usize>,
+    /// Every value tried so far failed with a conflict. Once all values fail, the search can
+    /// jump back to the latest node of `conflict` instead of the previous branching point
+    explained: bool,
+}
+
+impl SearchFrame {
+    fn new(
+        node: NodeIndex,
+        order: [Value; 3],
+        state: SearchState,
+        table: &CircuitTable,
+        nogoods: &NogoodStore,
+    ) -> Result<Self, SolverError> {
+        let remaining = state.value_map[node.index()].ok_or(GraphError::NotExistentNode)?;
+        // Values missing from the domain were removed because of these nodes
+        let mut conflict = vec![];
+        state.push_reasons(node.index(), table, nogoods, &mut conflict);
+        conflict.sort_unstable();
+        conflict.dedup();
+        Ok(Self {
+            node,
+            remaining,
+            order,
+            conflict,
+            state,
+            explained: true,
+        })
+    }
+}
+
+/// Iterator over the assignments of a circuit, created by `BacktrackAlgorithm::solutions`.
+///
+/// The search is driven by an explicit stack of branching points, so memory scales with the
+/// depth of the search rather than the number of solutions. The iterator owns a compact copy
+/// of the circuit, hence it can outlive the graph it was created from.
+#[derive(Debug, Clone)]
+pub struct SolutionIter {
+    table: CircuitTable,
+    /// State before the first decision, every restart starts over from it
+    root: SearchState,
+    /// The root branching point was pushed on the stack
+    started: bool,
+    stack: Vec<SearchFrame>,
+    nogoods: NogoodStore,
+    heuristic: Box<dyn BranchingHeuristic>,
+    restarts: Option<Restarts>,
+    bot_bound: Option<BotBound>,
+    stats: SolveStats,
+    found: usize,
+    monitor: Option<BudgetMonitor>,
+    observer: Option<SharedObserver>,
+    /// `Finished` was already reported to the observer
+    finish_reported: bool,
+}
+
+impl SolutionIter {
+    fn new<T, G>(pc_instance: &PureCircuitGraph<T, G>) -> Result<Self, SolverError> {
+        let (table, state) = CircuitTable::new(pc_instance)?;
+        Self::from_root(table, state)
+    }
+
+    fn consistent<T, G>(pc_instance: &PureCircuitGraph<T, G>) -> Result<Self, SolverError> {
+        let (table, mut state) = CircuitTable::new(pc_instance)?;
+        let consistent = state.arc_consistency(&table)?;
+        let mut search = Self::from_root(table, state)?;
+        // No solution, the search finishes without branching
+        search.started = !consistent;
+        Ok(search)
+    }
+
+    fn from_root(table: CircuitTable, root: SearchState) -> Result<Self, SolverError> {
+        if root.queue.is_empty() {
+            return Err(SolverError::EmptyGraph);
+        }
+        Ok(Self {
+            table,
+            root,
+            started: false,
+            stack: vec![],
+            nogoods: NogoodStore::default(),
+            heuristic: Box::new(DomainDegree),
+            restarts: None,
+            bot_bound: None,
+            stats: SolveStats {
+                max_depth: 1,
+                ..Default::default()
+            },
+            found: 0,
+            monitor: None,
+            observer: None,
+            finish_reported: false,
+        })
+    }
+
+    /// Number of branches explored so far
+    pub fn op_count(&self) -> usize {
+        self.stats.decisions
+    }
+
+    /// Counters of the search so far. The wall time only covers the time spent in `next`
+    pub fn stats(&self) -> SolveStats {
+        self.stats
+    }
+
+    /// Number of nogoods learned from the conflicts so far
+    pub fn learned_nogoods(&self) -> usize {
+        self.nogoods.len()
+    }
+
+    /// Stop the search once the budget runs out. Every branch counts as a node
+    pub fn with_budget(self, budget: &Budget) -> Self {
+        Self {
+            monitor: Some(budget.start()),
+            ..self
+        }
+    }
+
+    /// Whether the search was stopped by its budget
+    pub fn is_interrupted(&self) -> bool {
+        self.monitor
+            .as_ref()
+            .is_some_and(BudgetMonitor::was_interrupted)
+    }
+
+    /// Branch according to `heuristic` from now on. The heuristic can be swapped between
+    /// solutions, e.g. to compare heuristics on the same search
+    pub fn with_heuristic(self, heuristic: impl BranchingHeuristic + 'static) -> Self {
+        Self {
+            heuristic: Box::new(heuristic),
+            ..self
+        }
+    }
+
+    /// Restart the search from the root after `unit * luby(i)` conflicts for the `i`-th restart.
+    ///
+    /// Learned nogoods are kept across restarts and the heuristic is notified, hence a
+    /// `Portfolio` switches heuristic at every restart. The search stays complete, since the
+    /// Luby sequence is unbounded, and the solutions found before a restart are blocked by
+    /// nogoods so that they are not yielded again. Enable restarts before pulling the first
+    /// solution, the ones yielded earlier are not blocked.
+    pub fn with_restarts(self, unit: usize) -> Self {
+        Self {
+            restarts: Some(Restarts {
+                unit: unit.max(1),
+                ..Default::default()
+            }),
+            ..self
+        }
+    }
+
+    /// Only yield solutions whose weighted number of ⊥ values is below `limit`.
+    ///
+    /// `weights` is the cost of ⊥ on each value node, indexed like the solutions, i.e. by node
+    /// index with the gates in between. Nodes past its end cost nothing. A branch is pruned as
+    /// soon as the nodes fixed to ⊥ reach the limit. Pruned branches do not count as conflicts
+    /// and teach no nogoods.
+    pub fn with_bot_bound(self, weights: &[usize], limit: usize) -> Self {
+        Self {
+            bot_bound: Some(BotBound {
+                weights: weights.into(),
+                limit,
+            }),
+            ..self
+        }
+    }
+
+    /// Lower the limit of `with_bot_bound`, e.g. to the cost of the latest solution
+    /// (branch-and-bound). Does nothing without a bound
+    pub fn tighten_bot_bound(&mut self, limit: usize) {
+        if let Some(bound) = &mut self.bot_bound {
+            bound.limit = bound.limit.min(limit);
+        }
+    }
+
+    /// Only search the assignments where every node of `assumptions` holds its value.
+    /// The assumptions are propagated into the root, call before pulling the first solution
+    /// # Errors
+    /// * Graph(NotExistentNode): Exists assumption on a node that is not a value node
+    pub fn with_assumptions(self, assumptions: &[(NodeIndex, Value)]) -> Result<Self, SolverError> {
+        let mut search = self;
+        let mut root = std::mem::take(&mut search.root);
+        for (node, value) in assumptions {
+            let domain = root
+                .value_map
+                .get(node.index())
+                .copied()
+                .flatten()
+                .ok_or(GraphError::NotExistentNode)?;
+            let consistent = match root.sol_map[node.index()] {
+                Some(assigned) => assigned == *value,
+                None if !domain.contains(*value) => false,
+                None => {
+                    search.table.unit_propagate(
+                        *node,
+                        *value,
+                        &mut root,
+                        &search.nogoods,
+                        &mut search.stats,
+                    )? == Propagation::Consistent
+                }
+            };
+            if !consistent {
+                // No solution, the search finishes without branching
+                search.started = true;
+                break;
+            }
+        }
+        root.make_root();
+        search.root = root;
+        Ok(search)
+    }
+
+    /// Report every branch, solution and the end of the search to `observer`
+    pub fn with_observer(self, observer: SharedObserver) -> Self {
+        Self {
+            observer: Some(observer),
+            ..self
+        }
+    }
+
+    fn notify(&self, event: SolverEvent) {
+        if let Some(observer) = &self.observer {
+            observer.on_event(event);
+        }
+    }
+
+    fn finish(&mut self) {
+        if !self.finish_reported {
+            self.finish_reported = true;
+            self.notify(SolverEvent::Finished {
+                interrupted: self.is_interrupted(),
+            });
+        }
+    }
+}
+
+/// Subtree of the search, see `SolutionIter::cubes`
+#[derive(Debug, Clone)]
+pub(crate) enum Cube {
+    /// The decisions leading to the cube already complete an assignment
+    Solved(Vec<Option<Value>>),
+    /// Search over the nodes left unassigned by the decisions of the cube
+    Open(Box<SolutionIter>),
+}
+
+/// Limit on the ⊥ values of the solutions, see `SolutionIter::with_bot_bound`
+#[derive(Debug, Clone)]
+struct BotBound {
+    /// Cost of ⊥ on each value node, indexed like the solutions
+    weights: Box<[usize]>,
+    /// Solutions cost less than this
+    limit: usize,
+}
+
+/// Progress of the restart policy
+#[derive(Debug, Clone, Copy, Default)]
+struct Restarts {
+    /// Conflicts allowed before the first restart
+    unit: usize,
+    /// Restarts so far
+    count: usize,
+    /// Conflicts since the last restart
+    conflicts: usize,
+}
+
+/// `i`-th element of the Luby sequence: 1, 1, 2, 1, 1, 2, 4, 1, 1, 2, ...
+fn luby(mut i: usize) -> usize {
+    let (mut size, mut seq) = (1usize, 0u32);
+    while size < i + 1 {
+        seq += 1;
+        size = 2 * size + 1;
+    }
+    while size - 1 != i {
+        size = (size - 1) >> 1;
+        seq -= 1;
+        i %= size;
+    }
+    1 << seq
+}
+
+/// Compact copy of the circuit used by the search
+#[derive(Debug, Clone)]
+pub(crate) struct CircuitTable {
+    pub(crate) gates: Box<[GatePorts]>,
+    /// Gates adjacent to each value node, indexed like the solutions
+    pub(crate) adjacency: Box<[Box<[usize]>]>,
+}
+
+impl CircuitTable {
+    /// Build the table together with the root state of the search
+    pub(crate) fn new<T, G>(
+        pc_instance: &PureCircuitGraph<T, G>,
+    ) -> Result<(Self, SearchState), SolverError> {
+        pc_instance.validate_arity()?;
         let node_array = pc_instance.extract_node_graph(); // We want to get value nodes
         let mut value_map = vec![None; node_array.len()];
         for (el_node, el_val) in node_array.iter().zip(value_map.iter_mut()) {
@@ -375,198 +866,641 @@ impl BacktrackAlgorithm {
                 },
             );
         }
-        self.backtrack_root(pc_instance, value_map, queue)
-    }
 
-    fn backtrack_root<T, G>(
-        &self,
-        pc_instance: &PureCircuitGraph<T, G>,
-        value_map: Vec<Option<BitString>>,
-        mut queue: BacktrackPQ,
-    ) -> ARes<Vec<Vec<Option<Value>>>> {
-        let Some((val_ind, _)) = queue.pop() else {
-            return Err(anyhow!("Empty graph"));
-        };
-        let mut op_count = 1;
-        let mut count = vec![];
-        for v in value_map[val_ind.index()]
-            .ok_or(anyhow!("Node mapping incorrect"))?
-            .to_value_iter()
-        {
-            let mut sol_arr = vec![None; value_map.len()];
-            let mut val_map = value_map.clone();
-            let mut q = queue.clone();
-            let res =
-                self.unit_propagate(val_ind, v, &mut sol_arr, &mut val_map, &mut q, pc_instance)?;
-            if !res {
-                continue;
+        let gates = pc_instance
+            .graph
+            .node_indices()
+            .filter_map(|n| match pc_instance.graph[n].into_node() {
+                GraphNode::GateNode { gate, .. } => Some((n, gate)),
+                GraphNode::ValueNode(_) => None,
+            })
+            .map(|(gate_index, gate)| {
+                let ins = pc_instance
+                    .gate_ports(gate_index, Direction::Incoming)?
+                    .map(|n| n.map(NodeIndex::index));
+                let outs = pc_instance
+                    .gate_ports(gate_index, Direction::Outgoing)?
+                    .map(|n| n.map(NodeIndex::index));
+                Ok((gate, ins, outs))
+            })
+            .collect::<Result<Box<[GatePorts]>, SolverError>>()?;
+        let mut adjacency = vec![vec![]; node_array.len()];
+        for (pos, (_, ins, outs)) in gates.iter().enumerate() {
+            for n in ins.iter().chain(outs.iter()).flatten() {
+                if adjacency[*n].last() != Some(&pos) {
+                    adjacency[*n].push(pos);
+                }
             }
-
-            let mut res = self.backtrack_inner(pc_instance, val_map, q, sol_arr, &mut op_count)?;
-            count.append(&mut res);
         }
 
-        dbg!(op_count);
-        Ok(count)
+        Ok((
+            Self {
+                gates,
+                adjacency: adjacency.into_iter().map(Vec::into_boxed_slice).collect(),
+            },
+            SearchState {
+                sol_map: vec![None; value_map.len()],
+                implications: vec![Implication::default(); value_map.len()],
+                value_map,
+                queue,
+                level: 0,
+                assigned: 0,
+            },
+        ))
     }
 
-    fn unit_propagate<T, G>(
+    /// Assign `new_value` to `index_pc` and propagate until no node has a single value left.
+    /// The learned nogoods are propagated together with the gates
+    pub(crate) fn unit_propagate(
         &self,
         index_pc: NodeIndex,
         new_value: Value,
-        sol_map: &mut [Option<Value>],
-        value_map: &mut [Option<BitString>],
-        queue: &mut BacktrackPQ,
-        pc_instance: &PureCircuitGraph<T, G>,
-    ) -> ARes<bool> {
-        self.assign_node(index_pc, new_value, sol_map, value_map, queue)?;
-        self.propagate_value_node(index_pc, sol_map, value_map, queue, pc_instance)?;
-        while queue.peek().filter(|(_, p)| p.value_len == 1).is_some() {
-            let (nod_ind, _k) = queue.pop().unwrap();
-            let b = value_map[nod_ind.index()].ok_or(anyhow!("Not exist"))?;
-            if b.len() != 1 {
-                let el = b.len();
-                let el2 = _k.value_len;
-
-                return Err(anyhow!(
-                    "Incorrect measurement: Len {el}, Key {el2}, Map {value_map:?}, Ind {nod_ind:?}"
-                ));
-            }
-            let new_val = b.to_value_iter().next().unwrap();
-            self.assign_node(nod_ind, new_val, sol_map, value_map, queue)?;
-            self.propagate_value_node(nod_ind, sol_map, value_map, queue, pc_instance)?;
-        }
-
-        if queue.is_empty() {
-            return Ok(true);
+        state: &mut SearchState,
+        nogoods: &NogoodStore,
+        stats: &mut SolveStats,
+    ) -> Result<Propagation, SolverError> {
+        let ret = self.propagate_assignment(index_pc, new_value, state, nogoods, stats)?;
+        if ret != Propagation::Consistent {
+            stats.conflicts += 1;
         }
-        if queue.peek().filter(|(_, p)| p.value_len == 0).is_some() {
-            return Ok(false);
-        }
-
-        Ok(true)
+        Ok(ret)
     }
 
-    fn propagate_value_node<T, G>(
+    fn propagate_assignment(
         &self,
         index_pc: NodeIndex,
-        sol_map: &mut [Option<Value>],
-        value_map: &mut [Option<BitString>],
-        queue: &mut BacktrackPQ,
-        pc_instance: &PureCircuitGraph<T, G>,
-    ) -> ARes<()> {
-        for gate_indx in pc_instance.get_all_neigh(index_pc) {
-            let mut ins_vals = [None; MAX_DEGREE];
-            let mut ins_idx = [None; MAX_DEGREE];
-            let mut outs_vals = [None; MAX_DEGREE];
-            let mut outs_idx = [None; MAX_DEGREE];
-
-            for outer_e in pc_instance
-                .graph
-                .edges_directed(gate_indx, Direction::Outgoing)
-            {
-                let ind = (outer_e.weight().0 as usize)
-                    .checked_sub(1)
-                    .ok_or(anyhow!("Index issue"))?;
-                let val = outer_e.target();
-                outs_vals[ind] = sol_map[val.index()];
-                outs_idx[ind] = Some(val.index());
+        new_value: Value,
+        state: &mut SearchState,
+        nogoods: &NogoodStore,
+        stats: &mut SolveStats,
+    ) -> Result<Propagation, SolverError> {
+        state.assign_node(index_pc, new_value, true)?;
+        let mut node = index_pc;
+        loop {
+            let conflict = self.propagate_value_node(node, state)?;
+            if conflict != Propagation::Consistent {
+                return Ok(conflict);
             }
-            for outer_e in pc_instance
-                .graph
-                .edges_directed(gate_indx, Direction::Incoming)
-            {
-                let ind = (outer_e.weight().0 as usize)
-                    .checked_sub(1)
-                    .ok_or(anyhow!("Index issue"))?;
-                let val = outer_e.source();
-                ins_vals[ind] = sol_map[val.index()];
-                ins_idx[ind] = Some(val.index());
+            let conflict = nogoods.propagate(node, state)?;
+            if conflict != Propagation::Consistent {
+                return Ok(conflict);
+            }
+            let Some((nod_ind, k)) = state.queue.peek() else {
+                return Ok(Propagation::Consistent);
+            };
+            let nod_ind = *nod_ind;
+            match k.value_len {
+                0 => {
+                    // The reductions of the domain explain why it is empty
+                    let mut reasons = vec![];
+                    state.push_reasons(nod_ind.index(), self, nogoods, &mut reasons);
+                    // The latest gate that reduced the domain emptied it
+                    let gate = state.implications[nod_ind.index()]
+                        .reasons
+                        .iter()
+                        .flatten()
+                        .rev()
+                        .find_map(|r| match r {
+                            Reason::Gate { pos, .. } => Some(*pos),
+                            Reason::Nogood { .. } => None,
+                        });
+                    return Ok(Propagation::Conflict {
+                        nodes: reasons,
+                        gate,
+                    });
+                }
+                1 => (),
+                _ => return Ok(Propagation::Consistent),
             }
-            let GraphNode::GateNode { gate, .. } = pc_instance.graph[gate_indx].into_node() else {
-                panic!("error mappings");
+            let b = state.value_map[nod_ind.index()].ok_or(GraphError::NotExistentNode)?;
+            let (1, Some(new_val)) = (b.len(), b.to_value_iter().next()) else {
+                log::error!(
+                    "Incorrect measurement: Len {}, Key {}, Ind {nod_ind:?}",
+                    b.len(),
+                    k.value_len
+                );
+                return Err(SolverError::MissingAssignment {
+                    node_index: nod_ind,
+                });
             };
-            let (checked_ins, checked_outs) = gate.set_value(ins_vals, outs_vals)?;
-            for f in checked_ins
+            state.assign_node(nod_ind, new_val, false)?;
+            stats.propagations += 1;
+            node = nod_ind;
+        }
+    }
+
+    /// Restrict the domains of the nodes sharing a gate with `index_pc`
+    /// # Returns
+    /// The assigned ports of the first gate the assigned values violate
+    fn propagate_value_node(
+        &self,
+        index_pc: NodeIndex,
+        state: &mut SearchState,
+    ) -> Result<Propagation, SolverError> {
+        let gates = self
+            .adjacency
+            .get(index_pc.index())
+            .ok_or(GraphError::NotExistentNode)?;
+        for (pos, (gate, ins_idx, outs_idx)) in gates.iter().map(|pos| (pos, self.gates[*pos])) {
+            let port_value =
+                |n: Option<usize>| n.and_then(|n| state.sol_map.get(n).copied().flatten());
+            let ins_vals = ins_idx.map(port_value);
+            let outs_vals = outs_idx.map(port_value);
+            let (checked_ins, checked_outs) = match gate.set_value(ins_vals, outs_vals) {
+                Ok(sets) => sets,
+                Err(SolverError::InconsistentAssignment { .. }) => {
+                    let assigned = ins_idx
+                        .iter()
+                        .chain(outs_idx.iter())
+                        .flatten()
+                        .filter(|n| state.sol_map[**n].is_some())
+                        .copied()
+                        .collect();
+                    return Ok(Propagation::Conflict {
+                        nodes: assigned,
+                        gate: Some(*pos),
+                    });
+                }
+                Err(e) => return Err(e),
+            };
+            let reason = Reason::Gate {
+                pos: *pos,
+                assigned: state.assigned,
+            };
+            for (val, indx) in checked_ins
                 .into_iter()
-                .zip(ins_idx.into_iter())
-                .chain(checked_outs.into_iter().zip(outs_idx.into_iter()))
+                .zip(ins_idx)
+                .chain(checked_outs.into_iter().zip(outs_idx))
+                .filter_map(|(val, indx)| val.zip(indx))
             {
-                if let (Some(val), Some(indx)) = f {
-                    if sol_map[indx].is_none() {
-                        self.prop_node(indx, val, queue, value_map)?;
-                    }
+                if state.sol_map[indx].is_none() {
+                    state.prop_node(indx, val, reason)?;
                 }
             }
         }
-        Ok(())
+        Ok(Propagation::Consistent)
     }
+}
 
-    fn assign_node(
+impl CircuitTable {
+    /// Pick the next branching node of `state` with `heuristic`, together with its value order.
+    /// Falls back to the default order if the heuristic picks an unavailable node, and completes
+    /// a value order that misses values
+    fn select(
         &self,
+        heuristic: &mut dyn BranchingHeuristic,
+        state: &mut SearchState,
+    ) -> Option<(NodeIndex, [Value; 3])> {
+        let choice = heuristic.select_node(&BranchView::new(self, state));
+        let (node, _) = choice
+            .and_then(|n| state.queue.remove(&n))
+            .or_else(|| state.queue.pop())?;
+        let order = heuristic.value_order(node, &BranchView::new(self, state));
+        Some((node, complete_order(order)))
+    }
+}
+
+impl SearchState {
+    /// Make the assignment of the state a fixed starting point: its nodes no longer count as
+    /// decisions and conflicts depending only on them end the search
+    fn make_root(&mut self) {
+        self.level = 0;
+        for implication in self.implications.iter_mut() {
+            implication.level = 0;
+            implication.decision = false;
+        }
+    }
+
+    fn assign_node(
+        &mut self,
         node_indx: NodeIndex,
         new_value: Value,
-        sol_map: &mut [Option<Value>],
-        value_map: &mut [Option<BitString>],
-        queue: &mut BacktrackPQ,
-    ) -> ARes<()> {
-        if value_map[node_indx.index()].is_some() {
-            sol_map[node_indx.index()] = Some(new_value);
-            value_map[node_indx.index()] = Some(new_value.into());
-            queue.remove(&node_indx);
+        decision: bool,
+    ) -> Result<(), SolverError> {
+        if self.value_map[node_indx.index()].is_some() {
+            self.sol_map[node_indx.index()] = Some(new_value);
+            self.value_map[node_indx.index()] = Some(new_value.into());
+            self.queue.remove(&node_indx);
+            let implication = &mut self.implications[node_indx.index()];
+            implication.level = self.level;
+            implication.order = Some(self.assigned);
+            implication.decision = decision;
+            self.assigned += 1;
             Ok(())
         } else {
-            Err(anyhow!("Incorrect Assingment"))
+            Err(GraphError::NotExistentNode.into())
         }
     }
 
-    fn prop_node(
-        &self,
+    /// Restrict the domain of `indx` to `new_set` because of `reason`
+    pub(crate) fn prop_node(
+        &mut self,
         indx: usize,
         new_set: BitString,
-        queue: &mut BacktrackPQ,
-        value_map: &mut [Option<BitString>],
-    ) -> ARes<()> {
-        if value_map[indx].is_none() {
-            return Err(anyhow!("Value mapping"));
-        }
-        let new_set = value_map[indx].unwrap().op_inter(new_set);
-        value_map[indx] = Some(new_set);
-        queue.change_priority_by(&NodeIndex::from(indx as u32), |f| {
+        reason: Reason,
+    ) -> Result<(), SolverError> {
+        let Some(old_set) = self.value_map[indx] else {
+            return Err(GraphError::NotExistentNode.into());
+        };
+        let new_set = old_set.op_inter(new_set);
+        if new_set == old_set {
+            return Ok(());
+        }
+        self.value_map[indx] = Some(new_set);
+        self.queue.change_priority_by(&NodeIndex::new(indx), |f| {
             f.value_len = new_set.len();
         });
+        if let Some(slot) = self.implications[indx]
+            .reasons
+            .iter_mut()
+            .find(|r| r.is_none())
+        {
+            *slot = Some(reason);
+        }
         Ok(())
     }
 
-    fn backtrack_inner<T, G>(
+    /// Copy of the state that only branches on the nodes accepted by `keep`
+    pub(crate) fn restricted(&self, keep: impl Fn(NodeIndex) -> bool) -> Self {
+        Self {
+            sol_map: self.sol_map.clone(),
+            value_map: self.value_map.clone(),
+            queue: self
+                .queue
+                .iter()
+                .filter(|(n, _)| keep(**n))
+                .map(|(n, p)| (*n, *p))
+                .collect(),
+            level: self.level,
+            assigned: self.assigned,
+            implications: self.implications.clone(),
+        }
+    }
+
+    /// Whether `node` was assigned before the first `assigned` assignments were made
+    fn assigned_before(&self, node: usize, assigned: usize) -> bool {
+        self.implications[node].order.is_some_and(|o| o < assigned)
+    }
+
+    /// Push the assigned nodes that explain the reductions of the domain of `node`
+    fn push_reasons(
         &self,
-        pc_instance: &PureCircuitGraph<T, G>,
-        value_map: Vec<Option<BitString>>,
-        mut queue: BacktrackPQ,
-        sol_map: Vec<Option<Value>>,
-        op_count: &mut usize,
-    ) -> ARes<Vec<Vec<Option<Value>>>> {
-        let Some((val_ind, _)) = queue.pop() else {
-            return Ok(vec![sol_map]);
+        node: usize,
+        table: &CircuitTable,
+        nogoods: &NogoodStore,
+        out: &mut Vec<usize>,
+    ) {
+        for reason in self.implications[node].reasons.iter().flatten() {
+            match *reason {
+                Reason::Gate { pos, assigned } => {
+                    let (_, ins, outs) = &table.gates[pos];
+                    out.extend(
+                        ins.iter()
+                            .chain(outs.iter())
+                            .flatten()
+                            .filter(|n| self.assigned_before(**n, assigned)),
+                    );
+                }
+                Reason::Nogood { id, assigned } => out.extend(
+                    nogoods
+                        .nodes(id)
+                        .filter(|n| self.assigned_before(*n, assigned)),
+                ),
+            }
+        }
+    }
+
+    /// Resolve the nodes assigned at `level` in `conflict` through their reasons, until only
+    /// the decision of `level` is left
+    /// # Returns
+    /// The nodes of earlier levels the conflict depends on
+    fn explain(
+        &self,
+        conflict: &[usize],
+        level: usize,
+        table: &CircuitTable,
+        nogoods: &NogoodStore,
+    ) -> Vec<usize> {
+        let mut visited = vec![false; self.implications.len()];
+        let mut stack = conflict.to_vec();
+        let mut ret = vec![];
+        while let Some(n) = stack.pop() {
+            if std::mem::replace(&mut visited[n], true) {
+                continue;
+            }
+            let implication = &self.implications[n];
+            if implication.level < level {
+                ret.push(n);
+            } else if !implication.decision {
+                self.push_reasons(n, table, nogoods, &mut stack);
+            }
+        }
+        ret
+    }
+
+    /// Weighted number of value nodes fixed to ⊥, either assigned or left with no other value
+    pub(crate) fn bot_cost(&self, weights: &[usize]) -> usize {
+        let bot = Some(BitString::from(Value::Bot));
+        weights
+            .iter()
+            .zip(self.sol_map.iter().zip(&self.value_map))
+            .filter(|(_, (value, domain))| match value {
+                Some(value) => *value == Value::Bot,
+                None => **domain == bot,
+            })
+            .map(|(weight, _)| *weight)
+            .sum()
+    }
+
+    /// Nogood forbidding the current values of `nodes`
+    fn nogood(&self, nodes: impl IntoIterator<Item = usize>) -> Nogood {
+        nodes
+            .into_iter()
+            .filter_map(|n| Some((n, self.sol_map[n]?.into())))
+            .collect()
+    }
+}
+
+impl Iterator for SolutionIter {
+    type Item = Result<Vec<Option<Value>>, SolverError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let started = Instant::now();
+        let ret = self.search();
+        self.stats.wall_time += started.elapsed();
+        ret
+    }
+}
+
+impl FusedIterator for SolutionIter {}
+
+impl SolutionIter {
+    /// Run the search until the next solution
+    fn search(&mut self) -> Option<Result<Vec<Option<Value>>, SolverError>> {
+        let started = match std::mem::replace(&mut self.started, true) {
+            true => Ok(None),
+            false => self.push_root(),
         };
-        *op_count += 1;
-        let mut count = vec![];
-        for v in value_map[val_ind.index()]
-            .ok_or(anyhow!("Node mapping incorrect"))?
-            .to_value_iter()
-        {
-            let mut sol_arr = sol_map.clone();
-            let mut val_map = value_map.clone();
-            let mut q = queue.clone();
-            let res =
-                self.unit_propagate(val_ind, v, &mut sol_arr, &mut val_map, &mut q, pc_instance)?;
-            if !res {
+        match started {
+            Ok(None) => (),
+            Ok(Some(solution)) => {
+                self.found += 1;
+                self.notify(SolverEvent::Solution {
+                    op_count: self.stats.decisions,
+                    found: self.found,
+                });
+                return Some(Ok(solution));
+            }
+            Err(e) => return Some(Err(e)),
+        }
+        loop {
+            if self.stack.is_empty() || self.monitor.as_ref().is_some_and(BudgetMonitor::tick) {
+                self.finish();
+                return None;
+            }
+            let frame = self.stack.last_mut()?;
+            let remaining = frame.remaining;
+            let Some(value) = frame.order.into_iter().find(|v| remaining.contains(*v)) else {
+                // Branching points where every value failed with a conflict are left by `fail`
+                self.stack.pop();
+                if let Some(parent) = self.stack.last_mut() {
+                    parent.explained = false;
+                }
+                continue;
+            };
+            let node = frame.node;
+            frame.remaining = frame.remaining.remove(value);
+            // The last value of a branching point takes over its state instead of cloning it
+            let mut state = if frame.remaining.is_empty() {
+                std::mem::take(&mut frame.state)
+            } else {
+                frame.state.clone()
+            };
+            state.level = self.stack.len();
+            self.stats.decisions += 1;
+            self.notify(SolverEvent::Branch {
+                op_count: self.stats.decisions,
+                depth: self.stack.len(),
+            });
+
+            match self
+                .table
+                .unit_propagate(node, value, &mut state, &self.nogoods, &mut self.stats)
+            {
+                Ok(Propagation::Consistent) => (),
+                Ok(Propagation::Conflict { nodes, gate }) => {
+                    self.heuristic
+                        .on_conflict(&nodes, gate, &BranchView::new(&self.table, &state));
+                    self.fail(nodes, &state);
+                    let restarted = match self.restart_due() {
+                        true => self.restart(),
+                        false => Ok(()),
+                    };
+                    if let Err(e) = restarted {
+                        return Some(Err(e));
+                    }
+                    continue;
+                }
+                Err(e) => {
+                    self.stack.clear();
+                    return Some(Err(e));
+                }
+            }
+            if self
+                .bot_bound
+                .as_ref()
+                .is_some_and(|b| state.bot_cost(&b.weights) >= b.limit)
+            {
+                if let Some(frame) = self.stack.last_mut() {
+                    frame.explained = false;
+                }
                 continue;
             }
-            let mut res = self.backtrack_inner(pc_instance, val_map, q, sol_arr, op_count)?;
-            count.append(&mut res);
+            let Some((next_node, order)) = self.table.select(self.heuristic.as_mut(), &mut state)
+            else {
+                if let Some(frame) = self.stack.last_mut() {
+                    frame.explained = false;
+                }
+                if self.restarts.is_some() {
+                    // Later runs must not find the solution again. The decisions determine the
+                    // rest of the assignment, hence forbidding them blocks exactly this solution
+                    let decisions = (0..state.sol_map.len())
+                        .filter(|n| state.implications[*n].decision)
+                        .collect_vec();
+                    self.nogoods.block(state.nogood(decisions));
+                }
+                self.found += 1;
+                self.notify(SolverEvent::Solution {
+                    op_count: self.stats.decisions,
+                    found: self.found,
+                });
+                return Some(Ok(state.sol_map));
+            };
+            match SearchFrame::new(next_node, order, state, &self.table, &self.nogoods) {
+                Ok(frame) => self.stack.push(frame),
+                Err(e) => {
+                    self.stack.clear();
+                    return Some(Err(e));
+                }
+            }
+            self.stats.max_depth = self.stats.max_depth.max(self.stack.len());
+        }
+    }
+
+    /// Push the first branching point of the search
+    /// # Returns
+    /// The assignment of the root, if the assumptions already assign every node
+    fn push_root(&mut self) -> Result<Option<Vec<Option<Value>>>, SolverError> {
+        let mut state = self.root.clone();
+        let Some((node, order)) = self.table.select(self.heuristic.as_mut(), &mut state) else {
+            return Ok(Some(state.sol_map));
+        };
+        let frame = SearchFrame::new(node, order, state, &self.table, &self.nogoods)?;
+        self.stack.push(frame);
+        Ok(None)
+    }
+
+    /// Count the conflict towards the restart policy
+    fn restart_due(&mut self) -> bool {
+        let Some(restarts) = &mut self.restarts else {
+            return false;
+        };
+        restarts.conflicts += 1;
+        if restarts.conflicts < restarts.unit.saturating_mul(luby(restarts.count)) {
+            return false;
+        }
+        restarts.conflicts = 0;
+        restarts.count += 1;
+        true
+    }
+
+    /// Drop every branching point and start over from the root, keeping the learned nogoods
+    fn restart(&mut self) -> Result<(), SolverError> {
+        if self.stack.is_empty() {
+            // The conflict finished the search
+            return Ok(());
+        }
+        self.stack.clear();
+        self.stats.restarts += 1;
+        self.heuristic.on_restart();
+        // The root is not complete, otherwise the search would not have branched
+        self.push_root().map(|_| ())
+    }
+
+    /// Split the search tree into at least `count` subtrees by branching breadth-first, unless
+    /// the tree is smaller (cube-and-conquer). The cubes are listed in the order the search
+    /// visits them, and each one inherits the heuristic, restarts and nogoods of the search.
+    /// A search that already started is returned whole as a single cube.
+    /// # Returns
+    /// The cubes together with the counters of the split
+    pub(crate) fn cubes(&self, count: usize) -> Result<(Vec<Cube>, SolveStats), SolverError> {
+        let mut stats = SolveStats::default();
+        if self.started {
+            return Ok((vec![Cube::Open(Box::new(self.clone()))], stats));
+        }
+        let mut heuristic = self.heuristic.clone();
+        let mut frontier = vec![self.root.clone()];
+        while frontier.len() < count && frontier.iter().any(|s| !s.queue.is_empty()) {
+            stats.max_depth += 1;
+            let mut next = Vec::with_capacity(frontier.len() * 3);
+            for mut state in frontier {
+                let Some((node, order)) = self.table.select(heuristic.as_mut(), &mut state) else {
+                    next.push(state);
+                    continue;
+                };
+                let domain = state.value_map[node.index()].ok_or(GraphError::NotExistentNode)?;
+                for value in order.into_iter().filter(|v| domain.contains(*v)) {
+                    let mut child = state.clone();
+                    child.level = stats.max_depth;
+                    stats.decisions += 1;
+                    let propagation = self.table.unit_propagate(
+                        node,
+                        value,
+                        &mut child,
+                        &self.nogoods,
+                        &mut stats,
+                    )?;
+                    if propagation == Propagation::Consistent {
+                        next.push(child);
+                    }
+                }
+            }
+            frontier = next;
+        }
+        let cubes = frontier
+            .into_iter()
+            // The split does not prune on the ⊥ bound, its leaves may already exceed it
+            .filter(|state| {
+                self.bot_bound
+                    .as_ref()
+                    .is_none_or(|b| state.bot_cost(&b.weights) < b.limit)
+            })
+            .map(|mut state| {
+                if state.queue.is_empty() {
+                    return Cube::Solved(state.sol_map);
+                }
+                state.make_root();
+                Cube::Open(Box::new(Self {
+                    root: state,
+                    stats: SolveStats {
+                        max_depth: 1,
+                        ..Default::default()
+                    },
+                    ..self.clone()
+                }))
+            })
+            .collect();
+        Ok((cubes, stats))
+    }
+
+    /// Share `monitor` with other searches, e.g. the cubes of a parallel search
+    pub(crate) fn with_monitor(self, monitor: BudgetMonitor) -> Self {
+        Self {
+            monitor: Some(monitor),
+            ..self
+        }
+    }
+
+    /// Record why the current value of the top branching point failed and learn the nogood.
+    ///
+    /// Once every value of the branching point failed, jump back to the latest node the
+    /// failures depend on: none of the branching points in between can lead to a solution.
+    /// The failures then become the conflict of the current value of that node.
+    /// `state` holds the assignment the conflict was found in
+    fn fail(&mut self, mut conflict: Vec<usize>, state: &SearchState) {
+        loop {
+            let level = self.stack.len();
+            let Some(frame) = self.stack.last_mut() else {
+                return;
+            };
+            let earlier = state.explain(&conflict, level, &self.table, &self.nogoods);
+            self.nogoods
+                .learn(state.nogood(earlier.iter().copied().chain([frame.node.index()])));
+            for n in earlier {
+                if !frame.conflict.contains(&n) {
+                    frame.conflict.push(n);
+                }
+            }
+            if !frame.remaining.is_empty() || !frame.explained {
+                return;
+            }
+
+            let Some(frame) = self.stack.pop() else {
+                return;
+            };
+            let Some(level) = frame
+                .conflict
+                .iter()
+                .map(|n| state.implications[*n].level)
+                .max()
+            else {
+                // No assignment can extend to a solution
+                self.stack.clear();
+                return;
+            };
+            self.stack.truncate(level);
+            conflict = frame.conflict;
         }
-        Ok(count)
     }
 }
 
@@ -732,4 +1666,465 @@ mod tests {
             assert_eq!(3, back.len())
         }
     }
+
+    mod test_streaming {
+        use super::*;
+        use crate::{
+            fixtures::{dead_loop, purify_chain},
+            strategies::{CircuitParams, circuit_strategy},
+        };
+        use proptest::prelude::*;
+        use rstest::rstest;
+        use std::collections::HashSet;
+
+        /// Number of satisfying assignments by exhaustive evaluation
+        fn brute_force_count(pc: &PureCircuitGraph) -> usize {
+            let fitness = pc.to_fitness_function().unwrap();
+            (0..pc.count_values())
+                .map(|_| Value::iter())
+                .multi_cartesian_product()
+                .filter(|assignment| fitness.evaluate(assignment) == Ok(0))
+                .count()
+        }
+
+        #[test]
+        fn test_take_prefix() {
+            let pc = purify_chain();
+            let all = BacktrackAlgorithm.calculate(&pc).unwrap();
+            let first = BacktrackAlgorithm
+                .solutions(&pc)
+                .unwrap()
+                .take(3)
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(first, all[..3]);
+        }
+
+        #[test]
+        fn test_first_solution() {
+            let pc = purify_chain();
+            let all = BacktrackAlgorithm.calculate(&pc).unwrap();
+            assert_eq!(
+                BacktrackAlgorithm.first_solution(&pc).unwrap().as_ref(),
+                all.first()
+            );
+        }
+
+        #[test]
+        fn test_no_solution() {
+            let pc = dead_loop();
+
+            // Not forces v1 = Bot and Purify cannot output Bot twice
+            assert_eq!(BacktrackAlgorithm.first_solution(&pc), Ok(None));
+        }
+
+        #[test]
+        fn test_resume() {
+            let pc = purify_chain();
+            let all = BacktrackAlgorithm.calculate(&pc).unwrap();
+            let mut iter = BacktrackAlgorithm.solutions(&pc).unwrap();
+            let mut resumed = iter
+                .by_ref()
+                .take(2)
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            // The iterator owns its state, so the graph can change in between
+            drop(pc);
+            resumed.extend(iter.by_ref().map(Result::unwrap));
+            assert_eq!(resumed, all);
+            assert!(iter.next().is_none());
+        }
+
+        #[rstest]
+        #[case(&[(0, Value::Zero)])]
+        #[case(&[(0, Value::Bot), (4, Value::One)])]
+        #[case(&[(2, Value::Bot), (1, Value::One)])]
+        #[case(&[(2, Value::Bot), (2, Value::Zero)])]
+        fn test_assumptions(#[case] assumptions: &[(usize, Value)]) {
+            let pc = purify_chain();
+            let expected = BacktrackAlgorithm
+                .calculate(&pc)
+                .unwrap()
+                .into_iter()
+                .filter(|sol| assumptions.iter().all(|(n, v)| sol[*n] == Some(*v)))
+                .collect::<HashSet<_>>();
+            let assumptions = assumptions
+                .iter()
+                .map(|(n, v)| (NodeIndex::new(*n), *v))
+                .collect::<Vec<_>>();
+            let found = BacktrackAlgorithm
+                .solutions(&pc)
+                .unwrap()
+                .with_assumptions(&assumptions)
+                .unwrap()
+                .collect::<Result<HashSet<_>, _>>()
+                .unwrap();
+            assert_eq!(found, expected);
+        }
+
+        #[test]
+        fn test_assumption_on_gate() {
+            let pc = purify_chain();
+            let search = BacktrackAlgorithm
+                .solutions(&pc)
+                .unwrap()
+                .with_assumptions(&[(NodeIndex::new(3), Value::One)]);
+            assert_eq!(
+                search.err(),
+                Some(SolverError::Graph(GraphError::NotExistentNode))
+            );
+        }
+
+        #[test]
+        fn test_assumptions_assign_every_node() {
+            let pc = purify_chain();
+            let sol = BacktrackAlgorithm.first_solution(&pc).unwrap().unwrap();
+            let assumptions = sol
+                .iter()
+                .enumerate()
+                .filter_map(|(n, v)| v.map(|v| (NodeIndex::new(n), v)))
+                .collect::<Vec<_>>();
+            let found = BacktrackAlgorithm
+                .solutions(&pc)
+                .unwrap()
+                .with_assumptions(&assumptions)
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(found, vec![sol]);
+        }
+
+        proptest! {
+            #[test]
+            fn matches_brute_force(pc in circuit_strategy(CircuitParams::new(1..=6, 0..=6))) {
+                let sols = BacktrackAlgorithm.calculate(&pc);
+                prop_assert!(sols.is_ok(), "{:?}", sols);
+                let sols = sols.unwrap();
+                prop_assert!(sols.iter().all_unique());
+                let fitness = pc.to_fitness_function().unwrap();
+                for sol in &sols {
+                    let mut other = pc.clone();
+                    other.from_backtrack_sol(sol).unwrap();
+                    prop_assert_eq!(fitness.evaluate(&other.to_chromosone()), Ok(0));
+                }
+                prop_assert_eq!(sols.len(), brute_force_count(&pc));
+            }
+        }
+    }
+
+    mod test_learning {
+        use super::*;
+        use crate::{
+            solution_finders::counting::SolutionCount,
+            strategies::{CircuitParams, circuit_strategy},
+        };
+        use proptest::prelude::*;
+
+        /// Value node that only `Bot` satisfies
+        fn add_bot_node(pc: &mut PureCircuitGraph) -> NodeIndex {
+            let v = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+            let g = pc.add_node(NodeUnitialised::from_gate(Gate::Not), ());
+            pc.add_edge(v, g, ()).unwrap();
+            pc.add_edge(g, v, ()).unwrap();
+            v
+        }
+
+        /// Unsatisfiable core behind `free` unconstrained copy gates, which the branching
+        /// order picks first
+        pub(super) fn hidden_core(free: usize) -> PureCircuitGraph {
+            let mut pc = PureCircuitGraph::new();
+            for _ in 0..free {
+                let v = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+                for _ in 0..3 {
+                    let w = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+                    let g = pc.add_node(NodeUnitialised::from_gate(Gate::Copy), ());
+                    pc.add_edge(v, g, ()).unwrap();
+                    pc.add_edge(g, w, ()).unwrap();
+                }
+            }
+            // Purify needs a pure output when its input is Bot
+            let p = add_bot_node(&mut pc);
+            let q = add_bot_node(&mut pc);
+            let r = add_bot_node(&mut pc);
+            let g = pc.add_node(NodeUnitialised::from_gate(Gate::Purify), ());
+            pc.add_edge(p, g, ()).unwrap();
+            pc.add_edge(g, q, ()).unwrap();
+            pc.add_edge(g, r, ()).unwrap();
+            pc
+        }
+
+        #[test]
+        fn test_backjump_over_independent_nodes() {
+            let pc = hidden_core(100);
+            let mut search = BacktrackAlgorithm.solutions(&pc).unwrap();
+            assert_eq!(search.next(), None);
+            // Chronological backtracking would revisit the core for all 3^100 prefixes
+            assert!(search.stats().decisions < 1000, "{:?}", search.stats());
+            assert!(search.learned_nogoods() > 0);
+        }
+
+        proptest! {
+            #[test]
+            fn matches_counting(pc in circuit_strategy(CircuitParams::new(1..=10, 2..=14))) {
+                let sols = BacktrackAlgorithm.calculate(&pc).unwrap();
+                prop_assert!(sols.iter().all_unique());
+                let count = BacktrackAlgorithm.count_solutions(&pc, &Budget::default()).unwrap();
+                prop_assert_eq!(count, SolutionCount::Exact(sols.len() as u128));
+            }
+        }
+    }
+
+    mod test_restarts {
+        use super::{test_learning::hidden_core, *};
+        use crate::{
+            solution_finders::heuristics::{Activity, DomWdeg, Portfolio},
+            strategies::{CircuitParams, circuit_strategy},
+        };
+        use proptest::prelude::*;
+        use std::collections::HashSet;
+
+        #[test]
+        fn test_luby() {
+            assert_eq!(
+                (0..15).map(luby).collect_vec(),
+                vec![1, 1, 2, 1, 1, 2, 4, 1, 1, 2, 1, 1, 2, 4, 8]
+            );
+        }
+
+        #[test]
+        fn test_restarts_keep_nogoods() {
+            let pc = hidden_core(20);
+            let mut search = BacktrackAlgorithm
+                .solutions(&pc)
+                .unwrap()
+                .with_heuristic(Portfolio::new(vec![
+                    Box::new(DomWdeg::new()),
+                    Box::new(Activity::default()),
+                ]))
+                .with_restarts(1);
+            assert_eq!(search.next(), None);
+            assert!(search.stats().restarts > 0, "{:?}", search.stats());
+        }
+
+        proptest! {
+            #[test]
+            fn restarts_yield_every_solution_once(
+                pc in circuit_strategy(CircuitParams::new(1..=8, 2..=12)),
+            ) {
+                let expected = BacktrackAlgorithm.calculate(&pc).unwrap();
+                let sols = BacktrackAlgorithm
+                    .solutions(&pc)
+                    .unwrap()
+                    .with_heuristic(Activity::default())
+                    .with_restarts(1)
+                    .collect::<Result<Vec<_>, _>>()
+                    .unwrap();
+                prop_assert!(sols.iter().all_unique());
+                prop_assert_eq!(
+                    sols.into_iter().collect::<HashSet<_>>(),
+                    expected.into_iter().collect::<HashSet<_>>()
+                );
+            }
+        }
+    }
+
+    mod test_budget {
+        use super::*;
+        use crate::fixtures::copy_pairs;
+
+        #[test]
+        fn test_unlimited() {
+            let pc = copy_pairs(1);
+            let sol = BacktrackAlgorithm
+                .find_solution(BacktrackParamSet::new(&pc).unwrap())
+                .unwrap();
+            assert_eq!(sol.solutions, BacktrackAlgorithm.calculate(&pc).unwrap());
+            assert!(!sol.interrupted);
+        }
+
+        #[test]
+        fn test_max_solutions() {
+            let param_set = BacktrackParamSet {
+                max_solutions: Some(2),
+                ..BacktrackParamSet::new(&copy_pairs(1)).unwrap()
+            };
+            let sol = BacktrackAlgorithm.find_solution(param_set).unwrap();
+            assert_eq!(sol.solutions.len(), 2);
+            assert!(!sol.interrupted);
+        }
+
+        #[test]
+        fn test_node_limit() {
+            let param_set = BacktrackParamSet {
+                budget: Budget {
+                    max_nodes: Some(2),
+                    ..Default::default()
+                },
+                ..BacktrackParamSet::new(&copy_pairs(1)).unwrap()
+            };
+            // Each of the first two branches completes a solution
+            let sol = BacktrackAlgorithm.find_solution(param_set).unwrap();
+            assert_eq!(sol.solutions.len(), 2);
+            assert!(sol.interrupted);
+        }
+
+        #[test]
+        fn test_cancelled() {
+            let param_set = BacktrackParamSet::new(&copy_pairs(1)).unwrap();
+            param_set.budget.cancellation.cancel();
+            let sol = BacktrackAlgorithm.find_solution(param_set).unwrap();
+            assert!(sol.solutions.is_empty());
+            assert!(sol.interrupted);
+        }
+
+        #[test]
+        fn test_stats() {
+            let sol = BacktrackAlgorithm
+                .calculate_with_stats(&copy_pairs(1))
+                .unwrap();
+            // Every value of the first node forces the second one
+            assert_eq!(sol.solutions.len(), 3);
+            assert_eq!(sol.stats.decisions, 3);
+            assert_eq!(sol.stats.propagations, 3);
+            assert_eq!(sol.stats.conflicts, 0);
+            assert_eq!(sol.stats.max_depth, 1);
+
+            // Only Bot is its own negation
+            let mut pc = PureCircuitGraph::<(), ()>::new();
+            let v = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+            let g = pc.add_node(NodeUnitialised::from_gate(Gate::Not), ());
+            pc.add_edge(v, g, ()).unwrap();
+            pc.add_edge(g, v, ()).unwrap();
+            let sol = BacktrackAlgorithm
+                .find_solution(BacktrackParamSet::new(&pc).unwrap())
+                .unwrap();
+            assert_eq!(sol.solutions, vec![vec![Some(Value::Bot)]]);
+            assert_eq!(sol.stats.decisions, 3);
+            assert_eq!(sol.stats.conflicts, 2);
+        }
+    }
+
+    mod test_observer {
+        use super::*;
+        use crate::solution_finders::observer::ChannelObserver;
+
+        #[test]
+        fn test_events() {
+            let mut pc = PureCircuitGraph::<(), ()>::new();
+            let v1 = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+            let v2 = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+            let g = pc.add_node(NodeUnitialised::from_gate(Gate::Copy), ());
+            pc.add_edge(v1, g, ()).unwrap();
+            pc.add_edge(g, v2, ()).unwrap();
+
+            let (observer, receiver) = ChannelObserver::new();
+            let param_set = BacktrackParamSet {
+                observer: Some(observer.shared()),
+                ..BacktrackParamSet::new(&pc).unwrap()
+            };
+            let sol = BacktrackAlgorithm.find_solution(param_set).unwrap();
+            let events = receiver.try_iter().collect_vec();
+
+            let branches = events
+                .iter()
+                .filter(|e| matches!(e, SolverEvent::Branch { .. }))
+                .count();
+            assert_eq!(branches, 3);
+            let solutions = events
+                .iter()
+                .filter_map(|e| match e {
+                    SolverEvent::Solution { found, .. } => Some(*found),
+                    _ => None,
+                })
+                .collect_vec();
+            assert_eq!(solutions, (1..=sol.solutions.len()).collect_vec());
+            assert_eq!(
+                events.last(),
+                Some(&SolverEvent::Finished { interrupted: false })
+            );
+            assert_eq!(
+                events
+                    .iter()
+                    .filter(|e| matches!(e, SolverEvent::Finished { .. }))
+                    .count(),
+                1
+            );
+        }
+
+        #[test]
+        fn test_interrupted() {
+            let mut pc = PureCircuitGraph::<(), ()>::new();
+            pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+            let (observer, receiver) = ChannelObserver::new();
+            let mut search = BacktrackAlgorithm
+                .solutions(&pc)
+                .unwrap()
+                .with_budget(&Budget {
+                    max_nodes: Some(1),
+                    ..Default::default()
+                })
+                .with_observer(observer.shared());
+            assert_eq!(search.by_ref().count(), 1);
+            assert_eq!(search.next(), None);
+            assert_eq!(
+                receiver
+                    .try_iter()
+                    .filter(|e| matches!(e, SolverEvent::Finished { .. }))
+                    .collect_vec(),
+                vec![SolverEvent::Finished { interrupted: true }]
+            );
+        }
+    }
+
+    mod test_errors {
+        use super::*;
+
+        #[test]
+        fn test_missing_assignment() {
+            let mut pc = PureCircuitGraph::<(), ()>::new();
+            let v1 = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+            let v2 = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+            let g = pc.add_node(NodeUnitialised::from_gate(Gate::Copy), ());
+            pc.add_edge(v1, g, ()).unwrap();
+            pc.add_edge(g, v2, ()).unwrap();
+
+            assert_eq!(
+                pc.from_backtrack_sol(&[Some(Value::One), None]),
+                Err(SolverError::MissingAssignment { node_index: v2 })
+            );
+        }
+
+        #[test]
+        fn test_inconsistent_assignment() {
+            assert_eq!(
+                Gate::Copy.set_value([Some(Value::One), None], [Some(Value::Zero), None]),
+                Err(SolverError::InconsistentAssignment {
+                    gate: Gate::Copy,
+                    gate_index: None
+                })
+            );
+        }
+
+        #[test]
+        fn test_empty_graph() {
+            let pc = PureCircuitGraph::<(), ()>::new();
+            assert_eq!(
+                BacktrackAlgorithm.calculate(&pc),
+                Err(SolverError::EmptyGraph)
+            );
+        }
+
+        #[test]
+        fn test_dangling_gate() {
+            let mut pc = PureCircuitGraph::<(), ()>::new();
+            let v1 = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+            let g = pc.add_node(NodeUnitialised::from_gate(Gate::Purify), ());
+            pc.add_edge(v1, g, ()).unwrap();
+
+            assert_eq!(
+                BacktrackAlgorithm.calculate(&pc),
+                Err(SolverError::InvalidArity { gate_index: g })
+            );
+        }
+    }
 }
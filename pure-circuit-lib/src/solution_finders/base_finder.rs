@@ -1,9 +1,9 @@
 use crate::{
-    gates::{Gate, NodeUnitialised, NodeValue, Value},
-    graph::PureCircuitGraph,
+    gates::{Gate, NodeUnitialised, NodeValue, Value, VoltageOrdering},
+    graph::{GraphError, PureCircuitGraph},
+    solution_finders::solver_error::SolverError,
 };
-use genetic_algorithm::allele::Allele;
-use itertools::{EitherOrBoth, Itertools};
+use itertools::Itertools;
 use petgraph::prelude::*;
 use std::collections::HashMap;
 use std::fmt::Debug;
@@ -15,10 +15,39 @@ type Inner = (
     [Option<usize>; MAX_DEGREE],
 );
 
+/// How much a violated gate adds to the score of a chromosome
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ViolationMeasure {
+    /// Every violated gate adds 1
+    #[default]
+    Count,
+    /// Fewest port values, inputs included, to change for the gate to hold
+    PortChanges,
+    /// Fewest steps along `VoltageOrdering` that take the outputs to ones the inputs allow
+    Voltage,
+}
+
+/// Objective the meta-heuristics minimise besides the ⊥ values
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FitnessObjective {
+    pub measure: ViolationMeasure,
+    /// Weight of each gate, indexed like `FitnessPureCircuit::gate`. Missing gates weigh 1,
+    /// a gate of weight 0 is ignored by the score
+    pub gate_weights: Option<Vec<usize>>,
+}
+
+/// Fitness function of a circuit
 #[derive(Debug, Clone, Default)]
-pub struct FitnessPureCircuit(Box<[Inner]>);
+pub struct FitnessPureCircuit {
+    /// Gate type with the chromosome positions of its ports
+    gates: Box<[Inner]>,
+    /// Cost of ⊥ on every position of the chromosome, when minimising ⊥
+    bot_weights: Option<Box<[usize]>>,
+    /// Gates connected to every position of the chromosome
+    incident: Box<[Box<[usize]>]>,
+    objective: FitnessObjective,
+}
 
-impl Allele for Value {}
 impl<T: Debug + Copy, G: Debug + Copy> PureCircuitGraph<T, G> {
     /// Convert graph to an array of values
     /// # Example
@@ -42,35 +71,38 @@ impl<T: Debug + Copy, G: Debug + Copy> PureCircuitGraph<T, G> {
             .collect()
     }
 
-    /// Extract the values from a chromosome
-    pub fn from_chromosone(&mut self, chromosome: &[Value]) -> Option<()> {
-        for e in self
+    /// Extract the values from a chromosome, in the order of `to_chromosone`
+    /// # Errors
+    /// * InvalidAssignmentLength: The chromosome does not hold one value per value node
+    /// * Graph: Exists value node that could not be updated
+    pub fn from_chromosone(&mut self, chromosome: &[Value]) -> Result<(), SolverError> {
+        let nodes = self
             .graph
             .node_indices()
             .filter(|n| !self.graph[*n].node.is_gate())
-            .zip_longest(chromosome.iter())
-            .collect::<Box<[_]>>()
-        {
-            match e {
-                EitherOrBoth::Both(indx, value) => {
-                    if let Err(e) = self.update_node(indx, NodeUnitialised::from_value(*value)) {
-                        dbg!(e);
-                        return None;
-                    }
-                }
-                _ => return None,
-            };
+            .collect::<Box<[_]>>();
+        if nodes.len() != chromosome.len() {
+            return Err(SolverError::InvalidAssignmentLength {
+                expected: nodes.len(),
+                found: chromosome.len(),
+            });
+        }
+        for (indx, value) in nodes.iter().zip(chromosome) {
+            self.update_node(*indx, NodeUnitialised::from_value(*value))?;
         }
-        Some(())
+        Ok(())
     }
 
     /// Create a fitness function with respect to the gates
     /// Each gate is associated with a set of indices that correspond
     /// to the position of its neighbours with respect to the chromosome.
     /// # Returns
-    /// * None: Exist node with invalid arity
-    /// * Some(*): Fitness function instance
-    pub fn to_fitness_function(&self) -> Option<FitnessPureCircuit> {
+    /// * Ok(*): Fitness function instance
+    /// # Errors
+    /// * InvalidArity: Exists gate with invalid arity
+    /// * Graph(NonHeterogeneousEdge): Exists gate connected to another gate
+    pub fn to_fitness_function(&self) -> Result<FitnessPureCircuit, SolverError> {
+        self.validate_arity()?;
         let map = self
             .graph
             .node_indices()
@@ -78,6 +110,11 @@ impl<T: Debug + Copy, G: Debug + Copy> PureCircuitGraph<T, G> {
             .enumerate()
             .map(|(a, b)| (b, a))
             .collect::<HashMap<NodeIndex, usize>>();
+        let chromosome_index = |n: NodeIndex| {
+            map.get(&n)
+                .copied()
+                .ok_or(SolverError::Graph(GraphError::NonHeterogeneousEdge))
+        };
 
         let mapper = self
             .graph
@@ -89,87 +126,431 @@ impl<T: Debug + Copy, G: Debug + Copy> PureCircuitGraph<T, G> {
             .map(|(nod_ind, gate)| {
                 let mut ret_in = [None; MAX_DEGREE];
                 let mut ret_out = [None; MAX_DEGREE];
-                for (node, ind) in self
-                    .graph
-                    .neighbors_directed(nod_ind, Direction::Incoming)
-                    .collect_vec()
+                for (port, ind) in self
+                    .gate_ports(nod_ind, Direction::Incoming)?
                     .into_iter()
-                    .rev()
                     .enumerate()
                 {
-                    ret_in[node] = Some(ind)
+                    ret_in[port] = ind.map(chromosome_index).transpose()?;
                 }
-
-                for (node, ind) in self
-                    .graph
-                    .neighbors_directed(nod_ind, Direction::Outgoing)
-                    .collect_vec()
+                for (port, ind) in self
+                    .gate_ports(nod_ind, Direction::Outgoing)?
                     .into_iter()
-                    .rev()
                     .enumerate()
                 {
-                    ret_out[node] = Some(ind)
+                    ret_out[port] = ind.map(chromosome_index).transpose()?;
                 }
 
-                (gate, ret_in, ret_out)
+                Ok((gate, ret_in, ret_out))
             })
-            .map::<Option<Inner>, _>(|(g, in_ind, out_ind)| {
-                if g.arity()
-                    == (
-                        in_ind.iter().copied().filter(Option::is_some).count(),
-                        out_ind.iter().copied().filter(Option::is_some).count(),
-                    )
-                {
-                    let in_ind = in_ind
-                        .iter()
-                        .copied()
-                        .map(|e| e.map(|e| *map.get(&e).unwrap()))
-                        .collect_array::<MAX_DEGREE>()?;
-                    let out_ind = out_ind
-                        .iter()
-                        .copied()
-                        .map(|e| e.map(|e| *map.get(&e).unwrap()))
-                        .collect_array::<MAX_DEGREE>()?;
-                    Some((g, in_ind, out_ind))
-                } else {
-                    None
-                }
+            .collect::<Result<Box<[Inner]>, SolverError>>()?;
+        Ok(FitnessPureCircuit::new(mapper))
+    }
+}
+
+impl<T, G> PureCircuitGraph<T, G> {
+    /// Check that every gate satisfies its arity and is only connected to value nodes
+    /// # Errors
+    /// * InvalidArity: Exists gate with invalid arity
+    /// * Graph(NonHeterogeneousEdge): Exists gate connected to another gate
+    pub fn validate_arity(&self) -> Result<(), SolverError> {
+        for gate_index in self.graph.node_indices() {
+            let NodeValue::GateNode { gate, .. } = self.graph[gate_index].into_node() else {
+                continue;
+            };
+            if self
+                .get_all_neigh(gate_index)
+                .iter()
+                .any(|n| self.graph[*n].node.is_gate())
+            {
+                return Err(GraphError::NonHeterogeneousEdge.into());
+            }
+            let arity = (
+                self.graph
+                    .edges_directed(gate_index, Direction::Incoming)
+                    .count(),
+                self.graph
+                    .edges_directed(gate_index, Direction::Outgoing)
+                    .count(),
+            );
+            if arity != gate.arity() {
+                return Err(SolverError::InvalidArity { gate_index });
+            }
+        }
+        Ok(())
+    }
+
+    /// Value nodes connected to the ports of a gate, ordered by port
+    /// # Errors
+    /// * InvalidArity: Gate has more than `MAX_DEGREE` ports in the direction
+    /// * Graph(NonHeterogeneousEdge): Gate is connected to another gate or the index is not a gate
+    pub fn gate_ports(
+        &self,
+        gate_index: NodeIndex,
+        dir: Direction,
+    ) -> Result<[Option<NodeIndex>; MAX_DEGREE], SolverError> {
+        if !self
+            .graph
+            .node_weight(gate_index)
+            .is_some_and(|n| n.node.is_gate())
+        {
+            return Err(GraphError::NonHeterogeneousEdge.into());
+        }
+        let mut edges = self
+            .graph
+            .edges_directed(gate_index, dir)
+            .map(|e| {
+                (
+                    e.weight().0,
+                    match dir {
+                        Direction::Incoming => e.source(),
+                        Direction::Outgoing => e.target(),
+                    },
+                )
             })
-            .collect::<Option<Box<[Inner]>>>()?;
-        Some(FitnessPureCircuit(mapper))
+            .collect_vec();
+        if edges.len() > MAX_DEGREE {
+            return Err(SolverError::InvalidArity { gate_index });
+        }
+        edges.sort_by_key(|e| e.0);
+        let mut ret = [None; MAX_DEGREE];
+        for (port, (_, node)) in edges.into_iter().enumerate() {
+            if self.graph[node].node.is_gate() {
+                return Err(GraphError::NonHeterogeneousEdge.into());
+            }
+            ret[port] = Some(node);
+        }
+        Ok(ret)
     }
 }
 
 impl FitnessPureCircuit {
+    /// Index the gates connected to every position of the chromosome
+    fn new(gates: Box<[Inner]>) -> Self {
+        let len = gates
+            .iter()
+            .flat_map(|(_, ins, outs)| ins.iter().chain(outs.iter()))
+            .filter_map(|s| s.map(|ind| ind + 1))
+            .max()
+            .unwrap_or(0);
+        let mut incident = vec![vec![]; len];
+        for (gate, (_, ins, outs)) in gates.iter().enumerate() {
+            for node in ins.iter().chain(outs.iter()).flatten().unique() {
+                incident[*node].push(gate);
+            }
+        }
+        Self {
+            gates,
+            bot_weights: None,
+            incident: incident.into_iter().map(Vec::into_boxed_slice).collect(),
+            objective: FitnessObjective::default(),
+        }
+    }
+
+    /// Minimum length of the chromosomes that can be evaluated
+    pub fn required_len(&self) -> usize {
+        self.incident.len()
+    }
 
     /// Fitness function. Given a slice of values,
     /// measure the number of gates that are not satisfied
     /// # Returns
-    /// * None: Exists node with invalid arity
-    /// * Some(*): Number of missmatched gates
-    pub fn evaluate(&self, inputs: &[Value]) -> Option<usize> {
+    /// * Ok(*): Number of missmatched gates
+    /// # Errors
+    /// * InvalidAssignmentLength: Input is shorter than the chromosome
+    /// * Gate: Exists node with invalid arity
+    pub fn evaluate(&self, inputs: &[Value]) -> Result<usize, SolverError> {
+        if inputs.len() < self.required_len() {
+            return Err(SolverError::InvalidAssignmentLength {
+                expected: self.required_len(),
+                found: inputs.len(),
+            });
+        }
         let mut errors = 0usize;
-        let t = inputs;
-        for (g, ins, outs) in self.0.iter().copied() {
-            let ins = ins
-                .into_iter()
-                .filter_map(|s| s.map(|ind| t[ind]))
-                .collect_vec();
-            let outs = outs
-                .into_iter()
-                .filter_map(|s| s.map(|ind| t[ind]))
-                .collect_vec();
-            match g.check(ins.as_slice(), outs.as_slice()) {
-                Err(e) => {
-                    dbg!(e);
-                    return None;
+        for gate in 0..self.gates.len() {
+            errors += self.violated_by(gate, |ind| inputs[ind])? as usize;
+        }
+
+        Ok(errors)
+    }
+
+    /// Number of gates of the circuit
+    pub fn gate_count(&self) -> usize {
+        self.gates.len()
+    }
+
+    /// Gate type with the chromosome positions of its input and output ports.
+    /// None if the circuit has no such gate
+    pub fn gate(&self, gate: usize) -> Option<Inner> {
+        self.gates.get(gate).copied()
+    }
+
+    /// Positions of the chromosome connected to the gate, inputs first.
+    /// Empty if the circuit has no such gate
+    pub fn gate_nodes(&self, gate: usize) -> impl Iterator<Item = usize> + '_ {
+        self.gates
+            .get(gate)
+            .into_iter()
+            .flat_map(|(_, ins, outs)| ins.iter().chain(outs.iter()))
+            .flatten()
+            .copied()
+    }
+
+    /// Gates connected to a position of the chromosome, in ascending order without duplicates.
+    /// Empty for positions no gate is connected to
+    pub fn incident_gates(&self, node: usize) -> &[usize] {
+        self.incident.get(node).map_or(&[], |gates| gates)
+    }
+
+    /// Check whether the values violate a single gate
+    /// # Errors
+    /// * Graph(NotExistentNode): The circuit has no such gate
+    /// * InvalidAssignmentLength: Input is shorter than the chromosome
+    /// * Gate: The gate has an invalid arity
+    pub fn violated(&self, gate: usize, inputs: &[Value]) -> Result<bool, SolverError> {
+        self.check_gate(gate, inputs)?;
+        self.violated_by(gate, |ind| inputs[ind])
+    }
+
+    /// Check that `gate` exists and `inputs` covers the chromosome
+    fn check_gate(&self, gate: usize, inputs: &[Value]) -> Result<(), SolverError> {
+        if gate >= self.gates.len() {
+            return Err(GraphError::NotExistentNode.into());
+        }
+        if inputs.len() < self.required_len() {
+            return Err(SolverError::InvalidAssignmentLength {
+                expected: self.required_len(),
+                found: inputs.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Check a single gate against the values `value` reads, without allocating
+    fn violated_by(
+        &self,
+        gate: usize,
+        value: impl Fn(usize) -> Value,
+    ) -> Result<bool, SolverError> {
+        let (g, ins, outs) = self.gates[gate];
+        let (ins, in_len) = port_values(ins, &value);
+        let (outs, out_len) = port_values(outs, &value);
+        Ok(!g.check(&ins[..in_len], &outs[..out_len])?)
+    }
+
+    /// Number of violated gates after a few positions of a chromosome changed, given the
+    /// number before the change. Only the gates connected to the changed positions are checked
+    /// again, hence it agrees with `evaluate`
+    /// # Parameters
+    /// * `prev_score` - `evaluate` of the chromosome before the change
+    /// * `changed` - Changed positions with their value before the change.
+    ///   A position listed twice keeps the value of its first entry
+    /// * `inputs` - Chromosome after the change
+    /// # Errors
+    /// * InvalidAssignmentLength: Input is shorter than the chromosome or a changed position
+    /// * Gate: Exists node with invalid arity
+    pub fn evaluate_delta(
+        &self,
+        prev_score: usize,
+        changed: &[(usize, Value)],
+        inputs: &[Value],
+    ) -> Result<usize, SolverError> {
+        let expected = changed
+            .iter()
+            .map(|(ind, _)| ind + 1)
+            .fold(self.required_len(), usize::max);
+        if inputs.len() < expected {
+            return Err(SolverError::InvalidAssignmentLength {
+                expected,
+                found: inputs.len(),
+            });
+        }
+        let before = |ind: usize| {
+            changed
+                .iter()
+                .find(|(c, _)| *c == ind)
+                .map_or(inputs[ind], |(_, v)| *v)
+        };
+        let mut errors = prev_score;
+        for (k, (ind, _)) in changed.iter().enumerate() {
+            for gate in self.incident_gates(*ind) {
+                // Gates next to an earlier position were already checked again
+                if changed[..k]
+                    .iter()
+                    .any(|(c, _)| self.incident_gates(*c).contains(gate))
+                {
+                    continue;
                 }
-                Ok(b) => errors += !b as usize,
+                let was = self.violated_by(*gate, before)?;
+                let is = self.violated(*gate, inputs)?;
+                errors = (errors + is as usize).saturating_sub(was as usize);
             }
         }
+        Ok(errors)
+    }
+
+    /// Also minimise the ⊥ values, once no gate is violated.
+    /// The score becomes lexicographic: violated gates first, then the weighted ⊥ count
+    /// # Parameters
+    /// * `weights` - Cost of ⊥ on each position of the chromosome, missing positions cost 0.
+    ///   `BacktrackAlgorithm::purest_solution` takes its weights in the same order
+    pub fn minimising_bot(self, weights: Vec<usize>) -> Self {
+        Self {
+            bot_weights: Some(weights.into_boxed_slice()),
+            ..self
+        }
+    }
+
+    /// Weighted number of ⊥ values. Always 0 if not minimising ⊥
+    pub fn bot_cost(&self, inputs: &[Value]) -> usize {
+        self.bot_weights.as_ref().map_or(0, |weights| {
+            weights
+                .iter()
+                .zip(inputs)
+                .filter(|(_, v)| **v == Value::Bot)
+                .map(|(w, _)| *w)
+                .sum()
+        })
+    }
+
+    /// Measure the violated gates with `objective` in `score`, instead of counting them
+    pub fn with_objective(self, objective: FitnessObjective) -> Self {
+        Self { objective, ..self }
+    }
+
+    pub fn objective(&self) -> &FitnessObjective {
+        &self.objective
+    }
+
+    /// Weighted violation of a single gate under the objective, 0 if the gate holds
+    /// # Errors
+    /// * Graph(NotExistentNode): The circuit has no such gate
+    /// * InvalidAssignmentLength: Input is shorter than the chromosome
+    /// * Gate: The gate has an invalid arity
+    pub fn gate_violation(&self, gate: usize, inputs: &[Value]) -> Result<usize, SolverError> {
+        self.check_gate(gate, inputs)?;
+        self.graded_violation(gate, inputs)
+    }
+
+    /// `gate_violation` of a gate that exists, over an input that covers the chromosome
+    fn graded_violation(&self, gate: usize, inputs: &[Value]) -> Result<usize, SolverError> {
+        let (g, ins, outs) = self.gates[gate];
+        let (ins, in_len) = port_values(ins, |ind| inputs[ind]);
+        let (outs, out_len) = port_values(outs, |ind| inputs[ind]);
+        let (ins, outs) = (&ins[..in_len], &outs[..out_len]);
+        if g.check(ins, outs)? {
+            return Ok(0);
+        }
+        let weight = self
+            .objective
+            .gate_weights
+            .as_ref()
+            .and_then(|weights| weights.get(gate))
+            .map_or(1, |w| *w);
+        let graded = match self.objective.measure {
+            ViolationMeasure::Count => 1,
+            ViolationMeasure::PortChanges => {
+                repair_distance(g, ins, outs, true, |a, b| (a != b) as usize)?
+            }
+            ViolationMeasure::Voltage => repair_distance(g, ins, outs, false, |a, b| {
+                u8::from(VoltageOrdering(a)).abs_diff(VoltageOrdering(b).into()) as usize
+            })?,
+        };
+        Ok(weight.saturating_mul(graded))
+    }
+
+    /// Sum of the weighted violations of the gates under the objective.
+    /// Equal to `evaluate` under the default objective
+    /// # Errors
+    /// * InvalidAssignmentLength: Input is shorter than the chromosome
+    /// * Gate: Exists node with invalid arity
+    pub fn violation(&self, inputs: &[Value]) -> Result<usize, SolverError> {
+        if self.objective == FitnessObjective::default() {
+            return self.evaluate(inputs);
+        }
+        if inputs.len() < self.required_len() {
+            return Err(SolverError::InvalidAssignmentLength {
+                expected: self.required_len(),
+                found: inputs.len(),
+            });
+        }
+        (0..self.gates.len()).try_fold(0usize, |acc, gate| {
+            Ok(acc.saturating_add(self.graded_violation(gate, inputs)?))
+        })
+    }
+
+    /// Score the meta-heuristics minimise. Equal to `violation` if not minimising ⊥,
+    /// otherwise every unit of violation outweighs any number of ⊥ values
+    /// # Errors
+    /// * InvalidAssignmentLength: Input is shorter than the chromosome
+    /// * Gate: Exists node with invalid arity
+    pub fn score(&self, inputs: &[Value]) -> Result<usize, SolverError> {
+        Ok(self
+            .violation(inputs)?
+            .saturating_mul(self.score_base())
+            .saturating_add(self.bot_cost(inputs)))
+    }
+
+    /// One more than the highest possible ⊥ cost
+    fn score_base(&self) -> usize {
+        self.bot_weights
+            .as_ref()
+            .map_or(1, |weights| weights.iter().sum::<usize>().saturating_add(1))
+    }
+}
+
+/// Values of the connected ports, packed at the front of a fixed array
+fn port_values(
+    ports: [Option<usize>; MAX_DEGREE],
+    value: impl Fn(usize) -> Value,
+) -> ([Value; MAX_DEGREE], usize) {
+    let mut ret = [Value::Bot; MAX_DEGREE];
+    let mut len = 0;
+    for ind in ports.into_iter().flatten() {
+        ret[len] = value(ind);
+        len += 1;
+    }
+    (ret, len)
+}
 
-        Some(errors)
+/// Fewest `distance` summed over the ports that takes the gate to an assignment it allows.
+/// Only the outputs may change unless `with_inputs`
+fn repair_distance(
+    gate: Gate,
+    ins: &[Value],
+    outs: &[Value],
+    with_inputs: bool,
+    distance: impl Fn(Value, Value) -> usize,
+) -> Result<usize, SolverError> {
+    let free = outs.len() + with_inputs as usize * ins.len();
+    let mut best = usize::MAX;
+    for code in 0..3usize.pow(free as u32) {
+        let (mut new_ins, mut new_outs) = ([Value::Bot; MAX_DEGREE], [Value::Bot; MAX_DEGREE]);
+        new_ins[..ins.len()].copy_from_slice(ins);
+        new_outs[..outs.len()].copy_from_slice(outs);
+        // Outputs take the lowest digits, inputs the highest
+        let mut rest = code;
+        let inputs = with_inputs as usize * ins.len();
+        for port in new_outs[..outs.len()]
+            .iter_mut()
+            .chain(new_ins[..inputs].iter_mut())
+        {
+            *port = Value::ALL[rest % 3];
+            rest /= 3;
+        }
+        let (new_ins, new_outs) = (&new_ins[..ins.len()], &new_outs[..outs.len()]);
+        if !gate.check(new_ins, new_outs)? {
+            continue;
+        }
+        let dist = ins
+            .iter()
+            .zip(new_ins)
+            .chain(outs.iter().zip(new_outs))
+            .map(|(a, b)| distance(*a, *b))
+            .sum();
+        best = best.min(dist);
     }
+    Ok(best)
 }
 
 #[cfg(test)]
@@ -177,8 +558,9 @@ mod test_evo {
 
     use super::*;
 
-    use crate::{gates::NodeUnitialised, test_utils::enum_strategy};
+    use crate::{gates::NodeUnitialised, strategies::enum_strategy};
     use proptest::prelude::{Strategy, *};
+    use rstest::rstest;
 
     mod conversion_tests {
         use super::*;
@@ -210,6 +592,24 @@ mod test_evo {
             }
         }
 
+        #[test]
+        fn check_from_chromosone() {
+            let mut pc = PureCircuitGraph::<(), ()>::new();
+            pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+            pc.add_node(NodeUnitialised::from_gate(Gate::Copy), ());
+            pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+            assert_eq!(
+                pc.from_chromosone(&[Value::One]),
+                Err(SolverError::InvalidAssignmentLength {
+                    expected: 2,
+                    found: 1
+                })
+            );
+            assert_eq!(pc.to_chromosone(), Box::from([Value::Bot, Value::Bot]));
+            assert_eq!(pc.from_chromosone(&[Value::One, Value::Zero]), Ok(()));
+            assert_eq!(pc.to_chromosone(), Box::from([Value::One, Value::Zero]));
+        }
+
         #[test]
         fn check_mapping_1() {
             let mut pc = PureCircuitGraph::<(), ()>::new();
@@ -222,7 +622,7 @@ mod test_evo {
             pc.add_edge(gate_1, val_3, ()).unwrap();
             let fitness = pc.to_fitness_function().unwrap();
             assert_eq!(
-                *fitness.0,
+                *fitness.gates,
                 *Box::new([(Gate::And, [Some(1), Some(0)], [Some(2), None])])
             );
         }
@@ -239,7 +639,7 @@ mod test_evo {
             pc.add_edge(gate_1, val_3, ()).unwrap();
             let fitness = pc.to_fitness_function().unwrap();
             assert_eq!(
-                *fitness.0,
+                *fitness.gates,
                 *Box::new([(Gate::And, [Some(0), Some(1)], [Some(2), None])])
             );
         }
@@ -256,7 +656,7 @@ mod test_evo {
             pc.add_edge(gate_1, val_2, ()).unwrap();
             let fitness = pc.to_fitness_function().unwrap();
             assert_eq!(
-                *fitness.0,
+                *fitness.gates,
                 *Box::new([(Gate::And, [Some(0), Some(2)], [Some(1), None])])
             );
         }
@@ -269,7 +669,50 @@ mod test_evo {
             let val_2 = pc.add_node(NodeUnitialised::from_value(Value::One), ());
             pc.add_edge(val_1, gate_1, ()).unwrap();
             pc.add_edge(gate_1, val_2, ()).unwrap();
-            assert!(matches!(pc.to_fitness_function(), None));
+            assert_eq!(
+                pc.to_fitness_function().unwrap_err(),
+                SolverError::InvalidArity { gate_index: gate_1 }
+            );
+        }
+
+        #[test]
+        fn check_short_assignment() {
+            let mut pc = PureCircuitGraph::<(), ()>::new();
+            let val_1 = pc.add_node(NodeUnitialised::from_value(Value::One), ());
+            let val_2 = pc.add_node(NodeUnitialised::from_value(Value::One), ());
+            let gate_1 = pc.add_node(NodeUnitialised::from_gate(Gate::Copy), ());
+            pc.add_edge(val_1, gate_1, ()).unwrap();
+            pc.add_edge(gate_1, val_2, ()).unwrap();
+            let fit = pc.to_fitness_function().unwrap();
+            assert_eq!(
+                fit.evaluate(&[Value::One]),
+                Err(SolverError::InvalidAssignmentLength {
+                    expected: 2,
+                    found: 1
+                })
+            );
+        }
+
+        #[test]
+        fn check_minimising_bot() {
+            let mut pc = PureCircuitGraph::<(), ()>::new();
+            let val_1 = pc.add_node(NodeUnitialised::from_value(Value::One), ());
+            let val_2 = pc.add_node(NodeUnitialised::from_value(Value::One), ());
+            let gate_1 = pc.add_node(NodeUnitialised::from_gate(Gate::Copy), ());
+            pc.add_edge(val_1, gate_1, ()).unwrap();
+            pc.add_edge(gate_1, val_2, ()).unwrap();
+            let fit = pc.to_fitness_function().unwrap();
+            assert_eq!(fit.score(&[Value::Bot, Value::Bot]), Ok(0));
+            assert_eq!(fit.score(&[Value::Bot, Value::One]), Ok(1));
+
+            let fit = fit.minimising_bot(vec![1, 2]);
+            assert_eq!(fit.bot_cost(&[Value::Bot, Value::Bot]), 3);
+            assert_eq!(fit.score(&[Value::Zero, Value::Zero]), Ok(0));
+            assert_eq!(fit.score(&[Value::Bot, Value::Bot]), Ok(3));
+            // A violated gate outweighs every ⊥
+            assert_eq!(fit.score(&[Value::Zero, Value::One]), Ok(4));
+            assert_eq!(fit.score(&[Value::Bot, Value::One]), Ok(5));
+            assert_eq!(fit.evaluate(&[Value::Bot, Value::One]), Ok(1));
         }
 
         #[test]
@@ -288,7 +731,7 @@ mod test_evo {
             pc.add_edge(gate_2, val_4, ()).unwrap();
             let fit = pc.to_fitness_function().unwrap();
             assert_eq!(
-                *fit.0,
+                *fit.gates,
                 *Box::new([
                     (Gate::And, [Some(0), Some(1)], [Some(2), None]),
                     (Gate::Copy, [Some(2), None], [Some(3), None]),
@@ -296,6 +739,51 @@ mod test_evo {
             );
         }
 
+        #[test]
+        fn check_incident_gates() {
+            let mut pc = PureCircuitGraph::<(), ()>::new();
+            let val_1 = pc.add_node(NodeUnitialised::from_value(Value::One), ());
+            let val_2 = pc.add_node(NodeUnitialised::from_value(Value::One), ());
+            let val_3 = pc.add_node(NodeUnitialised::from_value(Value::One), ());
+            let gate_1 = pc.add_node(NodeUnitialised::from_gate(Gate::And), ());
+            let gate_2 = pc.add_node(NodeUnitialised::from_gate(Gate::Copy), ());
+            pc.add_edge(val_1, gate_1, ()).unwrap();
+            pc.add_edge(val_1, gate_1, ()).unwrap();
+            pc.add_edge(gate_1, val_2, ()).unwrap();
+            pc.add_edge(val_2, gate_2, ()).unwrap();
+            pc.add_edge(gate_2, val_3, ()).unwrap();
+            let fit = pc.to_fitness_function().unwrap();
+            assert_eq!(fit.gate_count(), 2);
+            assert_eq!(fit.gate_nodes(0).collect_vec(), vec![0, 0, 1]);
+            assert_eq!(fit.incident_gates(0), &[0]);
+            assert_eq!(fit.incident_gates(1), &[0, 1]);
+            assert_eq!(fit.incident_gates(2), &[1]);
+            assert!(fit.incident_gates(3).is_empty());
+            let values = [Value::One, Value::Zero, Value::Zero];
+            assert_eq!(fit.violated(0, &values), Ok(true));
+            assert_eq!(fit.violated(1, &values), Ok(false));
+            assert_eq!(
+                fit.violated(2, &values),
+                Err(SolverError::Graph(GraphError::NotExistentNode))
+            );
+            assert_eq!(
+                fit.violated(0, &[]),
+                Err(SolverError::InvalidAssignmentLength {
+                    expected: 3,
+                    found: 0
+                })
+            );
+            assert_eq!(
+                fit.gate_violation(0, &values[..2]),
+                Err(SolverError::InvalidAssignmentLength {
+                    expected: 3,
+                    found: 2
+                })
+            );
+            assert_eq!(fit.gate(2), None);
+            assert_eq!(fit.gate_nodes(2).count(), 0);
+        }
+
         #[test]
         fn check_mapping_big_2() {
             let mut pc = PureCircuitGraph::<(), ()>::new();
@@ -312,7 +800,7 @@ mod test_evo {
             pc.add_edge(val_3, gate_2, ()).unwrap();
             let fit = pc.to_fitness_function().unwrap();
             assert_eq!(
-                *fit.0,
+                *fit.gates,
                 *Box::new([
                     (Gate::And, [Some(0), Some(3)], [Some(1), None]),
                     (Gate::Copy, [Some(1), None], [Some(2), None]),
@@ -323,6 +811,160 @@ mod test_evo {
 
     mod fitness_tests {
         use super::*;
+        use crate::strategies::{CircuitParams, circuit_strategy};
+
+        /// Fitness of a single gate over the first positions of the chromosome
+        fn single_gate(gate: Gate) -> FitnessPureCircuit {
+            let (ins, outs) = gate.arity();
+            let mut ports = (0..).map(Some);
+            let mut ins_ports = [None; MAX_DEGREE];
+            let mut outs_ports = [None; MAX_DEGREE];
+            for port in ins_ports
+                .iter_mut()
+                .take(ins)
+                .chain(outs_ports.iter_mut().take(outs))
+            {
+                *port = ports.next().flatten();
+            }
+            FitnessPureCircuit::new(Box::new([(gate, ins_ports, outs_ports)]))
+        }
+
+        #[rstest]
+        #[case(Gate::Copy, &[Value::One, Value::One], 0, 0)]
+        #[case(Gate::Copy, &[Value::One, Value::Zero], 1, 2)]
+        #[case(Gate::Not, &[Value::Zero, Value::Bot], 1, 1)]
+        #[case(Gate::And, &[Value::Zero, Value::Bot, Value::One], 1, 2)]
+        #[case(Gate::Or, &[Value::Bot, Value::Bot, Value::Zero], 1, 1)]
+        // Changing the input alone cannot repair both outputs
+        #[case(Gate::Purify, &[Value::Bot, Value::One, Value::Zero], 2, 3)]
+        #[case(Gate::Purify, &[Value::Zero, Value::Zero, Value::One], 1, 2)]
+        fn check_graded_violation(
+            #[case] gate: Gate,
+            #[case] values: &[Value],
+            #[case] port_changes: usize,
+            #[case] voltage: usize,
+        ) {
+            let fit = single_gate(gate);
+            let violated = fit.evaluate(values).unwrap();
+            assert_eq!(fit.violation(values), Ok(violated));
+            for (measure, expected) in [
+                (ViolationMeasure::Count, violated),
+                (ViolationMeasure::PortChanges, port_changes),
+                (ViolationMeasure::Voltage, voltage),
+            ] {
+                let fit = fit.clone().with_objective(FitnessObjective {
+                    measure,
+                    gate_weights: Some(vec![3]),
+                });
+                assert_eq!(fit.violation(values), Ok(3 * expected), "{measure:?}");
+            }
+        }
+
+        #[test]
+        fn check_gate_weights() {
+            let mut pc = PureCircuitGraph::<(), ()>::new();
+            let val_1 = pc.add_node(NodeUnitialised::from_value(Value::One), ());
+            let val_2 = pc.add_node(NodeUnitialised::from_value(Value::One), ());
+            let val_3 = pc.add_node(NodeUnitialised::from_value(Value::One), ());
+            let gate_1 = pc.add_node(NodeUnitialised::from_gate(Gate::Copy), ());
+            let gate_2 = pc.add_node(NodeUnitialised::from_gate(Gate::Copy), ());
+            pc.add_edge(val_1, gate_1, ()).unwrap();
+            pc.add_edge(gate_1, val_2, ()).unwrap();
+            pc.add_edge(val_2, gate_2, ()).unwrap();
+            pc.add_edge(gate_2, val_3, ()).unwrap();
+            let fit = pc
+                .to_fitness_function()
+                .unwrap()
+                .minimising_bot(vec![1, 1, 1])
+                .with_objective(FitnessObjective {
+                    gate_weights: Some(vec![5]),
+                    ..Default::default()
+                });
+            let values = [Value::Zero, Value::One, Value::Bot];
+            assert_eq!(fit.evaluate(&values), Ok(2));
+            // The second gate is missing from the weights and weighs 1
+            assert_eq!(fit.violation(&values), Ok(6));
+            assert_eq!(fit.score(&values), Ok(6 * 4 + 1));
+
+            let fit = fit.with_objective(FitnessObjective {
+                gate_weights: Some(vec![0, 0]),
+                ..Default::default()
+            });
+            assert_eq!(fit.violation(&values), Ok(0));
+            assert_eq!(fit.evaluate(&values), Ok(2));
+        }
+
+        #[test]
+        fn check_delta_length() {
+            let fit =
+                FitnessPureCircuit::new(Box::new([(Gate::Copy, [Some(0), None], [Some(1), None])]));
+            let inputs = [Value::Bot, Value::Bot];
+            assert_eq!(fit.evaluate_delta(0, &[(0, Value::One)], &inputs), Ok(0));
+            assert_eq!(
+                fit.evaluate_delta(1, &[(0, Value::One)], &inputs[..1]),
+                Err(SolverError::InvalidAssignmentLength {
+                    expected: 2,
+                    found: 1
+                })
+            );
+            assert_eq!(
+                fit.evaluate_delta(0, &[(2, Value::One)], &inputs),
+                Err(SolverError::InvalidAssignmentLength {
+                    expected: 3,
+                    found: 2
+                })
+            );
+        }
+
+        proptest! {
+            #[test]
+            fn graded_violations_agree(
+                (pc, values) in circuit_strategy(CircuitParams::new(1..=10, 0..=12)).prop_flat_map(|pc| {
+                    let len = pc.to_chromosone().len();
+                    (Just(pc), prop::collection::vec(enum_strategy::<Value>(), len))
+                }),
+            ) {
+                let fit = pc.to_fitness_function().unwrap();
+                let graded = |measure| fit.clone().with_objective(FitnessObjective {
+                    measure,
+                    gate_weights: None,
+                });
+                let (changes, voltage) = (
+                    graded(ViolationMeasure::PortChanges),
+                    graded(ViolationMeasure::Voltage),
+                );
+                for gate in 0..fit.gate_count() {
+                    let violated = fit.violated(gate, &values).unwrap();
+                    let changes = changes.gate_violation(gate, &values).unwrap();
+                    let voltage = voltage.gate_violation(gate, &values).unwrap();
+                    prop_assert_eq!(changes > 0, violated);
+                    // Changing the outputs along the ordering changes every port it steps on
+                    prop_assert!(changes <= voltage);
+                    prop_assert!(changes <= fit.gate(gate).unwrap().2.iter().flatten().count());
+                }
+            }
+
+            #[test]
+            fn delta_matches_evaluate(
+                (pc, before, changes) in circuit_strategy(CircuitParams::new(1..=10, 0..=12)).prop_flat_map(|pc| {
+                    let len = pc.to_chromosone().len();
+                    (
+                        Just(pc),
+                        prop::collection::vec(enum_strategy::<Value>(), len),
+                        prop::collection::vec((0..len, enum_strategy::<Value>()), 0..=3),
+                    )
+                }),
+            ) {
+                let fit = pc.to_fitness_function().unwrap();
+                let mut after = before.clone();
+                let changed = changes
+                    .iter()
+                    .map(|(ind, value)| (*ind, std::mem::replace(&mut after[*ind], *value)))
+                    .collect_vec();
+                let prev = fit.evaluate(&before).unwrap();
+                prop_assert_eq!(fit.evaluate_delta(prev, &changed, &after), fit.evaluate(&after));
+            }
+        }
 
         proptest! {
 
@@ -338,9 +980,9 @@ mod test_evo {
                     pc.add_edge(gate_1, val_3, ()).unwrap();
                     let fitness = pc.to_fitness_function().unwrap();
 
-                    let s = fitness.evaluate(&vec![x,y,z]);
-                    assert_eq!(s, Some(
-                        (Gate::And.check(&[x,y], &[z]).unwrap() == false) as usize
+                    let s = fitness.evaluate(&[x,y,z]);
+                    assert_eq!(s, Ok(
+                        (!Gate::And.check(&[x,y], &[z]).unwrap()) as usize
                     ), "We are getting {}", Gate::And.check(&[x,y], &[z]).unwrap());
                 }
 
@@ -361,10 +1003,10 @@ mod test_evo {
                     pc.add_edge(val_4, gate_2, ()).unwrap();
                     pc.add_edge(gate_2, val_5, ()).unwrap();
                     let fitness = pc.to_fitness_function().unwrap();
-                    let res = fitness.evaluate(&s.to_vec());
-                    assert_eq!(res, Some(
-                        (Gate::Purify.check(&[s[1]], &[s[0], s[2]]).unwrap() == false) as usize
-                        + (Gate::Or.check(&[s[2], s[3]], &[s[4]]).unwrap() == false) as usize
+                    let res = fitness.evaluate(&s);
+                    assert_eq!(res, Ok(
+                        (!Gate::Purify.check(&[s[1]], &[s[0], s[2]]).unwrap()) as usize
+                        + (!Gate::Or.check(&[s[2], s[3]], &[s[4]]).unwrap()) as usize
                     ));
             }
         }
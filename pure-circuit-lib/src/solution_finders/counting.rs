@@ -0,0 +1,359 @@
+use std::{collections::HashMap, time::Instant};
+
+use crate::{
+    graph::{GraphError, PureCircuitGraph},
+    solution_finders::{
+        backtracking::{BacktrackAlgorithm, BitString, CircuitTable, Propagation, SearchState},
+        nogood::NogoodStore,
+        solver_error::SolverError,
+        solver_trait::{Budget, BudgetMonitor, SolveStats},
+    },
+};
+
+/// Number of solutions returned by `BacktrackAlgorithm::count_solutions`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolutionCount {
+    /// Number of solutions of the circuit
+    Exact(u128),
+    /// The count was interrupted by the budget or does not fit in a `u128`.
+    /// The circuit has at least this many solutions
+    LowerBound(u128),
+}
+
+impl SolutionCount {
+    /// Counted solutions, regardless of whether the count is exact
+    pub fn count(self) -> u128 {
+        match self {
+            Self::Exact(c) | Self::LowerBound(c) => c,
+        }
+    }
+
+    pub fn is_exact(self) -> bool {
+        matches!(self, Self::Exact(_))
+    }
+}
+
+/// Cache key of a component: the residual domain of every node of the component and of
+/// the assigned nodes sharing a gate with it, together with whether the node is assigned
+type ComponentKey = Box<[(usize, BitString, bool)]>;
+
+struct Counter {
+    table: CircuitTable,
+    cache: HashMap<ComponentKey, u128>,
+    monitor: BudgetMonitor,
+    /// Some count exceeded `u128::MAX` and was saturated
+    saturated: bool,
+    stats: SolveStats,
+}
+
+impl BacktrackAlgorithm {
+    /// Count the assignments of the circuit without enumerating them.
+    ///
+    /// Uses the unit propagation of the backtracker. Whenever the unassigned value nodes split
+    /// into disconnected components, the components are counted independently and their counts
+    /// multiplied. Component counts are cached on the residual domains of their nodes.
+    ///
+    /// # Parameters
+    /// * `budget` - Stop once the budget runs out and return the solutions counted so far.
+    ///   Every branch counts as a node
+    ///
+    /// # Errors
+    /// * InvalidArity: Exists gate with invalid arity
+    /// * EmptyGraph: Circuit does not contain any value nodes
+    pub fn count_solutions<T, G>(
+        &self,
+        pc_instance: &PureCircuitGraph<T, G>,
+        budget: &Budget,
+    ) -> Result<SolutionCount, SolverError> {
+        self.count_solutions_with_stats(pc_instance, budget)
+            .map(|(count, _)| count)
+    }
+
+    /// Count the assignments of the circuit, keeping the counters of the search.
+    /// The depth counts the nested branching points, cache hits refer to the component cache
+    pub fn count_solutions_with_stats<T, G>(
+        &self,
+        pc_instance: &PureCircuitGraph<T, G>,
+        budget: &Budget,
+    ) -> Result<(SolutionCount, SolveStats), SolverError> {
+        let started = Instant::now();
+        let (table, state) = CircuitTable::new(pc_instance)?;
+        if state.queue.is_empty() {
+            return Err(SolverError::EmptyGraph);
+        }
+        let mut counter = Counter {
+            table,
+            cache: HashMap::new(),
+            monitor: budget.start(),
+            saturated: false,
+            stats: SolveStats::default(),
+        };
+        let count = counter.count(&state, 0)?;
+        let count =
+            if counter.monitor.was_interrupted() || (counter.saturated && count == u128::MAX) {
+                SolutionCount::LowerBound(count)
+            } else {
+                SolutionCount::Exact(count)
+            };
+        counter.stats.wall_time = started.elapsed();
+        Ok((count, counter.stats))
+    }
+}
+
+impl Counter {
+    /// Count the completions of a propagated state
+    fn count(&mut self, state: &SearchState, depth: usize) -> Result<u128, SolverError> {
+        let components = self.components(state);
+        let mut ret = 1u128;
+        for (i, component) in components.iter().enumerate() {
+            let count = self.count_component(state, component, depth)?;
+            ret = ret.checked_mul(count).unwrap_or_else(|| {
+                self.saturated = true;
+                u128::MAX
+            });
+            if ret == 0 {
+                break;
+            }
+            if self.monitor.was_interrupted() && i + 1 < components.len() {
+                // Components that were not counted may have no solution
+                return Ok(0);
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Split the unassigned nodes into sets that do not share any gate
+    fn components(&self, state: &SearchState) -> Vec<Vec<usize>> {
+        let mut visited = vec![false; state.sol_map.len()];
+        let mut ret = vec![];
+        for (start, _) in state.queue.iter() {
+            if visited[start.index()] {
+                continue;
+            }
+            visited[start.index()] = true;
+            let mut component = vec![];
+            let mut stack = vec![start.index()];
+            while let Some(n) = stack.pop() {
+                component.push(n);
+                for pos in self.table.adjacency[n].iter() {
+                    let (_, ins, outs) = &self.table.gates[*pos];
+                    for m in ins.iter().chain(outs.iter()).flatten() {
+                        if !visited[*m] && state.sol_map[*m].is_none() {
+                            visited[*m] = true;
+                            stack.push(*m);
+                        }
+                    }
+                }
+            }
+            ret.push(component);
+        }
+        ret
+    }
+
+    fn component_key(&self, state: &SearchState, component: &[usize]) -> ComponentKey {
+        let mut nodes = component
+            .iter()
+            .flat_map(|n| self.table.adjacency[*n].iter())
+            .flat_map(|pos| {
+                let (_, ins, outs) = &self.table.gates[*pos];
+                ins.iter().chain(outs.iter()).flatten().copied()
+            })
+            .chain(component.iter().copied())
+            .collect::<Vec<_>>();
+        nodes.sort_unstable();
+        nodes.dedup();
+        nodes
+            .into_iter()
+            .map(|n| {
+                (
+                    n,
+                    state.value_map[n].unwrap_or_default(),
+                    state.sol_map[n].is_some(),
+                )
+            })
+            .collect()
+    }
+
+    fn count_component(
+        &mut self,
+        state: &SearchState,
+        component: &[usize],
+        depth: usize,
+    ) -> Result<u128, SolverError> {
+        let key = self.component_key(state, component);
+        if let Some(count) = self.cache.get(&key) {
+            self.stats.cache_hits += 1;
+            return Ok(*count);
+        }
+
+        let mut in_component = vec![false; state.sol_map.len()];
+        for n in component {
+            in_component[*n] = true;
+        }
+        let mut sub_state = state.restricted(|n| in_component[n.index()]);
+        let Some((node, _)) = sub_state.queue.pop() else {
+            return Ok(1);
+        };
+        let domain = sub_state.value_map[node.index()].ok_or(GraphError::NotExistentNode)?;
+        self.stats.max_depth = self.stats.max_depth.max(depth + 1);
+
+        let mut ret = 0u128;
+        for value in domain.to_value_iter() {
+            if self.monitor.tick() {
+                return Ok(ret);
+            }
+            self.stats.decisions += 1;
+            let mut branch = sub_state.clone();
+            // Learned nogoods would change the residual domains the cache is keyed on
+            let propagation = self.table.unit_propagate(
+                node,
+                value,
+                &mut branch,
+                &NogoodStore::default(),
+                &mut self.stats,
+            )?;
+            if propagation != Propagation::Consistent {
+                continue;
+            }
+            let count = self.count(&branch, depth + 1)?;
+            ret = ret.checked_add(count).unwrap_or_else(|| {
+                self.saturated = true;
+                u128::MAX
+            });
+            if self.monitor.was_interrupted() {
+                return Ok(ret);
+            }
+        }
+        self.cache.insert(key, ret);
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::copy_chain,
+        gates::{Gate, NodeUnitialised, Value},
+        strategies::{CircuitParams, circuit_strategy},
+    };
+    use proptest::prelude::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_independent_nodes() {
+        let mut pc = PureCircuitGraph::<(), ()>::new();
+        for _ in 0..100 {
+            pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+        }
+        // 3^100 does not fit in u128
+        let count = BacktrackAlgorithm
+            .count_solutions(&pc, &Budget::default())
+            .unwrap();
+        assert_eq!(count, SolutionCount::LowerBound(u128::MAX));
+
+        let mut pc = PureCircuitGraph::<(), ()>::new();
+        for _ in 0..40 {
+            pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+        }
+        let count = BacktrackAlgorithm
+            .count_solutions(&pc, &Budget::default())
+            .unwrap();
+        assert_eq!(count, SolutionCount::Exact(3u128.pow(40)));
+    }
+
+    #[test]
+    fn test_copy_chain() {
+        let pc = copy_chain(200);
+        let count = BacktrackAlgorithm
+            .count_solutions(&pc, &Budget::default())
+            .unwrap();
+        assert_eq!(count, SolutionCount::Exact(3));
+    }
+
+    #[test]
+    fn test_stats() {
+        let (count, stats) = BacktrackAlgorithm
+            .count_solutions_with_stats(&copy_chain(10), &Budget::default())
+            .unwrap();
+        assert_eq!(count, SolutionCount::Exact(3));
+        // The first value node decides the whole chain
+        assert_eq!(stats.decisions, 3);
+        assert_eq!(stats.propagations, 30);
+        assert_eq!(stats.conflicts, 0);
+        assert_eq!(stats.max_depth, 1);
+    }
+
+    #[test]
+    fn test_disconnected_purify() {
+        let mut pc = PureCircuitGraph::<(), ()>::new();
+        for _ in 0..20 {
+            let v1 = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+            let v2 = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+            let v3 = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+            let g = pc.add_node(NodeUnitialised::from_gate(Gate::Purify), ());
+            pc.add_edge(v1, g, ()).unwrap();
+            pc.add_edge(g, v2, ()).unwrap();
+            pc.add_edge(g, v3, ()).unwrap();
+        }
+        let count = BacktrackAlgorithm
+            .count_solutions(&pc, &Budget::default())
+            .unwrap();
+        assert_eq!(count, SolutionCount::Exact(5u128.pow(20)));
+    }
+
+    #[test]
+    fn test_time_limit() {
+        let mut pc = PureCircuitGraph::<(), ()>::new();
+        for _ in 0..20 {
+            pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+        }
+        let budget = Budget {
+            time_limit: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        let count = BacktrackAlgorithm.count_solutions(&pc, &budget).unwrap();
+        assert!(!count.is_exact());
+        assert!(count.count() <= 3u128.pow(20));
+    }
+
+    #[test]
+    fn test_node_limit() {
+        let pc = copy_chain(10);
+        let budget = Budget {
+            max_nodes: Some(2),
+            ..Default::default()
+        };
+        // The first two values of the chain are counted before the budget runs out
+        let count = BacktrackAlgorithm.count_solutions(&pc, &budget).unwrap();
+        assert_eq!(count, SolutionCount::LowerBound(2));
+    }
+
+    #[test]
+    fn test_cancelled() {
+        let budget = Budget::default();
+        budget.cancellation.cancel();
+        let count = BacktrackAlgorithm
+            .count_solutions(&copy_chain(3), &budget)
+            .unwrap();
+        assert_eq!(count, SolutionCount::LowerBound(0));
+    }
+
+    #[test]
+    fn test_empty_graph() {
+        let pc = PureCircuitGraph::<(), ()>::new();
+        assert_eq!(
+            BacktrackAlgorithm.count_solutions(&pc, &Budget::default()),
+            Err(SolverError::EmptyGraph)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn matches_enumeration(pc in circuit_strategy(CircuitParams::new(1..=8, 0..=8))) {
+            let sols = BacktrackAlgorithm.calculate(&pc).unwrap();
+            let count = BacktrackAlgorithm.count_solutions(&pc, &Budget::default()).unwrap();
+            prop_assert_eq!(count, SolutionCount::Exact(sols.len() as u128));
+        }
+    }
+}
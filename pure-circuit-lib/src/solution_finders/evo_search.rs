@@ -1,9 +1,13 @@
-use std::marker::PhantomData;
+use std::{
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
 
-use anyhow::{Result as ARes, anyhow};
 use genetic_algorithm::{
+    allele::Allele,
     crossover,
-    fitness::Fitness,
+    fitness::{Fitness, FitnessChromosome, FitnessValue},
     genotype::{Genotype, ListGenotype},
     mutate, select,
     strategy::{
@@ -16,9 +20,16 @@ use strum::IntoEnumIterator;
 
 use crate::{
     gates::Value,
-    solution_finders::{base_finder::FitnessPureCircuit, solver_trait::SolverTrait},
+    solution_finders::{
+        base_finder::{FitnessObjective, FitnessPureCircuit},
+        observer::{SharedObserver, SolverEvent},
+        solver_error::SolverError,
+        solver_trait::{Budget, BudgetMonitor, SolveStats, SolverTrait},
+    },
 };
 
+impl Allele for Value {}
+
 impl Fitness for FitnessPureCircuit {
     type Genotype = ListGenotype<Value>;
     fn calculate_for_chromosome(
@@ -26,7 +37,30 @@ impl Fitness for FitnessPureCircuit {
         chromosome: &genetic_algorithm::fitness::prelude::FitnessChromosome<Self>,
         _genotype: &Self::Genotype,
     ) -> Option<genetic_algorithm::fitness::prelude::FitnessValue> {
-        self.evaluate(&chromosome.genes).map(|x| x as isize)
+        self.score(&chromosome.genes)
+            .ok()
+            .map(|x| isize::try_from(x).unwrap_or(isize::MAX))
+    }
+}
+
+/// Fitness of a circuit, which can count the violated gates of a chromosome whatever it scores
+pub trait CircuitFitness: Fitness<Genotype = ListGenotype<Value>> {
+    /// Number of violated gates of a chromosome
+    /// # Errors
+    /// * InvalidAssignmentLength: Chromosome is shorter than the circuit
+    fn violations(&self, chromosome: &[Value]) -> Result<usize, SolverError>;
+
+    /// Score the chromosomes with `objective` instead
+    fn with_objective(self, objective: FitnessObjective) -> Self;
+}
+
+impl CircuitFitness for FitnessPureCircuit {
+    fn violations(&self, chromosome: &[Value]) -> Result<usize, SolverError> {
+        self.evaluate(chromosome)
+    }
+
+    fn with_objective(self, objective: FitnessObjective) -> Self {
+        FitnessPureCircuit::with_objective(self, objective)
     }
 }
 
@@ -41,6 +75,137 @@ pub struct SolverStruct<T> {
 pub struct SolutionReturn {
     pub chromosone: Vec<Value>,
     pub errors: usize,
+    /// The budget ran out before the algorithm finished, the chromosome is the best found so far
+    pub interrupted: bool,
+    pub stats: SolveStats,
+}
+
+/// Fitness score the meta-heuristics aim for
+const TARGET_SCORE: FitnessValue = 0;
+
+/// Best chromosome with its score and number of violated gates
+type BestChromosome = Arc<Mutex<Option<(Vec<Value>, FitnessValue, usize)>>>;
+
+/// Fitness wrapper that checks the budget on every evaluation. Every evaluation counts as a node.
+///
+/// Once the budget runs out, chromosomes report the target score so that the strategy stops
+/// right away. The best chromosome evaluated until then is recorded in `best`.
+#[derive(Debug, Clone)]
+struct BudgetFitness<G> {
+    inner: G,
+    monitor: BudgetMonitor,
+    best: BestChromosome,
+}
+
+impl<G: CircuitFitness> BudgetFitness<G> {
+    fn new(inner: G, budget: &Budget) -> Self {
+        Self {
+            inner,
+            monitor: budget.start(),
+            best: Arc::default(),
+        }
+    }
+
+    /// Best chromosome evaluated within the budget
+    /// # Errors
+    /// * Cancelled: The budget ran out before any chromosome was evaluated
+    /// * NoSolution: No chromosome received a fitness score
+    fn into_solution(
+        self,
+        generation_capped: bool,
+        stats: SolveStats,
+    ) -> Result<SolutionReturn, SolverError> {
+        let best = self
+            .best
+            .lock()
+            .map_err(|_| SolverError::NoSolution)?
+            .take();
+        match best {
+            Some((chromosone, _, errors)) => Ok(SolutionReturn {
+                chromosone,
+                errors,
+                interrupted: self.monitor.was_interrupted() || (generation_capped && errors > 0),
+                stats: SolveStats {
+                    fitness_evaluations: self.monitor.nodes(),
+                    ..stats
+                },
+            }),
+            None if self.monitor.was_interrupted() => Err(SolverError::Cancelled),
+            None => Err(SolverError::NoSolution),
+        }
+    }
+}
+
+impl<G: CircuitFitness> Fitness for BudgetFitness<G> {
+    type Genotype = ListGenotype<Value>;
+    fn calculate_for_chromosome(
+        &mut self,
+        chromosome: &FitnessChromosome<Self>,
+        genotype: &Self::Genotype,
+    ) -> Option<FitnessValue> {
+        if self.monitor.tick() {
+            return Some(TARGET_SCORE);
+        }
+        let score = self.inner.calculate_for_chromosome(chromosome, genotype)?;
+        let mut best = self.best.lock().ok()?;
+        if best.as_ref().is_none_or(|(_, b, _)| score < *b) {
+            let violations = self.inner.violations(&chromosome.genes).ok()?;
+            *best = Some((chromosome.genes.clone(), score, violations));
+        }
+        Some(score)
+    }
+}
+
+/// Reporter forwarding the progress of the strategies to a `SolverObserver`.
+/// The best chromosome comes from the budget wrapper, so it is not skewed by the target score
+/// reported once the budget runs out. Its violated gates are reported rather than its score,
+/// which depends on the objective.
+#[derive(Debug, Clone)]
+struct ObserverReporter {
+    observer: Option<SharedObserver>,
+    best: BestChromosome,
+}
+
+impl ObserverReporter {
+    fn new<G>(observer: Option<SharedObserver>, fitness: &BudgetFitness<G>) -> Self {
+        Self {
+            observer,
+            best: fitness.best.clone(),
+        }
+    }
+
+    fn finish(&self, solution: &Result<SolutionReturn, SolverError>) {
+        if let Some(observer) = &self.observer {
+            observer.on_event(SolverEvent::Finished {
+                interrupted: solution.as_ref().is_ok_and(|s| s.interrupted),
+            });
+        }
+    }
+}
+
+impl StrategyReporter for ObserverReporter {
+    type Genotype = ListGenotype<Value>;
+
+    fn on_new_generation<S: StrategyState<Self::Genotype>, C: StrategyConfig>(
+        &mut self,
+        _genotype: &Self::Genotype,
+        state: &S,
+        _config: &C,
+    ) {
+        let Some(observer) = &self.observer else {
+            return;
+        };
+        let best_fitness = self
+            .best
+            .lock()
+            .ok()
+            .and_then(|b| b.as_ref().map(|(_, _, violations)| *violations))
+            .map(|violations| isize::try_from(violations).unwrap_or(isize::MAX));
+        observer.on_event(SolverEvent::Generation {
+            generation: state.current_generation(),
+            best_fitness,
+        });
+    }
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -156,6 +321,10 @@ pub struct EvoParamSet<T> {
     pub population_size: usize,
     pub num_of_species: usize,
     pub with_parallel: bool,
+    /// Objective replacing the one of the fitness, e.g. weighted gates or a graded violation
+    pub objective: Option<FitnessObjective>,
+    pub budget: Budget,
+    pub observer: Option<SharedObserver>,
 }
 
 impl Default for EvoParamSet<Build> {
@@ -171,6 +340,9 @@ impl Default for EvoParamSet<Build> {
             crossover: NewType(CrossoverWrapper::Uniform(CrossoverUniform::new(0.5, 0.1))),
             mutate: NewType(MutateWrapper::MultiGene(MutateMultiGene::new(1, 0.2))),
             with_parallel: false,
+            objective: None,
+            budget: Budget::default(),
+            observer: None,
         }
     }
 }
@@ -191,26 +363,34 @@ impl EvoParamSet<Build> {
             mutate: self.mutate.clone(),
             num_of_species: self.num_of_species,
             with_parallel: self.with_parallel,
+            objective: self.objective.clone(),
+            budget: self.budget.clone(),
+            observer: self.observer.clone(),
         }
     }
 }
 
-impl<G: Fitness<Genotype = ListGenotype<Value>>> SolverTrait
-    for SolverStruct<EvolutionaryAlgorithm<G>>
-{
+impl<G: CircuitFitness> SolverTrait for SolverStruct<EvolutionaryAlgorithm<G>> {
     type ParamSet = EvoParamSet<Instance<G>>;
 
     type Solution = SolutionReturn;
 
     // Genetic algorithm setup and execution
-    fn find_solution(&self, param_set: Self::ParamSet) -> ARes<Self::Solution> {
+    fn find_solution(&self, param_set: Self::ParamSet) -> Result<Self::Solution, SolverError> {
+        let started = Instant::now();
         let genotype = ListGenotype::builder()
             .with_allele_list(Value::iter().collect_vec())
             .with_genes_size(param_set.param_type.size)
             .with_genes_hashing(param_set.gene_hashing)
             .build()
-            .map_err(|e| anyhow!(e.0))?;
+            .map_err(|e| SolverError::GenotypeBuild(e.0.into()))?;
 
+        let func = match param_set.objective {
+            Some(objective) => param_set.param_type.func.with_objective(objective),
+            None => param_set.param_type.func,
+        };
+        let fitness = BudgetFitness::new(func, &param_set.budget);
+        let reporter = ObserverReporter::new(param_set.observer, &fitness);
         let evolve = Evolve::builder()
             .with_genotype(genotype.clone())
             .with_target_population_size(param_set.population_size)
@@ -218,38 +398,53 @@ impl<G: Fitness<Genotype = ListGenotype<Value>>> SolverTrait
             .with_select(param_set.selection.0)
             .with_crossover(param_set.crossover.0)
             .with_mutate(param_set.mutate.0)
-            .with_fitness(param_set.param_type.func)
+            .with_fitness(fitness.clone())
             .with_fitness_ordering(FitnessOrdering::Minimize)
             .with_fitness_cache(param_set.fitness_cache)
-            .with_target_fitness_score(0)
-            .with_max_stale_generations(param_set.stale_generations);
+            .with_target_fitness_score(TARGET_SCORE)
+            .with_max_stale_generations(param_set.stale_generations)
+            .with_max_generations_option(param_set.budget.max_generations)
+            .with_reporter(reporter.clone());
 
-        let evolve = if param_set.with_parallel {
+        let (best_run, runs) = if param_set.with_parallel {
             evolve
                 .with_par_fitness(true)
                 .call_par_speciated(param_set.num_of_species)
-                .map_err(|e| anyhow!(e.0))?
+                .map_err(|e| SolverError::StrategyBuild(e.0.into()))?
         } else {
             evolve
                 .with_par_fitness(false)
                 .call_speciated(param_set.num_of_species)
-                .map_err(|e| anyhow!(e.0))?
+                .map_err(|e| SolverError::StrategyBuild(e.0.into()))?
+        };
+        let generation_capped = param_set
+            .budget
+            .max_generations
+            .is_some_and(|m| best_run.state.current_generation >= m);
+        let stats = SolveStats {
+            wall_time: started.elapsed(),
+            generations: runs
+                .iter()
+                .chain([&best_run])
+                .map(|run| run.state.current_generation)
+                .sum(),
+            // Every run shares the cache of the builder
+            cache_hits: best_run
+                .config
+                .fitness_cache
+                .as_ref()
+                .map_or(0, |cache| cache.hit_miss_stats().0),
+            ..Default::default()
         };
-        let (a, b) = evolve
-            .0
-            .best_genes_and_fitness_score()
-            .ok_or(anyhow!("Error when computing score"))?;
-
-        Ok(SolutionReturn {
-            chromosone: a,
-            errors: b as usize,
-        })
+        let solution = fitness.into_solution(generation_capped, stats);
+        reporter.finish(&solution);
+        solution
     }
 }
 
 pub struct Instance<T: Fitness<Genotype = ListGenotype<Value>>> {
-    func: T,
-    size: usize,
+    pub(crate) func: T,
+    pub(crate) size: usize,
 }
 
 impl<T: Fitness<Genotype = ListGenotype<Value>>> Instance<T> {
@@ -261,7 +456,7 @@ impl<T: Fitness<Genotype = ListGenotype<Value>>> Instance<T> {
 #[derive(Clone, Copy, Debug)]
 pub struct Build;
 
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug)]
 /// Parameter set of the hill climbing algorithm
 pub struct HillParamSet<T> {
     pub param_type: T,
@@ -272,6 +467,10 @@ pub struct HillParamSet<T> {
     pub population_size: usize,
     pub num_of_runs: usize,
     pub with_parallel: bool,
+    /// Objective replacing the one of the fitness, e.g. weighted gates or a graded violation
+    pub objective: Option<FitnessObjective>,
+    pub budget: Budget,
+    pub observer: Option<SharedObserver>,
 }
 
 impl Default for HillParamSet<Build> {
@@ -285,6 +484,9 @@ impl Default for HillParamSet<Build> {
             population_size: 250,
             num_of_runs: 15,
             with_parallel: false,
+            objective: None,
+            budget: Budget::default(),
+            observer: None,
         }
     }
 }
@@ -303,68 +505,89 @@ impl HillParamSet<Build> {
             population_size: self.population_size,
             num_of_runs: self.num_of_runs,
             with_parallel: self.with_parallel,
+            objective: self.objective.clone(),
+            budget: self.budget.clone(),
+            observer: self.observer.clone(),
         }
     }
 }
 
-impl<G: Fitness<Genotype = ListGenotype<Value>>> SolverTrait
-    for SolverStruct<HillClimbAlgorithm<G>>
-{
+impl<G: CircuitFitness> SolverTrait for SolverStruct<HillClimbAlgorithm<G>> {
     type ParamSet = HillParamSet<Instance<G>>;
 
     type Solution = SolutionReturn;
 
     // Hill Climbing algorithm setup and execution
-    fn find_solution(&self, param_set: Self::ParamSet) -> ARes<Self::Solution> {
+    fn find_solution(&self, param_set: Self::ParamSet) -> Result<Self::Solution, SolverError> {
+        let started = Instant::now();
         let genotype = ListGenotype::builder()
             .with_allele_list(Value::iter().collect_vec())
             .with_genes_size(param_set.param_type.size)
             .with_genes_hashing(param_set.gene_hashing)
             .build()
-            .map_err(|e| anyhow!(e.0))?;
+            .map_err(|e| SolverError::GenotypeBuild(e.0.into()))?;
 
+        let func = match param_set.objective {
+            Some(objective) => param_set.param_type.func.with_objective(objective),
+            None => param_set.param_type.func,
+        };
+        let fitness = BudgetFitness::new(func, &param_set.budget);
+        let reporter = ObserverReporter::new(param_set.observer, &fitness);
         let hill_climb = HillClimb::builder()
             .with_genotype(genotype.clone())
             .with_variant(param_set.hill_variant.0)
             .with_max_stale_generations(param_set.stale_generations)
-            .with_fitness(param_set.param_type.func)
+            .with_max_generations_option(param_set.budget.max_generations)
+            .with_fitness(fitness.clone())
             .with_fitness_cache(param_set.fitness_cache)
             .with_fitness_ordering(FitnessOrdering::Minimize)
-            .with_target_fitness_score(0);
+            .with_target_fitness_score(TARGET_SCORE)
+            .with_reporter(reporter.clone());
 
-        let hill_climb = if param_set.with_parallel {
+        let (best_run, runs) = if param_set.with_parallel {
             hill_climb
                 .with_par_fitness(true)
                 .call_par_repeatedly(50)
-                .map_err(|e| anyhow!(e.0))?
+                .map_err(|e| SolverError::StrategyBuild(e.0.into()))?
         } else {
             hill_climb
                 .with_par_fitness(false)
                 .call_repeatedly(50)
-                .map_err(|e| anyhow!(e.0))?
+                .map_err(|e| SolverError::StrategyBuild(e.0.into()))?
         };
 
-        let (a, b) = hill_climb
-            .0
-            .best_genes_and_fitness_score()
-            .ok_or(anyhow!("Error when computing score"))?;
-
-        Ok(SolutionReturn {
-            chromosone: a,
-            errors: b as usize,
-        })
+        let generation_capped = param_set
+            .budget
+            .max_generations
+            .is_some_and(|m| best_run.state.current_generation >= m);
+        let stats = SolveStats {
+            wall_time: started.elapsed(),
+            generations: runs
+                .iter()
+                .chain([&best_run])
+                .map(|run| run.state.current_generation)
+                .sum(),
+            cache_hits: best_run
+                .config
+                .fitness_cache
+                .as_ref()
+                .map_or(0, |cache| cache.hit_miss_stats().0),
+            ..Default::default()
+        };
+        let solution = fitness.into_solution(generation_capped, stats);
+        reporter.finish(&solution);
+        solution
     }
 }
 
 #[cfg(test)]
 mod evo_testers {
-    use std::fmt::Debug;
-
     use crate::{
         gates::{Gate, NodeUnitialised},
         graph::PureCircuitGraph,
+        solution_finders::base_finder::ViolationMeasure,
     };
-    use itertools::all;
+    use rstest::rstest;
 
     use super::*;
     fn setup_good_graph() -> PureCircuitGraph {
@@ -378,4 +601,176 @@ mod evo_testers {
         pc_resource.add_edge(gate_1, val_3, ()).unwrap();
         pc_resource
     }
+
+    fn hill_params(budget: Budget) -> HillParamSet<Instance<FitnessPureCircuit>> {
+        let pc = setup_good_graph();
+        let instance = Instance::new(pc.to_fitness_function().unwrap(), pc.count_values());
+        HillParamSet {
+            budget,
+            ..Default::default()
+        }
+        .build(instance)
+    }
+
+    fn evo_params(budget: Budget) -> EvoParamSet<Instance<FitnessPureCircuit>> {
+        let pc = setup_good_graph();
+        let instance = Instance::new(pc.to_fitness_function().unwrap(), pc.count_values());
+        EvoParamSet {
+            budget,
+            population_size: 20,
+            num_of_species: 2,
+            ..Default::default()
+        }
+        .build(instance)
+    }
+
+    #[test]
+    fn test_unlimited_budget() {
+        let sol = SolverHillClimb::default()
+            .find_solution(hill_params(Budget::default()))
+            .unwrap();
+        assert_eq!(sol.errors, 0);
+        assert!(!sol.interrupted);
+        assert!(sol.stats.fitness_evaluations > 0);
+
+        let sol = SolverEvo::default()
+            .find_solution(evo_params(Budget::default()))
+            .unwrap();
+        assert_eq!(sol.errors, 0);
+        assert!(!sol.interrupted);
+        assert!(sol.stats.fitness_evaluations > 0);
+    }
+
+    #[test]
+    fn test_minimising_bot() {
+        let pc = setup_good_graph();
+        let fitness = pc
+            .to_fitness_function()
+            .unwrap()
+            .minimising_bot(vec![1; pc.count_values()]);
+        let instance = || Instance::new(fitness.clone(), pc.count_values());
+
+        let sol = SolverHillClimb::default()
+            .find_solution(HillParamSet::default().build(instance()))
+            .unwrap();
+        assert_eq!(sol.errors, 0);
+        assert_eq!(fitness.bot_cost(&sol.chromosone), 0);
+
+        let sol = SolverEvo::default()
+            .find_solution(
+                EvoParamSet {
+                    population_size: 20,
+                    num_of_species: 2,
+                    ..Default::default()
+                }
+                .build(instance()),
+            )
+            .unwrap();
+        assert_eq!(sol.errors, 0);
+        assert_eq!(fitness.bot_cost(&sol.chromosone), 0);
+    }
+
+    #[rstest]
+    #[case(ViolationMeasure::Count)]
+    #[case(ViolationMeasure::PortChanges)]
+    #[case(ViolationMeasure::Voltage)]
+    fn test_objective(#[case] measure: ViolationMeasure) {
+        let objective = Some(FitnessObjective {
+            measure,
+            gate_weights: Some(vec![2]),
+        });
+        let sol = SolverHillClimb::default()
+            .find_solution(HillParamSet {
+                objective: objective.clone(),
+                ..hill_params(Budget::default())
+            })
+            .unwrap();
+        // Errors count the violated gates, whatever the objective scores
+        assert_eq!(sol.errors, 0);
+        assert!(!sol.interrupted);
+
+        let sol = SolverEvo::default()
+            .find_solution(EvoParamSet {
+                objective,
+                ..evo_params(Budget::default())
+            })
+            .unwrap();
+        assert_eq!(sol.errors, 0);
+        assert!(!sol.interrupted);
+    }
+
+    #[test]
+    fn test_cancelled_before_start() {
+        let budget = Budget::default();
+        budget.cancellation.cancel();
+        assert_eq!(
+            SolverHillClimb::default()
+                .find_solution(hill_params(budget.clone()))
+                .unwrap_err(),
+            SolverError::Cancelled
+        );
+        assert_eq!(
+            SolverEvo::default()
+                .find_solution(evo_params(budget))
+                .unwrap_err(),
+            SolverError::Cancelled
+        );
+    }
+
+    #[test]
+    fn test_node_limit() {
+        let fitness = setup_good_graph().to_fitness_function().unwrap();
+        let budget = Budget {
+            max_nodes: Some(1),
+            ..Default::default()
+        };
+        for sol in [
+            SolverHillClimb::default().find_solution(hill_params(budget.clone())),
+            SolverEvo::default().find_solution(evo_params(budget.clone())),
+        ] {
+            let sol = sol.unwrap();
+            // The single evaluated chromosome is returned with its real score.
+            // The run only finishes on its own if that chromosome is already a solution
+            assert_eq!(fitness.evaluate(&sol.chromosone), Ok(sol.errors));
+            assert!(sol.interrupted || sol.errors == 0);
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        use crate::solution_finders::observer::ChannelObserver;
+
+        for hill in [true, false] {
+            let (observer, receiver) = ChannelObserver::new();
+            let sol = if hill {
+                SolverHillClimb::default().find_solution(HillParamSet {
+                    observer: Some(observer.shared()),
+                    ..hill_params(Budget::default())
+                })
+            } else {
+                SolverEvo::default().find_solution(EvoParamSet {
+                    observer: Some(observer.shared()),
+                    ..evo_params(Budget::default())
+                })
+            }
+            .unwrap();
+            let events = receiver.try_iter().collect_vec();
+            assert_eq!(
+                events.last(),
+                Some(&SolverEvent::Finished {
+                    interrupted: sol.interrupted
+                })
+            );
+            let scores = events
+                .iter()
+                .filter_map(|e| match e {
+                    SolverEvent::Generation { best_fitness, .. } => Some(*best_fitness),
+                    _ => None,
+                })
+                .collect_vec();
+            // The best score never gets worse, even across runs
+            assert!(scores.iter().all(Option::is_some));
+            assert!(scores.windows(2).all(|w| w[0] >= w[1]));
+        }
+    }
 }
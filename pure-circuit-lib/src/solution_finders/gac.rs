@@ -0,0 +1,356 @@
+use std::collections::VecDeque;
+
+use itertools::Itertools;
+use petgraph::graph::NodeIndex;
+
+use crate::{
+    gates::Gate,
+    graph::PureCircuitGraph,
+    solution_finders::{
+        backtracking::{BitString, CircuitTable, SearchState},
+        solver_error::SolverError,
+    },
+};
+
+/// Domains of the value nodes of the circuit before any reduction, indexed like the solutions.
+/// Gate indexes hold `None`
+pub fn full_domains<T, G>(pc_instance: &PureCircuitGraph<T, G>) -> Vec<Option<BitString>> {
+    pc_instance
+        .extract_node_graph()
+        .into_iter()
+        .map(|n| n.map(|_| BitString::all()))
+        .collect()
+}
+
+/// Generalised arc consistency over the gate relations.
+///
+/// Removes from the domain of every port the values that no tuple of the gate relation supports
+/// given the domains of the other ports, until no domain changes. The reduced domains keep every
+/// solution of the circuit, hence they can restrict any solver beforehand.
+///
+/// # Parameters
+/// * `domains` - Domain of each value node, indexed like the solutions (see `full_domains`)
+///
+/// # Return
+/// Ok(Some(*)): Reduced domains
+/// Ok(None): A domain became empty, the circuit has no solution within `domains`
+///
+/// # Errors
+/// * InvalidArity: Exists gate with invalid arity
+/// * InvalidAssignmentLength: `domains` does not cover every value node
+/// * MissingAssignment: Value node connected to a gate has no domain
+///
+/// # Example
+/// ```
+/// use pure_circuit_lib::graph::PureCircuitGraph;
+/// use pure_circuit_lib::gates::{Gate, NodeUnitialised, Value};
+/// use pure_circuit_lib::solution_finders::backtracking::BitString;
+/// use pure_circuit_lib::solution_finders::gac::{full_domains, propagate};
+///
+/// let mut pc = PureCircuitGraph::<(), ()>::default();
+/// let a = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+/// let b = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+/// let g = pc.add_node(NodeUnitialised::from_gate(Gate::And), ());
+/// let out = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+/// pc.add_edge(a, g, ()).unwrap();
+/// pc.add_edge(b, g, ()).unwrap();
+/// pc.add_edge(g, out, ()).unwrap();
+///
+/// let mut domains = full_domains(&pc);
+/// domains[out.index()] = Some(Value::One.into());
+/// let domains = propagate(&pc, domains).unwrap().unwrap();
+/// assert_eq!(domains[a.index()], Some(BitString::from(Value::One)));
+/// assert_eq!(domains[b.index()], Some(BitString::from(Value::One)));
+/// ```
+pub fn propagate<T, G>(
+    pc_instance: &PureCircuitGraph<T, G>,
+    mut domains: Vec<Option<BitString>>,
+) -> Result<Option<Vec<Option<BitString>>>, SolverError> {
+    let (table, _) = CircuitTable::new(pc_instance)?;
+    if domains.len() < table.adjacency.len() {
+        return Err(SolverError::InvalidAssignmentLength {
+            expected: table.adjacency.len(),
+            found: domains.len(),
+        });
+    }
+    if domains.iter().flatten().any(|d| d.is_empty()) {
+        return Ok(None);
+    }
+    Ok(table.arc_consistency(&mut domains)?.then_some(domains))
+}
+
+impl CircuitTable {
+    /// Reduce `domains` to their arc consistent fixpoint.
+    /// Returns false once a domain becomes empty, the domains are left partially reduced then
+    pub(crate) fn arc_consistency(
+        &self,
+        domains: &mut [Option<BitString>],
+    ) -> Result<bool, SolverError> {
+        let mut worklist = (0..self.gates.len()).collect::<VecDeque<_>>();
+        let mut queued = vec![true; self.gates.len()];
+        while let Some(pos) = worklist.pop_front() {
+            queued[pos] = false;
+            let (gate, ins, outs) = self.gates[pos];
+            let ins = ins.into_iter().flatten().collect_vec();
+            let outs = outs.into_iter().flatten().collect_vec();
+            for (node, supported) in supports(gate, &ins, &outs, domains)? {
+                if domains[node] == Some(supported) {
+                    continue;
+                }
+                domains[node] = Some(supported);
+                if supported.is_empty() {
+                    return Ok(false);
+                }
+                for other in self.adjacency[node].iter().copied() {
+                    if other != pos && !queued[other] {
+                        queued[other] = true;
+                        worklist.push_back(other);
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl SearchState {
+    /// Reduce the domains of the root state to their arc consistent fixpoint.
+    /// The reductions hold in every branch, hence they are recorded without a reason.
+    /// Returns false if the circuit has no solution
+    pub(crate) fn arc_consistency(&mut self, table: &CircuitTable) -> Result<bool, SolverError> {
+        let mut domains = self.value_map.clone();
+        if !table.arc_consistency(&mut domains)? {
+            return Ok(false);
+        }
+        for (indx, domain) in domains.into_iter().enumerate() {
+            let Some(domain) = domain.filter(|d| self.value_map[indx] != Some(*d)) else {
+                continue;
+            };
+            self.value_map[indx] = Some(domain);
+            self.queue.change_priority_by(&NodeIndex::new(indx), |f| {
+                f.value_len = domain.len();
+            });
+        }
+        Ok(true)
+    }
+}
+
+/// Values of each port of `gate` that belong to a tuple of its relation within `domains`.
+/// A node connected to several ports of the gate takes the same value in all of them
+fn supports(
+    gate: Gate,
+    ins: &[usize],
+    outs: &[usize],
+    domains: &[Option<BitString>],
+) -> Result<Vec<(usize, BitString)>, SolverError> {
+    let nodes = ins.iter().chain(outs).copied().unique().collect_vec();
+    let sets = nodes
+        .iter()
+        .map(|n| {
+            domains[*n].ok_or(SolverError::MissingAssignment {
+                node_index: NodeIndex::new(*n),
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let position = |n: &usize| nodes.iter().position(|m| m == n).unwrap_or_default();
+    let (ins, outs) = (
+        ins.iter().map(position).collect_vec(),
+        outs.iter().map(position).collect_vec(),
+    );
+    let mut supported = vec![BitString::default(); nodes.len()];
+    let mut in_vals = Vec::with_capacity(ins.len());
+    let mut out_vals = Vec::with_capacity(outs.len());
+    for tuple in sets
+        .iter()
+        .map(|s| s.to_value_iter().collect_vec())
+        .multi_cartesian_product()
+    {
+        in_vals.clear();
+        in_vals.extend(ins.iter().map(|p| tuple[*p]));
+        out_vals.clear();
+        out_vals.extend(outs.iter().map(|p| tuple[*p]));
+        if gate.check(&in_vals, &out_vals)? {
+            for (set, value) in supported.iter_mut().zip(&tuple) {
+                *set = set.insert(*value);
+            }
+        }
+    }
+    Ok(nodes.into_iter().zip(supported).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        gates::{NodeUnitialised, Value},
+        solution_finders::backtracking::BacktrackAlgorithm,
+        strategies::{CircuitParams, circuit_strategy},
+    };
+    use proptest::prelude::*;
+    use rstest::rstest;
+    use std::collections::HashSet;
+
+    fn single_gate(gate: Gate) -> (PureCircuitGraph, Vec<NodeIndex>, Vec<NodeIndex>) {
+        let mut pc = PureCircuitGraph::new();
+        let g = pc.add_node(NodeUnitialised::from_gate(gate), ());
+        let (ins, outs) = gate.arity();
+        let ins = (0..ins)
+            .map(|_| {
+                let n = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+                pc.add_edge(n, g, ()).unwrap();
+                n
+            })
+            .collect();
+        let outs = (0..outs)
+            .map(|_| {
+                let n = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+                pc.add_edge(g, n, ()).unwrap();
+                n
+            })
+            .collect();
+        (pc, ins, outs)
+    }
+
+    #[rstest]
+    #[case(Gate::And, Value::Zero, BitString::all())]
+    #[case(Gate::And, Value::Bot, BitString::from(Value::Bot).insert(Value::One))]
+    #[case(Gate::Or, Value::One, BitString::all())]
+    #[case(Gate::Nor, Value::One, BitString::from(Value::Zero))]
+    #[case(Gate::Nand, Value::Bot, BitString::from(Value::Bot).insert(Value::One))]
+    fn test_output_restricts_inputs(
+        #[case] gate: Gate,
+        #[case] out: Value,
+        #[case] expected: BitString,
+    ) {
+        let (pc, ins, outs) = single_gate(gate);
+        let mut domains = full_domains(&pc);
+        domains[outs[0].index()] = Some(out.into());
+        let domains = propagate(&pc, domains).unwrap().unwrap();
+        for n in ins {
+            assert_eq!(domains[n.index()], Some(expected));
+        }
+    }
+
+    #[test]
+    fn test_partial_domains() {
+        // The assigned ports alone do not restrict anything, the partial domains do
+        let (pc, ins, outs) = single_gate(Gate::And);
+        let mut domains = full_domains(&pc);
+        domains[ins[0].index()] = Some(BitString::from(Value::Bot).insert(Value::One));
+        domains[ins[1].index()] = Some(BitString::from(Value::Bot).insert(Value::One));
+        let domains = propagate(&pc, domains).unwrap().unwrap();
+        assert_eq!(
+            domains[outs[0].index()],
+            Some(BitString::from(Value::Bot).insert(Value::One))
+        );
+    }
+
+    #[test]
+    fn test_purify() {
+        let (pc, ins, outs) = single_gate(Gate::Purify);
+        let mut domains = full_domains(&pc);
+        domains[outs[0].index()] = Some(Value::Bot.into());
+        let domains = propagate(&pc, domains).unwrap().unwrap();
+        assert_eq!(domains[ins[0].index()], Some(Value::Bot.into()));
+        assert_eq!(domains[outs[1].index()], Some(Value::One.into()));
+    }
+
+    #[test]
+    fn test_chain_fixpoint() {
+        // Not gates alternate the value along the chain, from both ends
+        let mut pc = PureCircuitGraph::<(), ()>::new();
+        let first = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+        let mut nodes = vec![first];
+        for _ in 0..10 {
+            let next = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+            let g = pc.add_node(NodeUnitialised::from_gate(Gate::Not), ());
+            pc.add_edge(*nodes.last().unwrap(), g, ()).unwrap();
+            pc.add_edge(g, next, ()).unwrap();
+            nodes.push(next);
+        }
+        let mut domains = full_domains(&pc);
+        domains[nodes[10].index()] = Some(Value::One.into());
+        let domains = propagate(&pc, domains).unwrap().unwrap();
+        for (i, n) in nodes.iter().enumerate() {
+            let expected = if i % 2 == 0 { Value::One } else { Value::Zero };
+            assert_eq!(domains[n.index()], Some(expected.into()));
+        }
+    }
+
+    #[test]
+    fn test_self_loop() {
+        let mut pc = PureCircuitGraph::<(), ()>::new();
+        let n = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+        let g = pc.add_node(NodeUnitialised::from_gate(Gate::Not), ());
+        pc.add_edge(n, g, ()).unwrap();
+        pc.add_edge(g, n, ()).unwrap();
+        let domains = propagate(&pc, full_domains(&pc)).unwrap().unwrap();
+        assert_eq!(domains[n.index()], Some(Value::Bot.into()));
+
+        let mut domains = full_domains(&pc);
+        domains[n.index()] = Some(BitString::from(Value::Zero).insert(Value::One));
+        assert_eq!(propagate(&pc, domains).unwrap(), None);
+    }
+
+    #[test]
+    fn test_invalid_domains() {
+        let (pc, ins, _) = single_gate(Gate::Copy);
+        assert_eq!(
+            propagate(&pc, vec![]),
+            Err(SolverError::InvalidAssignmentLength {
+                expected: 3,
+                found: 0
+            })
+        );
+        let mut domains = full_domains(&pc);
+        domains[ins[0].index()] = None;
+        assert_eq!(
+            propagate(&pc, domains),
+            Err(SolverError::MissingAssignment { node_index: ins[0] })
+        );
+    }
+
+    #[test]
+    fn test_consistent_solutions() {
+        let mut pc = PureCircuitGraph::<(), ()>::new();
+        let n = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+        let g = pc.add_node(NodeUnitialised::from_gate(Gate::Not), ());
+        pc.add_edge(n, g, ()).unwrap();
+        pc.add_edge(g, n, ()).unwrap();
+        let mut search = BacktrackAlgorithm.consistent_solutions(&pc).unwrap();
+        assert_eq!(
+            search.by_ref().collect::<Result<Vec<_>, _>>().unwrap(),
+            vec![vec![Some(Value::Bot)]]
+        );
+        // The only value left is tried without any conflict
+        assert_eq!(search.stats().decisions, 1);
+        assert_eq!(search.stats().conflicts, 0);
+    }
+
+    proptest! {
+        #[test]
+        fn keeps_solutions(
+            pc in circuit_strategy(CircuitParams::new(1..=8, 1..=10)),
+        ) {
+            let solutions = BacktrackAlgorithm.calculate(&pc).unwrap();
+            let domains = propagate(&pc, full_domains(&pc)).unwrap();
+            let Some(domains) = domains else {
+                prop_assert!(solutions.is_empty());
+                return Ok(());
+            };
+            for solution in &solutions {
+                for (value, domain) in solution.iter().zip(&domains) {
+                    if let Some(value) = value {
+                        prop_assert!(!domain.unwrap().op_inter((*value).into()).is_empty());
+                    }
+                }
+            }
+            let consistent = BacktrackAlgorithm
+                .consistent_solutions(&pc)
+                .unwrap()
+                .collect::<Result<HashSet<_>, _>>()
+                .unwrap();
+            prop_assert_eq!(consistent, solutions.into_iter().collect::<HashSet<_>>());
+        }
+    }
+}
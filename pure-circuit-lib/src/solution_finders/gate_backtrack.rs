@@ -1,8 +1,7 @@
 use crate::{
     gates::{Gate, Value, VoltageOrdering},
-    solution_finders::backtracking::BitString,
+    solution_finders::{backtracking::BitString, solver_error::SolverError},
 };
-use anyhow::{Result as ARes, anyhow};
 
 use crate::solution_finders::base_finder::MAX_DEGREE;
 
@@ -17,13 +16,12 @@ impl Gate {
     /// Conditional assigned inputs and outputs
     /// # Output
     /// Ok(.) => Tuple of the valid input/output sets for the remaining values
-    /// Err(.) => Invalid assignments
+    /// Err(.) => `SolverError::InconsistentAssignment` on invalid assignments
     pub(crate) fn set_value(
         &self,
         assingment_input: [Option<Value>; MAX_DEGREE],
         assignment_output: [Option<Value>; MAX_DEGREE],
-    ) -> ARes<BitStringIOState> {
-        #[allow(unreachable_code)]
+    ) -> Result<BitStringIOState, SolverError> {
         match self {
             Self::Not => match (assingment_input, assignment_output) {
                 ([Some(v), _], [None, _]) => Ok((
@@ -37,7 +35,7 @@ impl Gate {
                 ([Some(a), _], [Some(b), _]) if a == b.inverse() => {
                     Ok(([Some(a.into()), None], [Some(b.into()), None]))
                 }
-                _ => ARes::Err(anyhow!("Incorrect assingment for {self}")),
+                _ => Err(self.inconsistent()),
             },
             Self::Copy => match (assingment_input, assignment_output) {
                 ([Some(v), _], [None, _]) | ([None, _], [Some(v), _]) => Ok((
@@ -47,7 +45,7 @@ impl Gate {
                 ([Some(a), _], [Some(b), _]) if a == b => {
                     Ok(([Some(a.into()), None], [Some(b.into()), None]))
                 }
-                _ => ARes::Err(anyhow!("Incorrect assingment for {self}")),
+                _ => Err(self.inconsistent()),
             },
             Self::And => match (assingment_input, assignment_output) {
                 ([None, None], [Some(v), _]) => Ok((
@@ -100,7 +98,7 @@ impl Gate {
                 {
                     Ok(([Some(a.into()), Some(b.into())], [Some(v.into()), None]))
                 }
-                _ => ARes::Err(anyhow!("Incorrect assingment for {self}")),
+                _ => Err(self.inconsistent()),
             },
             Self::Or => match (assingment_input, assignment_output) {
                 ([None, None], [Some(v), _]) => Ok((
@@ -153,7 +151,7 @@ impl Gate {
                 {
                     Ok(([Some(a.into()), Some(b.into())], [Some(v.into()), None]))
                 }
-                _ => ARes::Err(anyhow!("Incorrect assingment for {self}")),
+                _ => Err(self.inconsistent()),
             },
             Self::Nor => {
                 let (ins, outs) = Gate::And.set_value(
@@ -266,10 +264,15 @@ impl Gate {
                 {
                     Ok(([Some(a.into()), None], [Some(b.into()), Some(c.into())]))
                 }
-                _ => ARes::Err(anyhow!("Incorrect assingment for {self}")),
+                _ => Err(self.inconsistent()),
             },
-            #[allow(unreachable_patterns)]
-            _ => unimplemented!("Gate {self} was not implemented"),
+        }
+    }
+
+    fn inconsistent(self) -> SolverError {
+        SolverError::InconsistentAssignment {
+            gate: self,
+            gate_index: None,
         }
     }
 }
@@ -334,7 +337,7 @@ mod tests {
     }
 
     mod gate_evals {
-        use crate::test_utils::enum_strategy;
+        use crate::strategies::enum_strategy;
 
         use rstest::rstest;
 
@@ -469,7 +472,7 @@ mod tests {
                         .unwrap();
                     let expected = (
                         shift(
-                            [Some(BitString::greater_than(s1.into())), Some(s1.into())],
+                            [Some(BitString::greater_than(s1)), Some(s1.into())],
                             index,
                         ),
                         [Some(s1.into()), None],
@@ -590,7 +593,7 @@ mod tests {
                         .unwrap();
                     let expected = (
                         shift(
-                            [Some(BitString::smaller_than(s1.into())), Some(s1.into())],
+                            [Some(BitString::smaller_than(s1)), Some(s1.into())],
                             index,
                         ),
                         [Some(s1.into()), None],
@@ -681,7 +684,7 @@ mod tests {
                         .unwrap();
                     let expected = (
                         shift(
-                            [Some(BitString::smaller_than(s1.into())), Some(s1.into())],
+                            [Some(BitString::smaller_than(s1)), Some(s1.into())],
                             index,
                         ),
                         [Some(s1.inverse().into()), None],
@@ -773,7 +776,7 @@ mod tests {
                         .unwrap();
                     let expected = (
                         shift(
-                            [Some(BitString::greater_than(s1.into())), Some(s1.into())],
+                            [Some(BitString::greater_than(s1)), Some(s1.into())],
                             index,
                         ),
                         [Some(s1.inverse().into()), None],
@@ -0,0 +1,570 @@
+use std::fmt::Debug;
+
+use itertools::Itertools;
+use petgraph::graph::NodeIndex;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::{
+    gates::Value,
+    solution_finders::backtracking::{BitString, CircuitTable, SearchState},
+};
+
+/// Values in the order `BitString::to_value_iter` yields them
+const DEFAULT_VALUE_ORDER: [Value; 3] = [Value::Zero, Value::Bot, Value::One];
+
+/// `order` without repeated values, followed by the values it misses in the default order.
+/// Hence every value is tried exactly once, whatever order a heuristic returns
+pub(crate) fn complete_order(order: [Value; 3]) -> [Value; 3] {
+    let mut complete = DEFAULT_VALUE_ORDER;
+    let mut len = 0;
+    for value in order.into_iter().chain(DEFAULT_VALUE_ORDER) {
+        if !complete[..len].contains(&value) {
+            complete[len] = value;
+            len += 1;
+        }
+    }
+    complete
+}
+
+/// Variable and value ordering of the backtracker, set with `SolutionIter::with_heuristic`.
+///
+/// The heuristic is consulted at every branching point. Unit propagation runs before, hence
+/// every candidate has at least two values left. Heuristics learning from the search (e.g.
+/// `DomWdeg`, `Activity`) are told about every conflict and restart.
+pub trait BranchingHeuristic: Debug + Send + Sync {
+    /// Node to branch on among `view.candidates()`.
+    /// None, or a node that is not a candidate, falls back to `DomainDegree`
+    fn select_node(&mut self, view: &BranchView) -> Option<NodeIndex>;
+
+    /// Order the values of `node` are tried in. Values missing from its domain are skipped.
+    /// Repeated values are tried once, and values left out are tried last in the default order
+    fn value_order(&mut self, _node: NodeIndex, _view: &BranchView) -> [Value; 3] {
+        DEFAULT_VALUE_ORDER
+    }
+
+    /// Unit propagation failed. `nodes` are the assigned nodes of the conflict and `gate` the
+    /// violated gate, if a gate rather than a learned nogood failed
+    fn on_conflict(&mut self, _nodes: &[usize], _gate: Option<usize>, _view: &BranchView) {}
+
+    /// The search restarted from the root
+    fn on_restart(&mut self) {}
+
+    fn clone_box(&self) -> Box<dyn BranchingHeuristic>;
+}
+
+impl Clone for Box<dyn BranchingHeuristic> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+impl BranchingHeuristic for Box<dyn BranchingHeuristic> {
+    fn select_node(&mut self, view: &BranchView) -> Option<NodeIndex> {
+        self.as_mut().select_node(view)
+    }
+
+    fn value_order(&mut self, node: NodeIndex, view: &BranchView) -> [Value; 3] {
+        self.as_mut().value_order(node, view)
+    }
+
+    fn on_conflict(&mut self, nodes: &[usize], gate: Option<usize>, view: &BranchView) {
+        self.as_mut().on_conflict(nodes, gate, view);
+    }
+
+    fn on_restart(&mut self) {
+        self.as_mut().on_restart();
+    }
+
+    fn clone_box(&self) -> Box<dyn BranchingHeuristic> {
+        self.as_ref().clone_box()
+    }
+}
+
+/// Read-only view of a search branch handed to the heuristics.
+/// Nodes and gates are indexed like the solutions and `CircuitTable::gates` respectively
+#[derive(Debug, Clone, Copy)]
+pub struct BranchView<'a> {
+    table: &'a CircuitTable,
+    state: &'a SearchState,
+}
+
+impl<'a> BranchView<'a> {
+    pub(crate) fn new(table: &'a CircuitTable, state: &'a SearchState) -> Self {
+        Self { table, state }
+    }
+
+    /// Unassigned value nodes the search can branch on
+    pub fn candidates(&self) -> impl Iterator<Item = NodeIndex> + 'a {
+        self.state.queue.iter().map(|(n, _)| *n)
+    }
+
+    /// Choice of `DomainDegree`: smallest domain, then in-degree zero, then most neighbours
+    pub fn default_choice(&self) -> Option<NodeIndex> {
+        self.state.queue.peek().map(|(n, _)| *n)
+    }
+
+    /// Candidates tied with `default_choice`, ordered by index
+    pub fn ties(&self) -> Vec<NodeIndex> {
+        let Some((_, best)) = self.state.queue.peek() else {
+            return vec![];
+        };
+        self.state
+            .queue
+            .iter()
+            .filter(|(_, k)| *k == best)
+            .map(|(n, _)| *n)
+            .sorted()
+            .collect()
+    }
+
+    /// Values left for `node`, None if it is not a value node
+    pub fn domain(&self, node: usize) -> Option<BitString> {
+        self.state.value_map.get(node).copied().flatten()
+    }
+
+    pub fn is_assigned(&self, node: usize) -> bool {
+        self.state.sol_map.get(node).is_some_and(Option::is_some)
+    }
+
+    /// Gates connected to `node`
+    pub fn gates(&self, node: usize) -> &'a [usize] {
+        self.table.adjacency.get(node).map_or(&[], |g| g)
+    }
+
+    /// Value nodes connected to the ports of `gate`
+    pub fn ports(&self, gate: usize) -> impl Iterator<Item = usize> + 'a {
+        self.table
+            .gates
+            .get(gate)
+            .into_iter()
+            .flat_map(|(_, ins, outs)| ins.iter().chain(outs.iter()).flatten().copied())
+    }
+
+    /// Number of gates in the circuit
+    pub fn gate_count(&self) -> usize {
+        self.table.gates.len()
+    }
+}
+
+/// Default ordering: smallest domain, then in-degree zero, then most neighbours.
+/// Values are tried in the order Zero, Bot, One
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DomainDegree;
+
+impl BranchingHeuristic for DomainDegree {
+    fn select_node(&mut self, view: &BranchView) -> Option<NodeIndex> {
+        view.default_choice()
+    }
+
+    fn clone_box(&self) -> Box<dyn BranchingHeuristic> {
+        Box::new(*self)
+    }
+}
+
+/// Smallest ratio of domain size to weighted degree (dom/wdeg).
+///
+/// Every gate starts with weight one, which grows each time the gate is violated. The weighted
+/// degree of a node sums the weights of its gates that still have another unassigned port.
+#[derive(Debug, Clone, Default)]
+pub struct DomWdeg {
+    weights: Vec<usize>,
+}
+
+impl DomWdeg {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn weight(&self, gate: usize) -> usize {
+        self.weights.get(gate).copied().unwrap_or(1)
+    }
+
+    fn wdeg(&self, node: usize, view: &BranchView) -> usize {
+        view.gates(node)
+            .iter()
+            .filter(|g| view.ports(**g).any(|p| p != node && !view.is_assigned(p)))
+            .map(|g| self.weight(*g))
+            .sum()
+    }
+}
+
+impl BranchingHeuristic for DomWdeg {
+    fn select_node(&mut self, view: &BranchView) -> Option<NodeIndex> {
+        let score = |n: NodeIndex| {
+            let dom = view.domain(n.index()).map_or(0, BitString::len);
+            (dom, self.wdeg(n.index(), view))
+        };
+        // dom_a / wdeg_a < dom_b / wdeg_b, a node without weighted degree goes last
+        view.candidates()
+            .map(|n| (n, score(n)))
+            .min_by(|(na, (da, wa)), (nb, (db, wb))| {
+                (*wa == 0)
+                    .cmp(&(*wb == 0))
+                    .then((da * wb).cmp(&(db * wa)))
+                    .then(da.cmp(db))
+                    .then(na.cmp(nb))
+            })
+            .map(|(n, _)| n)
+    }
+
+    fn on_conflict(&mut self, _nodes: &[usize], gate: Option<usize>, view: &BranchView) {
+        let Some(gate) = gate else {
+            return;
+        };
+        if self.weights.len() <= gate {
+            self.weights.resize(view.gate_count().max(gate + 1), 1);
+        }
+        self.weights[gate] += 1;
+    }
+
+    fn clone_box(&self) -> Box<dyn BranchingHeuristic> {
+        Box::new(self.clone())
+    }
+}
+
+/// Activities past this bound are rescaled to avoid overflowing
+const ACTIVITY_LIMIT: f64 = 1e100;
+
+/// Most active node first, ties broken by the smallest domain.
+///
+/// Every node of a conflict gains activity. The gain grows geometrically by `1 / decay`, so
+/// older conflicts matter less than recent ones.
+#[derive(Debug, Clone)]
+pub struct Activity {
+    activity: Vec<f64>,
+    increment: f64,
+    decay: f64,
+}
+
+impl Activity {
+    /// * `decay` - Factor in `(0, 1]` applied to the activity of past conflicts
+    pub fn new(decay: f64) -> Self {
+        Self {
+            activity: vec![],
+            increment: 1.0,
+            decay: decay.clamp(f64::EPSILON, 1.0),
+        }
+    }
+
+    fn activity(&self, node: usize) -> f64 {
+        self.activity.get(node).copied().unwrap_or_default()
+    }
+}
+
+impl Default for Activity {
+    fn default() -> Self {
+        Self::new(0.95)
+    }
+}
+
+impl BranchingHeuristic for Activity {
+    fn select_node(&mut self, view: &BranchView) -> Option<NodeIndex> {
+        view.candidates().min_by(|a, b| {
+            self.activity(b.index())
+                .total_cmp(&self.activity(a.index()))
+                .then_with(|| {
+                    let dom = |n: &NodeIndex| view.domain(n.index()).map_or(0, BitString::len);
+                    dom(a).cmp(&dom(b))
+                })
+                .then(a.cmp(b))
+        })
+    }
+
+    fn on_conflict(&mut self, nodes: &[usize], _gate: Option<usize>, _view: &BranchView) {
+        for n in nodes {
+            if self.activity.len() <= *n {
+                self.activity.resize(n + 1, 0.0);
+            }
+            self.activity[*n] += self.increment;
+        }
+        self.increment /= self.decay;
+        if self.increment > ACTIVITY_LIMIT || self.activity.iter().any(|a| *a > ACTIVITY_LIMIT) {
+            self.activity.iter_mut().for_each(|a| *a /= ACTIVITY_LIMIT);
+            self.increment /= ACTIVITY_LIMIT;
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn BranchingHeuristic> {
+        Box::new(self.clone())
+    }
+}
+
+/// `DomainDegree`, breaking its ties uniformly at random.
+/// The same seed reproduces the same search
+#[derive(Debug, Clone)]
+pub struct RandomTies {
+    rng: StdRng,
+}
+
+impl RandomTies {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl BranchingHeuristic for RandomTies {
+    fn select_node(&mut self, view: &BranchView) -> Option<NodeIndex> {
+        let ties = view.ties();
+        if ties.is_empty() {
+            return None;
+        }
+        Some(ties[self.rng.random_range(0..ties.len())])
+    }
+
+    fn clone_box(&self) -> Box<dyn BranchingHeuristic> {
+        Box::new(self.clone())
+    }
+}
+
+/// Branch like the inner heuristic, trying ⊥ after the pure values
+#[derive(Debug, Clone, Default)]
+pub struct BotLast<H = DomainDegree>(pub H);
+
+impl<H: BranchingHeuristic + Clone + 'static> BranchingHeuristic for BotLast<H> {
+    fn select_node(&mut self, view: &BranchView) -> Option<NodeIndex> {
+        self.0.select_node(view)
+    }
+
+    fn value_order(&mut self, node: NodeIndex, view: &BranchView) -> [Value; 3] {
+        let mut order = self.0.value_order(node, view);
+        if let Some(pos) = order.iter().position(|v| *v == Value::Bot) {
+            order[pos..].rotate_left(1);
+        }
+        order
+    }
+
+    fn on_conflict(&mut self, nodes: &[usize], gate: Option<usize>, view: &BranchView) {
+        self.0.on_conflict(nodes, gate, view);
+    }
+
+    fn on_restart(&mut self) {
+        self.0.on_restart();
+    }
+
+    fn clone_box(&self) -> Box<dyn BranchingHeuristic> {
+        Box::new(self.clone())
+    }
+}
+
+/// Switch to the next heuristic at every restart (see `SolutionIter::with_restarts`).
+/// Every heuristic is told about the conflicts, so each one keeps learning while inactive
+#[derive(Debug, Clone)]
+pub struct Portfolio {
+    heuristics: Vec<Box<dyn BranchingHeuristic>>,
+    current: usize,
+}
+
+impl Portfolio {
+    pub fn new(heuristics: Vec<Box<dyn BranchingHeuristic>>) -> Self {
+        Self {
+            heuristics,
+            current: 0,
+        }
+    }
+}
+
+impl BranchingHeuristic for Portfolio {
+    fn select_node(&mut self, view: &BranchView) -> Option<NodeIndex> {
+        self.heuristics.get_mut(self.current)?.select_node(view)
+    }
+
+    fn value_order(&mut self, node: NodeIndex, view: &BranchView) -> [Value; 3] {
+        self.heuristics
+            .get_mut(self.current)
+            .map_or(DEFAULT_VALUE_ORDER, |h| h.value_order(node, view))
+    }
+
+    fn on_conflict(&mut self, nodes: &[usize], gate: Option<usize>, view: &BranchView) {
+        for h in self.heuristics.iter_mut() {
+            h.on_conflict(nodes, gate, view);
+        }
+    }
+
+    fn on_restart(&mut self) {
+        for h in self.heuristics.iter_mut() {
+            h.on_restart();
+        }
+        self.current = (self.current + 1) % self.heuristics.len().max(1);
+    }
+
+    fn clone_box(&self) -> Box<dyn BranchingHeuristic> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::copy_pairs,
+        gates::{Gate, NodeUnitialised},
+        graph::PureCircuitGraph,
+        solution_finders::{backtracking::BacktrackAlgorithm, solver_error::SolverError},
+        strategies::{CircuitParams, circuit_strategy},
+    };
+    use proptest::prelude::*;
+    use std::collections::HashSet;
+
+    /// Returns an order that repeats Zero and leaves out Bot
+    #[derive(Debug, Clone)]
+    struct Repeating;
+
+    impl BranchingHeuristic for Repeating {
+        fn select_node(&mut self, _view: &BranchView) -> Option<NodeIndex> {
+            None
+        }
+
+        fn value_order(&mut self, _node: NodeIndex, _view: &BranchView) -> [Value; 3] {
+            [Value::Zero, Value::Zero, Value::One]
+        }
+
+        fn clone_box(&self) -> Box<dyn BranchingHeuristic> {
+            Box::new(self.clone())
+        }
+    }
+
+    fn heuristics() -> Vec<Box<dyn BranchingHeuristic>> {
+        vec![
+            Box::new(Repeating),
+            Box::new(DomainDegree),
+            Box::new(DomWdeg::new()),
+            Box::new(Activity::default()),
+            Box::new(RandomTies::new(7)),
+            Box::new(BotLast(DomWdeg::new())),
+            Box::new(Portfolio::new(vec![
+                Box::new(RandomTies::new(3)),
+                Box::new(BotLast(DomainDegree)),
+            ])),
+        ]
+    }
+
+    fn collect(
+        search: impl Iterator<Item = Result<Vec<Option<Value>>, SolverError>>,
+    ) -> Vec<Vec<Option<Value>>> {
+        search.collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn test_bot_last() {
+        let search = BacktrackAlgorithm
+            .solutions(&copy_pairs(1))
+            .unwrap()
+            .with_heuristic(BotLast(DomainDegree));
+        assert_eq!(
+            collect(search),
+            vec![
+                vec![Some(Value::Zero), Some(Value::Zero)],
+                vec![Some(Value::One), Some(Value::One)],
+                vec![Some(Value::Bot), Some(Value::Bot)],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_complete_order() {
+        assert_eq!(
+            complete_order([Value::Zero, Value::Zero, Value::One]),
+            [Value::Zero, Value::One, Value::Bot]
+        );
+        assert_eq!(
+            complete_order([Value::One, Value::One, Value::One]),
+            [Value::One, Value::Zero, Value::Bot]
+        );
+        assert_eq!(
+            complete_order([Value::Bot, Value::One, Value::Zero]),
+            [Value::Bot, Value::One, Value::Zero]
+        );
+
+        let pc = copy_pairs(2);
+        let search = BacktrackAlgorithm
+            .solutions(&pc)
+            .unwrap()
+            .with_heuristic(Repeating);
+        assert_eq!(
+            collect(search).into_iter().collect::<HashSet<_>>(),
+            BacktrackAlgorithm
+                .calculate(&pc)
+                .unwrap()
+                .into_iter()
+                .collect::<HashSet<_>>()
+        );
+    }
+
+    #[test]
+    fn test_random_ties_seed() {
+        let mut pc = PureCircuitGraph::<(), ()>::new();
+        for _ in 0..6 {
+            pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+        }
+        let run = |seed| {
+            collect(
+                BacktrackAlgorithm
+                    .solutions(&pc)
+                    .unwrap()
+                    .with_heuristic(RandomTies::new(seed))
+                    .take(20),
+            )
+        };
+        assert_eq!(run(1), run(1));
+        // Every node is tied, the default order branches on them in a fixed order
+        assert!((0..10).any(|seed| run(seed) != run(seed + 1)));
+    }
+
+    #[test]
+    fn test_dom_wdeg_weights() {
+        // Not self-loop, every pure value violates the gate
+        let mut pc = PureCircuitGraph::<(), ()>::new();
+        let v = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+        let g = pc.add_node(NodeUnitialised::from_gate(Gate::Not), ());
+        pc.add_edge(v, g, ()).unwrap();
+        pc.add_edge(g, v, ()).unwrap();
+        let (table, state) = CircuitTable::new(&pc).unwrap();
+        let view = BranchView::new(&table, &state);
+        let mut heuristic = DomWdeg::new();
+        heuristic.on_conflict(&[v.index()], Some(0), &view);
+        heuristic.on_conflict(&[v.index()], None, &view);
+        assert_eq!(heuristic.weight(0), 2);
+        // The only port of the gate is the node itself
+        assert_eq!(heuristic.wdeg(v.index(), &view), 0);
+        assert_eq!(heuristic.select_node(&view), Some(v));
+    }
+
+    #[test]
+    fn test_portfolio_switches() {
+        let pc = copy_pairs(1);
+        let (table, state) = CircuitTable::new(&pc).unwrap();
+        let view = BranchView::new(&table, &state);
+        let mut portfolio = Portfolio::new(vec![
+            Box::new(DomainDegree),
+            Box::new(BotLast(DomainDegree)),
+        ]);
+        let node = NodeIndex::new(0);
+        assert_eq!(portfolio.value_order(node, &view), DEFAULT_VALUE_ORDER);
+        portfolio.on_restart();
+        assert_eq!(
+            portfolio.value_order(node, &view),
+            [Value::Zero, Value::One, Value::Bot]
+        );
+        portfolio.on_restart();
+        assert_eq!(portfolio.value_order(node, &view), DEFAULT_VALUE_ORDER);
+    }
+
+    proptest! {
+        #[test]
+        fn same_solutions(pc in circuit_strategy(CircuitParams::new(1..=8, 0..=10))) {
+            let expected = BacktrackAlgorithm
+                .calculate(&pc)
+                .unwrap()
+                .into_iter()
+                .collect::<HashSet<_>>();
+            for heuristic in heuristics() {
+                let search = BacktrackAlgorithm.solutions(&pc).unwrap();
+                let sols = collect(search.with_heuristic(heuristic));
+                prop_assert_eq!(sols.len(), expected.len());
+                prop_assert_eq!(sols.into_iter().collect::<HashSet<_>>(), expected.clone());
+            }
+        }
+    }
+}
@@ -0,0 +1,562 @@
+use std::{fmt::Debug, time::Instant};
+
+use itertools::Itertools;
+
+use crate::{
+    gates::Value,
+    graph::{GraphError, PureCircuitGraph},
+    solution_finders::{
+        backtracking::BitString,
+        base_finder::FitnessPureCircuit,
+        observer::{SharedObserver, SolverEvent},
+        solver_error::SolverError,
+        solver_trait::{Budget, SolveStats, SolverTrait},
+    },
+};
+
+/// Exact minimisation of the number of violated gates with depth-first branch-and-bound
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MaxCspAlgorithm;
+
+/// Parameter set of the branch-and-bound optimiser
+#[derive(Debug, Clone)]
+pub struct MaxCspParamSet {
+    pub fitness: FitnessPureCircuit,
+    /// Values each value node may take, in the order of the chromosome.
+    /// Restricting a domain to a single value pins the node
+    pub domains: Vec<BitString>,
+    pub budget: Budget,
+    pub observer: Option<SharedObserver>,
+}
+
+impl MaxCspParamSet {
+    /// Optimise over every assignment of the circuit, without limits
+    /// # Errors
+    /// * InvalidArity: Exists gate with invalid arity
+    /// * Graph(NonHeterogeneousEdge): Exists gate connected to another gate
+    pub fn new<T: Debug + Copy, G: Debug + Copy>(
+        pc_instance: &PureCircuitGraph<T, G>,
+    ) -> Result<Self, SolverError> {
+        Ok(Self {
+            fitness: pc_instance.to_fitness_function()?,
+            domains: vec![BitString::all(); pc_instance.count_values()],
+            budget: Budget::default(),
+            observer: None,
+        })
+    }
+
+    /// Pin the value node at `position` of the chromosome to `value`
+    /// # Errors
+    /// * Graph(NotExistentNode): `position` is past the end of the chromosome
+    pub fn pin(mut self, position: usize, value: Value) -> Result<Self, SolverError> {
+        *self
+            .domains
+            .get_mut(position)
+            .ok_or(SolverError::Graph(GraphError::NotExistentNode))? = value.into();
+        Ok(self)
+    }
+}
+
+/// Assignment found by the branch-and-bound optimiser
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaxCspSolution {
+    /// Best assignment found, in the order of the chromosome
+    pub assignment: Vec<Value>,
+    /// Gates the assignment violates
+    pub violations: usize,
+    /// No assignment within the domains violates fewer gates
+    pub lower_bound: usize,
+    /// The budget ran out before the assignment was proven optimal
+    pub interrupted: bool,
+    pub stats: SolveStats,
+}
+
+impl MaxCspSolution {
+    pub fn is_optimal(&self) -> bool {
+        self.violations == self.lower_bound
+    }
+}
+
+impl MaxCspAlgorithm {
+    /// Find an assignment of the circuit violating the fewest gates
+    /// # Errors
+    /// * InvalidArity: Exists gate with invalid arity
+    pub fn minimise<T: Debug + Copy, G: Debug + Copy>(
+        &self,
+        pc_instance: &PureCircuitGraph<T, G>,
+    ) -> Result<MaxCspSolution, SolverError> {
+        self.find_solution(MaxCspParamSet::new(pc_instance)?)
+    }
+}
+
+/// Partial assignment of the branch-and-bound search
+#[derive(Debug)]
+struct Bounder<'a> {
+    fitness: &'a FitnessPureCircuit,
+    domains: &'a [BitString],
+    assignment: Vec<Option<Value>>,
+    /// Unassigned value nodes of every gate
+    open_ports: Vec<usize>,
+    /// Gates with every port assigned that are violated
+    cost: usize,
+}
+
+impl<'a> Bounder<'a> {
+    fn new(fitness: &'a FitnessPureCircuit, domains: &'a [BitString]) -> Self {
+        Self {
+            fitness,
+            domains,
+            assignment: vec![None; domains.len()],
+            open_ports: (0..fitness.gate_count())
+                .map(|g| fitness.gate_nodes(g).unique().count())
+                .collect(),
+            cost: 0,
+        }
+    }
+
+    /// Some assignment of the open ports of `gate` within their domains satisfies it,
+    /// assuming `extra` on top of the current assignment
+    fn satisfiable(&self, gate: usize, extra: Option<(usize, Value)>) -> Result<bool, SolverError> {
+        let (g, ins, outs) = self
+            .fitness
+            .gate(gate)
+            .ok_or(SolverError::Graph(GraphError::NotExistentNode))?;
+        let ports = ins
+            .iter()
+            .flatten()
+            .chain(outs.iter().flatten())
+            .collect_vec();
+        let domain = |node: usize| match (self.assignment[node], extra) {
+            (Some(v), _) => vec![v],
+            (None, Some((n, v))) if n == node => vec![v],
+            (None, _) => self.domains[node].to_value_iter().collect_vec(),
+        };
+        for values in ports.iter().map(|n| domain(**n)).multi_cartesian_product() {
+            let consistent = ports
+                .iter()
+                .zip(&values)
+                .tuple_combinations()
+                .all(|((a, x), (b, y))| a != b || x == y);
+            let (ins_vals, outs_vals) = values.split_at(ins.iter().flatten().count());
+            if consistent && g.check(ins_vals, outs_vals)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Lower bound on the violated gates of every completion of the assignment.
+    ///
+    /// Adds to the cost a number of gate-disjoint conflicts: open gates that cannot be
+    /// satisfied, then for every unassigned value node, the unused gates around it if each
+    /// value of the node leaves one of them unsatisfiable. Every conflict holds a violated gate
+    /// in any completion.
+    fn lower_bound(&self) -> Result<usize, SolverError> {
+        let mut used = vec![false; self.open_ports.len()];
+        let mut conflicts = 0;
+        for gate in (0..used.len()).filter(|g| self.open_ports[*g] > 0) {
+            if !self.satisfiable(gate, None)? {
+                used[gate] = true;
+                conflicts += 1;
+            }
+        }
+        for node in (0..self.assignment.len()).filter(|n| self.assignment[*n].is_none()) {
+            let gates = self
+                .fitness
+                .incident_gates(node)
+                .iter()
+                .copied()
+                .filter(|g| !used[*g])
+                .collect_vec();
+            if gates.is_empty() {
+                continue;
+            }
+            let mut conflict = true;
+            for value in self.domains[node].to_value_iter() {
+                let mut killed = false;
+                for gate in &gates {
+                    if !self.satisfiable(*gate, Some((node, value)))? {
+                        killed = true;
+                        break;
+                    }
+                }
+                if !killed {
+                    conflict = false;
+                    break;
+                }
+            }
+            if conflict {
+                conflicts += 1;
+                for gate in gates {
+                    used[gate] = true;
+                }
+            }
+        }
+        Ok(self.cost + conflicts)
+    }
+
+    /// Assign `value` to `node`
+    /// # Returns
+    /// Gates that became fully assigned and are violated
+    fn assign(&mut self, node: usize, value: Value) -> Result<usize, SolverError> {
+        self.assignment[node] = Some(value);
+        let mut added = 0;
+        for gate in self.fitness.incident_gates(node) {
+            self.open_ports[*gate] -= 1;
+            if self.open_ports[*gate] == 0 && !self.satisfiable(*gate, None)? {
+                added += 1;
+            }
+        }
+        self.cost += added;
+        Ok(added)
+    }
+
+    fn unassign(&mut self, node: usize, added: usize) {
+        self.assignment[node] = None;
+        for gate in self.fitness.incident_gates(node) {
+            self.open_ports[*gate] += 1;
+        }
+        self.cost -= added;
+    }
+
+    /// Values of `node`, those leaving fewer of its gates unsatisfiable first
+    fn value_order(&self, node: usize) -> Result<Vec<Value>, SolverError> {
+        let mut values = vec![];
+        for value in self.domains[node].to_value_iter() {
+            let mut killed = 0;
+            for gate in self.fitness.incident_gates(node) {
+                killed += !self.satisfiable(*gate, Some((node, value)))? as usize;
+            }
+            values.push((killed, value));
+        }
+        values.sort_by_key(|(killed, _)| *killed);
+        Ok(values.into_iter().map(|(_, v)| v).collect())
+    }
+}
+
+/// Branching point of the search
+#[derive(Debug)]
+struct Frame {
+    node: usize,
+    values: Vec<Value>,
+    next: usize,
+    /// Cost added by the value currently assigned to `node`, if any
+    added: Option<usize>,
+}
+
+impl SolverTrait for MaxCspAlgorithm {
+    type ParamSet = MaxCspParamSet;
+
+    type Solution = MaxCspSolution;
+
+    /// Depth-first branch-and-bound over the value nodes, most connected first.
+    ///
+    /// Every node of the search counts as a node of the budget. Once the budget runs out, the
+    /// best assignment found so far is returned with the lower bound of the root.
+    /// # Errors
+    /// * InvalidAssignmentLength: The domains do not cover every value node of the gates
+    /// * NoSolution: Some domain is empty
+    /// * Cancelled: The budget ran out before any complete assignment was found
+    fn find_solution(&self, param_set: Self::ParamSet) -> Result<Self::Solution, SolverError> {
+        let started = Instant::now();
+        let monitor = param_set.budget.start();
+        let fitness = &param_set.fitness;
+        if param_set.domains.len() < fitness.required_len() {
+            return Err(SolverError::InvalidAssignmentLength {
+                expected: fitness.required_len(),
+                found: param_set.domains.len(),
+            });
+        }
+        if param_set.domains.iter().any(|d| d.is_empty()) {
+            return Err(SolverError::NoSolution);
+        }
+        let notify = |event| {
+            if let Some(observer) = &param_set.observer {
+                observer.on_event(event);
+            }
+        };
+        let mut stats = SolveStats::default();
+        let mut bounder = Bounder::new(fitness, &param_set.domains);
+
+        // Pinned and unconnected nodes take their first value without branching
+        let mut order = vec![];
+        for (node, domain) in param_set.domains.iter().enumerate() {
+            match (domain.len(), fitness.incident_gates(node).is_empty()) {
+                (1, _) | (_, true) => {
+                    let value = domain
+                        .to_value_iter()
+                        .next()
+                        .ok_or(SolverError::NoSolution)?;
+                    bounder.assign(node, value)?;
+                }
+                _ => order.push(node),
+            }
+        }
+        order.sort_by_key(|n| std::cmp::Reverse(fitness.incident_gates(*n).len()));
+
+        let root_bound = bounder.lower_bound()?;
+        let mut best: Option<(Vec<Value>, usize)> = None;
+        let mut frames: Vec<Frame> = vec![];
+        let mut exhausted = false;
+        'search: loop {
+            if monitor.tick() {
+                break;
+            }
+            let bound = bounder.lower_bound()?;
+            if best.as_ref().is_some_and(|(_, b)| bound >= *b) {
+                stats.conflicts += 1;
+            } else if frames.len() == order.len() {
+                let assignment = bounder.assignment.iter().flatten().copied().collect();
+                best = Some((assignment, bounder.cost));
+                notify(SolverEvent::Incumbent {
+                    op_count: stats.decisions,
+                    violations: bounder.cost,
+                });
+                if bounder.cost <= root_bound {
+                    exhausted = true;
+                    break;
+                }
+            } else {
+                let node = order[frames.len()];
+                frames.push(Frame {
+                    node,
+                    values: bounder.value_order(node)?,
+                    next: 0,
+                    added: None,
+                });
+            }
+            // Move to the next value of the deepest open branching point
+            loop {
+                let Some(frame) = frames.last_mut() else {
+                    exhausted = true;
+                    break 'search;
+                };
+                if let Some(added) = frame.added.take() {
+                    bounder.unassign(frame.node, added);
+                }
+                if let Some(value) = frame.values.get(frame.next).copied() {
+                    frame.next += 1;
+                    frame.added = Some(bounder.assign(frame.node, value)?);
+                    stats.decisions += 1;
+                    stats.max_depth = stats.max_depth.max(frames.len());
+                    notify(SolverEvent::Branch {
+                        op_count: stats.decisions,
+                        depth: frames.len(),
+                    });
+                    continue 'search;
+                }
+                frames.pop();
+            }
+        }
+
+        let interrupted = !exhausted;
+        notify(SolverEvent::Finished { interrupted });
+        let (assignment, violations) = best.ok_or(SolverError::Cancelled)?;
+        stats.wall_time = started.elapsed();
+        Ok(MaxCspSolution {
+            assignment,
+            lower_bound: match exhausted {
+                true => violations,
+                false => root_bound,
+            },
+            violations,
+            interrupted,
+            stats,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{copy_pairs, not_ring},
+        gates::{Gate, NodeUnitialised},
+        solution_finders::observer::ChannelObserver,
+        strategies::{CircuitParams, circuit_strategy},
+    };
+    use proptest::prelude::*;
+    use strum::IntoEnumIterator;
+    /// Fewest violated gates over every assignment within the domains
+    fn brute_force(fitness: &FitnessPureCircuit, domains: &[BitString]) -> usize {
+        domains
+            .iter()
+            .map(|d| d.to_value_iter().collect_vec())
+            .multi_cartesian_product()
+            .map(|values| fitness.evaluate(&values).unwrap())
+            .min()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn test_satisfiable_circuit() {
+        let sol = MaxCspAlgorithm.minimise(&not_ring(5)).unwrap();
+        assert_eq!(sol.violations, 0);
+        assert_eq!(sol.assignment, vec![Value::Bot; 5]);
+        assert!(sol.is_optimal());
+        assert!(!sol.interrupted);
+    }
+
+    #[test]
+    fn test_pinned() {
+        let pc = not_ring(3);
+        let param_set = MaxCspParamSet::new(&pc)
+            .unwrap()
+            .pin(0, Value::One)
+            .unwrap();
+        let fitness = param_set.fitness.clone();
+        let sol = MaxCspAlgorithm.find_solution(param_set).unwrap();
+        assert_eq!(sol.violations, 1);
+        assert_eq!(sol.lower_bound, 1);
+        assert_eq!(sol.assignment[0], Value::One);
+        assert_eq!(fitness.evaluate(&sol.assignment), Ok(1));
+        assert_eq!(
+            MaxCspParamSet::new(&pc)
+                .unwrap()
+                .pin(3, Value::One)
+                .unwrap_err(),
+            SolverError::Graph(GraphError::NotExistentNode)
+        );
+    }
+
+    #[test]
+    fn test_disjoint_conflicts() {
+        let pc = copy_pairs(3);
+        let param_set = MaxCspParamSet::new(&pc).unwrap();
+        let mut domains = param_set.domains.clone();
+        // Each Copy gate has to be violated: pinned inputs and outputs differ
+        domains[0] = Value::One.into();
+        domains[1] = Value::Zero.into();
+        domains[2] = Value::Bot.into();
+        domains[3] = BitString::from(Value::Zero).insert(Value::One);
+        let bounder = Bounder::new(&param_set.fitness, &domains);
+        assert_eq!(bounder.lower_bound(), Ok(2));
+
+        let sol = MaxCspAlgorithm
+            .find_solution(MaxCspParamSet {
+                domains,
+                ..param_set
+            })
+            .unwrap();
+        assert_eq!(sol.violations, 2);
+        assert!(sol.is_optimal());
+    }
+
+    #[test]
+    fn test_incumbent_events() {
+        let (observer, receiver) = ChannelObserver::new();
+        let param_set = MaxCspParamSet {
+            observer: Some(observer.shared()),
+            ..MaxCspParamSet::new(&not_ring(5))
+                .unwrap()
+                .pin(0, Value::One)
+                .unwrap()
+        };
+        let sol = MaxCspAlgorithm.find_solution(param_set).unwrap();
+        let incumbents = receiver
+            .try_iter()
+            .filter_map(|e| match e {
+                SolverEvent::Incumbent { violations, .. } => Some(violations),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert!(incumbents.windows(2).all(|w| w[1] < w[0]));
+        assert_eq!(incumbents.last(), Some(&sol.violations));
+        assert_eq!(sol.violations, 1);
+    }
+
+    #[test]
+    fn test_node_conflict() {
+        // The node shared by two Copy gates cannot match both pinned neighbours
+        let mut pc = PureCircuitGraph::new();
+        let a = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+        let b = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+        let c = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+        for (from, to) in [(a, b), (b, c)] {
+            let g = pc.add_node(NodeUnitialised::from_gate(Gate::Copy), ());
+            pc.add_edge(from, g, ()).unwrap();
+            pc.add_edge(g, to, ()).unwrap();
+        }
+        let param_set = MaxCspParamSet::new(&pc)
+            .unwrap()
+            .pin(0, Value::One)
+            .and_then(|p| p.pin(2, Value::Zero))
+            .unwrap();
+        let bounder = Bounder::new(&param_set.fitness, &param_set.domains);
+        // Every gate can be satisfied on its own, the conflict comes from node 1
+        assert_eq!(bounder.lower_bound(), Ok(1));
+        let sol = MaxCspAlgorithm.find_solution(param_set).unwrap();
+        assert_eq!(sol.violations, 1);
+        assert_eq!(sol.stats.decisions, 1);
+    }
+
+    #[test]
+    fn test_budget() {
+        let param_set = MaxCspParamSet::new(&not_ring(9)).unwrap();
+        param_set.budget.cancellation.cancel();
+        assert_eq!(
+            MaxCspAlgorithm.find_solution(param_set),
+            Err(SolverError::Cancelled)
+        );
+
+        let param_set = MaxCspParamSet {
+            budget: Budget {
+                max_nodes: Some(12),
+                ..Default::default()
+            },
+            ..MaxCspParamSet::new(&not_ring(11))
+                .unwrap()
+                .pin(0, Value::One)
+                .unwrap()
+        };
+        let fitness = param_set.fitness.clone();
+        let sol = MaxCspAlgorithm.find_solution(param_set).unwrap();
+        assert!(sol.interrupted);
+        assert!(sol.lower_bound <= sol.violations);
+        assert_eq!(fitness.evaluate(&sol.assignment), Ok(sol.violations));
+    }
+
+    #[test]
+    fn test_invalid_domains() {
+        let mut param_set = MaxCspParamSet::new(&not_ring(3)).unwrap();
+        param_set.domains[1] = BitString::default();
+        assert_eq!(
+            MaxCspAlgorithm.find_solution(param_set.clone()),
+            Err(SolverError::NoSolution)
+        );
+        param_set.domains.truncate(2);
+        assert_eq!(
+            MaxCspAlgorithm.find_solution(param_set),
+            Err(SolverError::InvalidAssignmentLength {
+                expected: 3,
+                found: 2
+            })
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn matches_brute_force(
+            pc in circuit_strategy(CircuitParams::new(1..=7, 0..=8)),
+            pins in prop::collection::vec(
+                prop::option::of(prop::sample::select(Value::iter().collect_vec())),
+                7,
+            ),
+        ) {
+            let mut param_set = MaxCspParamSet::new(&pc).unwrap();
+            for (domain, pin) in param_set.domains.iter_mut().zip(pins) {
+                if let Some(value) = pin {
+                    *domain = value.into();
+                }
+            }
+            let expected = brute_force(&param_set.fitness, &param_set.domains);
+            let fitness = param_set.fitness.clone();
+            let domains = param_set.domains.clone();
+            let sol = MaxCspAlgorithm.find_solution(param_set).unwrap();
+            prop_assert_eq!(sol.violations, expected);
+            prop_assert!(sol.is_optimal());
+            prop_assert_eq!(fitness.evaluate(&sol.assignment), Ok(expected));
+            prop_assert!(sol.assignment.iter().zip(domains).all(|(v, d)| d.contains(*v)));
+        }
+    }
+}
@@ -0,0 +1,641 @@
+use std::collections::HashMap;
+
+use petgraph::graph::NodeIndex;
+use rand::Rng;
+
+use crate::{
+    gates::Value,
+    graph::{GraphError, PureCircuitGraph},
+    solution_finders::{
+        backtracking::CircuitTable,
+        counting::SolutionCount,
+        solver_error::SolverError,
+        solver_trait::{Budget, BudgetMonitor},
+    },
+};
+
+/// Terminal rejecting every assignment
+const FALSE: usize = 0;
+/// Terminal accepting every assignment
+const TRUE: usize = 1;
+
+/// Predicate over an assignment indexed like the solutions
+type Allowed<'a> = dyn Fn(&[Option<Value>]) -> Result<bool, SolverError> + 'a;
+
+/// Decision node, branching on the value node at `level` of the order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct DiagramNode {
+    level: usize,
+    children: [usize; 3],
+}
+
+/// Reduced ordered multi-valued decision diagram of the solutions of a circuit.
+///
+/// Every decision node branches on one value node, with one child per value. Isomorphic nodes
+/// are shared and nodes whose three children agree are skipped, hence a path may jump over
+/// levels whose value is free. Counts are `u128` and saturate, sampling and marginals are
+/// only exact below `u128::MAX` solutions.
+#[derive(Debug, Clone)]
+pub struct SolutionDiagram {
+    /// Value node at every level
+    order: Vec<usize>,
+    /// Level of every node of the circuit, None for gates. Indexed like the solutions
+    levels: Vec<Option<usize>>,
+    /// Terminals first, children always come before their parents
+    nodes: Vec<DiagramNode>,
+    unique: HashMap<DiagramNode, usize>,
+    root: usize,
+    /// Completions of every node from its level on
+    counts: Vec<u128>,
+}
+
+/// `3^exp`, saturated
+fn free(exp: usize) -> u128 {
+    3u128.saturating_pow(exp.try_into().unwrap_or(u32::MAX))
+}
+
+impl SolutionDiagram {
+    /// Compile the solutions of the circuit, branching on the value nodes in the order the
+    /// backtracker would pick them first
+    ///
+    /// # Parameters
+    /// * `budget` - Every new node of the intermediate diagrams counts as a node
+    ///
+    /// # Errors
+    /// * InvalidArity: Exists gate with invalid arity
+    /// * EmptyGraph: Circuit does not contain any value nodes
+    /// * Cancelled: The budget ran out before the diagram was compiled
+    pub fn compile<T, G>(
+        pc_instance: &PureCircuitGraph<T, G>,
+        budget: &Budget,
+    ) -> Result<Self, SolverError> {
+        let (table, mut state) = CircuitTable::new(pc_instance)?;
+        let mut order = vec![];
+        while let Some((node, _)) = state.queue.pop() {
+            order.push(node.index());
+        }
+        Self::build(&table, order, &budget.start(), usize::MAX)?.ok_or(SolverError::Cancelled)
+    }
+
+    /// Compile like `compile`, but on the running `monitor` of a caller and giving up once
+    /// the compile takes more than `limit` of its nodes
+    ///
+    /// # Returns
+    /// None if the diagram needs more than `limit` nodes to compile
+    ///
+    /// # Errors
+    /// * InvalidArity: Exists gate with invalid arity
+    /// * EmptyGraph: Circuit does not contain any value nodes
+    /// * Cancelled: The monitor ran out before the diagram was compiled
+    pub(crate) fn compile_within<T, G>(
+        pc_instance: &PureCircuitGraph<T, G>,
+        monitor: &BudgetMonitor,
+        limit: usize,
+    ) -> Result<Option<Self>, SolverError> {
+        let (table, mut state) = CircuitTable::new(pc_instance)?;
+        let mut order = vec![];
+        while let Some((node, _)) = state.queue.pop() {
+            order.push(node.index());
+        }
+        Self::build(&table, order, monitor, limit)
+    }
+
+    /// Compile the solutions of the circuit with a custom order of the value nodes
+    ///
+    /// # Errors
+    /// * InvalidArity: Exists gate with invalid arity
+    /// * EmptyGraph: Circuit does not contain any value nodes
+    /// * Graph(NotExistentNode): The order holds a node that is not a value node
+    /// * InvalidAssignmentLength: The order holds a value node twice
+    /// * MissingAssignment: The order misses a value node
+    /// * Cancelled: The budget ran out before the diagram was compiled
+    pub fn compile_with_order<T, G>(
+        pc_instance: &PureCircuitGraph<T, G>,
+        order: &[NodeIndex],
+        budget: &Budget,
+    ) -> Result<Self, SolverError> {
+        let (table, state) = CircuitTable::new(pc_instance)?;
+        let mut seen = vec![false; state.value_map.len()];
+        for node in order {
+            match state.value_map.get(node.index()) {
+                Some(Some(_)) => seen[node.index()] = true,
+                _ => return Err(GraphError::NotExistentNode.into()),
+            }
+        }
+        if let Some(missing) = (0..seen.len()).find(|n| state.value_map[*n].is_some() && !seen[*n])
+        {
+            return Err(SolverError::MissingAssignment {
+                node_index: NodeIndex::new(missing),
+            });
+        }
+        let expected = seen.iter().filter(|s| **s).count();
+        if order.len() != expected {
+            return Err(SolverError::InvalidAssignmentLength {
+                expected,
+                found: order.len(),
+            });
+        }
+        let order = order.iter().map(|n| n.index()).collect();
+        Self::build(&table, order, &budget.start(), usize::MAX)?.ok_or(SolverError::Cancelled)
+    }
+
+    /// None if the compile takes more than `limit` nodes of the monitor
+    fn build(
+        table: &CircuitTable,
+        order: Vec<usize>,
+        monitor: &BudgetMonitor,
+        limit: usize,
+    ) -> Result<Option<Self>, SolverError> {
+        if order.is_empty() {
+            return Err(SolverError::EmptyGraph);
+        }
+        let mut levels = vec![None; table.adjacency.len()];
+        for (level, node) in order.iter().enumerate() {
+            levels[*node] = Some(level);
+        }
+        let terminal = |child| DiagramNode {
+            level: order.len(),
+            children: [child; 3],
+        };
+        let mut diagram = Self {
+            nodes: vec![terminal(FALSE), terminal(TRUE)],
+            order,
+            levels,
+            unique: HashMap::new(),
+            root: TRUE,
+            counts: vec![],
+        };
+        // Sweep the gates along the order, so that intermediate diagrams stay narrow
+        let mut gates = table.gates.iter().collect::<Vec<_>>();
+        gates.sort_by_key(|(_, ins, outs)| {
+            ins.iter()
+                .chain(outs.iter())
+                .flatten()
+                .filter_map(|n| diagram.levels[*n])
+                .max()
+        });
+        let until = monitor.nodes().saturating_add(limit);
+        for (gate, ins, outs) in gates {
+            let scope = ins
+                .iter()
+                .chain(outs.iter())
+                .flatten()
+                .copied()
+                .collect::<Vec<_>>();
+            let relation = diagram.relation(&scope, &|assignment: &[Option<Value>]| {
+                let ins = ins
+                    .iter()
+                    .filter_map(|n| n.and_then(|n| assignment[n]))
+                    .collect::<Vec<_>>();
+                let outs = outs
+                    .iter()
+                    .filter_map(|n| n.and_then(|n| assignment[n]))
+                    .collect::<Vec<_>>();
+                Ok(gate.check(&ins, &outs)?)
+            })?;
+            diagram.root = match diagram.and(diagram.root, relation, monitor, until) {
+                Ok(root) => root,
+                // The limit was reached before the monitor ran out
+                Err(SolverError::Cancelled) if !monitor.was_interrupted() => return Ok(None),
+                Err(e) => return Err(e),
+            };
+        }
+        diagram.refresh();
+        Ok(Some(diagram))
+    }
+
+    /// Shared node branching on `level`, skipped if every branch agrees
+    fn node(&mut self, level: usize, children: [usize; 3]) -> usize {
+        if children[1..].iter().all(|c| *c == children[0]) {
+            return children[0];
+        }
+        let node = DiagramNode { level, children };
+        *self.unique.entry(node).or_insert_with(|| {
+            self.nodes.push(node);
+            self.nodes.len() - 1
+        })
+    }
+
+    /// Diagram of the assignments of `scope` accepted by `allowed`, which receives the
+    /// assignment indexed like the solutions
+    fn relation(&mut self, scope: &[usize], allowed: &Allowed<'_>) -> Result<usize, SolverError> {
+        let mut scope = scope.to_vec();
+        scope.sort_by_key(|n| self.levels[*n]);
+        scope.dedup();
+        let mut assignment = vec![None; self.levels.len()];
+        self.relation_from(&scope, &mut assignment, allowed)
+    }
+
+    fn relation_from(
+        &mut self,
+        scope: &[usize],
+        assignment: &mut [Option<Value>],
+        allowed: &Allowed<'_>,
+    ) -> Result<usize, SolverError> {
+        let Some((node, rest)) = scope.split_first() else {
+            return Ok(match allowed(assignment)? {
+                true => TRUE,
+                false => FALSE,
+            });
+        };
+        let mut children = [FALSE; 3];
+        for (child, value) in children.iter_mut().zip(Value::ALL) {
+            assignment[*node] = Some(value);
+            *child = self.relation_from(rest, assignment, allowed)?;
+        }
+        assignment[*node] = None;
+        let level = self.levels[*node].ok_or(GraphError::NotExistentNode)?;
+        Ok(self.node(level, children))
+    }
+
+    /// Conjunction of two diagrams, cancelled once the monitor runs out or has recorded more
+    /// than `until` nodes
+    fn and(
+        &mut self,
+        a: usize,
+        b: usize,
+        monitor: &BudgetMonitor,
+        until: usize,
+    ) -> Result<usize, SolverError> {
+        let mut memo = HashMap::new();
+        self.and_memo(a, b, monitor, until, &mut memo)
+    }
+
+    fn and_memo(
+        &mut self,
+        a: usize,
+        b: usize,
+        monitor: &BudgetMonitor,
+        until: usize,
+        memo: &mut HashMap<(usize, usize), usize>,
+    ) -> Result<usize, SolverError> {
+        match (a, b) {
+            (FALSE, _) | (_, FALSE) => return Ok(FALSE),
+            (TRUE, other) | (other, TRUE) => return Ok(other),
+            _ if a == b => return Ok(a),
+            _ => (),
+        }
+        let key = (a.min(b), a.max(b));
+        if let Some(node) = memo.get(&key) {
+            return Ok(*node);
+        }
+        if monitor.tick() || monitor.nodes() > until {
+            return Err(SolverError::Cancelled);
+        }
+        let (node_a, node_b) = (self.nodes[a], self.nodes[b]);
+        let level = node_a.level.min(node_b.level);
+        let branch = |node: DiagramNode, id: usize, i: usize| match node.level == level {
+            true => node.children[i],
+            false => id,
+        };
+        let mut children = [FALSE; 3];
+        for (i, child) in children.iter_mut().enumerate() {
+            *child = self.and_memo(
+                branch(node_a, a, i),
+                branch(node_b, b, i),
+                monitor,
+                until,
+                memo,
+            )?;
+        }
+        let ret = self.node(level, children);
+        memo.insert(key, ret);
+        Ok(ret)
+    }
+
+    /// Recompute the completions of every node, children come before their parents
+    fn refresh(&mut self) {
+        let mut counts = vec![0u128; self.nodes.len()];
+        counts[TRUE] = 1;
+        for id in TRUE + 1..self.nodes.len() {
+            let node = self.nodes[id];
+            counts[id] = node.children.iter().fold(0u128, |acc, c| {
+                acc.saturating_add(counts[*c].saturating_mul(self.gap(node.level, *c)))
+            });
+        }
+        self.counts = counts;
+    }
+
+    /// Assignments of the levels skipped between `level` and `child`
+    fn gap(&self, level: usize, child: usize) -> u128 {
+        free(self.nodes[child].level - level - 1)
+    }
+
+    /// Restrict the diagram to the assignments of `nodes` accepted by `allowed`, which receives
+    /// their values in the same order
+    ///
+    /// # Errors
+    /// * Graph(NotExistentNode): Exists node that is not a value node of the circuit
+    /// * Cancelled: The budget ran out, the diagram is left unchanged
+    pub fn conjoin(
+        &mut self,
+        nodes: &[NodeIndex],
+        allowed: impl Fn(&[Value]) -> bool,
+        budget: &Budget,
+    ) -> Result<(), SolverError> {
+        let scope = nodes.iter().map(|n| n.index()).collect::<Vec<_>>();
+        if scope
+            .iter()
+            .any(|n| self.levels.get(*n).copied().flatten().is_none())
+        {
+            return Err(GraphError::NotExistentNode.into());
+        }
+        let relation = self.relation(&scope, &|assignment: &[Option<Value>]| {
+            let values = scope
+                .iter()
+                .map(|n| assignment[*n].ok_or(GraphError::NotExistentNode))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(allowed(&values))
+        })?;
+        self.root = self.and(self.root, relation, &budget.start(), usize::MAX)?;
+        self.refresh();
+        Ok(())
+    }
+
+    /// Restrict the diagram to the assignments where `node` holds `value`
+    /// # Errors
+    /// * Graph(NotExistentNode): The node is not a value node of the circuit
+    pub fn pin(&mut self, node: NodeIndex, value: Value) -> Result<(), SolverError> {
+        self.conjoin(&[node], |v| v[0] == value, &Budget::default())
+    }
+
+    /// Value nodes in the order the diagram branches on them
+    pub fn order(&self) -> Vec<NodeIndex> {
+        self.order.iter().copied().map(NodeIndex::new).collect()
+    }
+
+    /// Number of decision nodes reachable from the root
+    pub fn size(&self) -> usize {
+        self.reachable().len()
+    }
+
+    /// Decision nodes reachable from the root, sorted by level
+    fn reachable(&self) -> Vec<usize> {
+        let mut seen = vec![false; self.nodes.len()];
+        let mut stack = vec![self.root];
+        let mut ret = vec![];
+        while let Some(id) = stack.pop() {
+            if id <= TRUE || std::mem::replace(&mut seen[id], true) {
+                continue;
+            }
+            ret.push(id);
+            stack.extend(self.nodes[id].children);
+        }
+        ret.sort_by_key(|id| self.nodes[*id].level);
+        ret
+    }
+
+    /// Number of solutions
+    pub fn count(&self) -> SolutionCount {
+        let ret = self.counts[self.root].saturating_mul(free(self.nodes[self.root].level));
+        match ret == u128::MAX {
+            true => SolutionCount::LowerBound(ret),
+            false => SolutionCount::Exact(ret),
+        }
+    }
+
+    /// Whether the circuit has no solution left
+    pub fn is_empty(&self) -> bool {
+        self.root == FALSE
+    }
+
+    /// Whether the assignment, indexed like the solutions, is a solution
+    pub fn contains(&self, assignment: &[Option<Value>]) -> bool {
+        let mut id = self.root;
+        while id > TRUE {
+            let node = self.nodes[id];
+            match assignment.get(self.order[node.level]).copied().flatten() {
+                Some(value) => id = node.children[value.index()],
+                None => return false,
+            }
+        }
+        id == TRUE
+    }
+
+    /// Uniformly random solution, indexed like the solutions of the backtracker.
+    /// None if the circuit has no solution
+    pub fn sample(&self, rng: &mut impl Rng) -> Option<Vec<Option<Value>>> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut ret = vec![None; self.levels.len()];
+        let mut id = self.root;
+        for (level, n) in self.order.iter().enumerate() {
+            let node = self.nodes[id];
+            let value = match node.level == level {
+                false => Value::ALL[rng.random_range(0..3)],
+                true => {
+                    let weights = node
+                        .children
+                        .map(|c| self.counts[c].saturating_mul(self.gap(level, c)));
+                    let total = weights.iter().fold(0u128, |a, w| a.saturating_add(*w));
+                    let mut pick = rng.random_range(0..total);
+                    let i = weights
+                        .iter()
+                        .position(|w| match pick < *w {
+                            true => true,
+                            false => {
+                                pick -= w;
+                                false
+                            }
+                        })
+                        .unwrap_or(2);
+                    id = node.children[i];
+                    Value::ALL[i]
+                }
+            };
+            ret[*n] = Some(value);
+        }
+        Some(ret)
+    }
+
+    /// Number of solutions in which each value node takes each value, indexed like the
+    /// solutions and then by value. None for gates
+    pub fn marginals(&self) -> Vec<Option<[u128; 3]>> {
+        let mut per_level = vec![[0u128; 3]; self.order.len()];
+        // Paths from the root level into every node
+        let mut paths = vec![0u128; self.nodes.len()];
+        let add_edge = |per_level: &mut [[u128; 3]], from: usize, into: usize, reach: u128| {
+            // Levels skipped by the edge are free
+            let through = reach.saturating_mul(self.counts[into]);
+            for level in per_level[from..self.nodes[into].level].iter_mut() {
+                let share = through.saturating_mul(free(self.nodes[into].level - from - 1));
+                for m in level.iter_mut() {
+                    *m = m.saturating_add(share);
+                }
+            }
+        };
+        paths[self.root] = free(self.nodes[self.root].level);
+        add_edge(&mut per_level, 0, self.root, 1);
+        for id in self.reachable() {
+            let node = self.nodes[id];
+            for (i, child) in node.children.into_iter().enumerate() {
+                let reach = paths[id].saturating_mul(self.gap(node.level, child));
+                paths[child] = paths[child].saturating_add(reach);
+                let through = paths[id].saturating_mul(self.counts[child]);
+                per_level[node.level][i] = per_level[node.level][i]
+                    .saturating_add(through.saturating_mul(self.gap(node.level, child)));
+                add_edge(&mut per_level, node.level + 1, child, paths[id]);
+            }
+        }
+        self.levels
+            .iter()
+            .map(|level| level.map(|l| per_level[l]))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::copy_chain,
+        gates::NodeUnitialised,
+        solution_finders::backtracking::BacktrackAlgorithm,
+        strategies::{CircuitParams, circuit_strategy},
+    };
+    use proptest::prelude::*;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    #[test]
+    fn test_chain() {
+        let pc = copy_chain(50);
+        let diagram = SolutionDiagram::compile(&pc, &Budget::default()).unwrap();
+        assert_eq!(diagram.count(), SolutionCount::Exact(3));
+        assert!(diagram.size() <= 3 * 51);
+        assert_eq!(
+            SolutionDiagram::compile(&PureCircuitGraph::<(), ()>::new(), &Budget::default())
+                .unwrap_err(),
+            SolverError::EmptyGraph
+        );
+    }
+
+    #[test]
+    fn test_free_nodes() {
+        let mut pc = PureCircuitGraph::<(), ()>::new();
+        for _ in 0..5 {
+            pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+        }
+        let diagram = SolutionDiagram::compile(&pc, &Budget::default()).unwrap();
+        assert_eq!(diagram.count(), SolutionCount::Exact(243));
+        assert_eq!(diagram.size(), 0);
+        assert!(diagram.marginals().iter().all(|m| *m == Some([81; 3])));
+    }
+
+    #[test]
+    fn test_pin_and_conjoin() {
+        let pc = copy_chain(4);
+        let mut diagram = SolutionDiagram::compile(&pc, &Budget::default()).unwrap();
+        diagram.pin(NodeIndex::new(0), Value::One).unwrap();
+        assert_eq!(diagram.count(), SolutionCount::Exact(1));
+        diagram
+            .conjoin(
+                &[NodeIndex::new(0), NodeIndex::new(1)],
+                |v| v[0] != v[1],
+                &Budget::default(),
+            )
+            .unwrap();
+        assert!(diagram.is_empty());
+        assert_eq!(diagram.sample(&mut StdRng::seed_from_u64(0)), None);
+        assert_eq!(
+            diagram.pin(NodeIndex::new(2), Value::One),
+            Err(SolverError::Graph(GraphError::NotExistentNode))
+        );
+    }
+
+    #[test]
+    fn test_order() {
+        let pc = copy_chain(3);
+        let order = [5, 3, 1, 0].map(NodeIndex::new);
+        let diagram = SolutionDiagram::compile_with_order(&pc, &order, &Budget::default()).unwrap();
+        assert_eq!(diagram.order(), order);
+        assert_eq!(diagram.count(), SolutionCount::Exact(3));
+        assert!(matches!(
+            SolutionDiagram::compile_with_order(&pc, &order[1..], &Budget::default()),
+            Err(SolverError::MissingAssignment { .. })
+        ));
+        assert!(matches!(
+            SolutionDiagram::compile_with_order(&pc, &[order[0]; 4], &Budget::default()),
+            Err(SolverError::MissingAssignment { .. })
+        ));
+        assert!(matches!(
+            SolutionDiagram::compile_with_order(
+                &pc,
+                &[order[0], order[1], order[2], order[3], order[3]],
+                &Budget::default()
+            ),
+            Err(SolverError::InvalidAssignmentLength {
+                expected: 4,
+                found: 5
+            })
+        ));
+    }
+
+    #[test]
+    fn test_budget() {
+        let budget = Budget::default();
+        budget.cancellation.cancel();
+        assert_eq!(
+            SolutionDiagram::compile(&copy_chain(5), &budget).unwrap_err(),
+            SolverError::Cancelled
+        );
+    }
+
+    #[test]
+    fn test_compile_within() {
+        let pc = copy_chain(5);
+        let monitor = Budget::default().start();
+        let diagram = SolutionDiagram::compile_within(&pc, &monitor, usize::MAX)
+            .unwrap()
+            .unwrap();
+        assert_eq!(diagram.count(), SolutionCount::Exact(3));
+        let used = monitor.nodes();
+        assert!(used > 0);
+
+        // Too large for the limit, the monitor keeps running
+        assert!(
+            SolutionDiagram::compile_within(&pc, &monitor, used - 1)
+                .unwrap()
+                .is_none()
+        );
+        assert!(!monitor.was_interrupted());
+
+        let monitor = Budget {
+            max_nodes: Some(used - 1),
+            ..Default::default()
+        }
+        .start();
+        assert_eq!(
+            SolutionDiagram::compile_within(&pc, &monitor, usize::MAX).unwrap_err(),
+            SolverError::Cancelled
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn matches_backtracker(
+            pc in circuit_strategy(CircuitParams::new(1..=8, 0..=10)),
+            seed in any::<u64>(),
+        ) {
+            let solutions = BacktrackAlgorithm.calculate(&pc).unwrap();
+            let diagram = SolutionDiagram::compile(&pc, &Budget::default()).unwrap();
+            prop_assert_eq!(diagram.count(), SolutionCount::Exact(solutions.len() as u128));
+            prop_assert!(solutions.iter().all(|s| diagram.contains(s)));
+
+            let mut rng = StdRng::seed_from_u64(seed);
+            if let Some(sample) = diagram.sample(&mut rng) {
+                prop_assert!(solutions.contains(&sample));
+            }
+
+            let marginals = diagram.marginals();
+            for (n, marginal) in marginals.iter().enumerate() {
+                let expected = marginal.map(|_| {
+                    Value::ALL.map(|v| {
+                        solutions.iter().filter(|s| s[n] == Some(v)).count() as u128
+                    })
+                });
+                prop_assert_eq!(*marginal, expected);
+            }
+        }
+    }
+}
@@ -1,5 +1,40 @@
+#[cfg(feature = "backtrack")]
 pub mod backtracking;
+#[cfg(feature = "evolutionary")]
 pub mod evo_search;
+pub mod observer;
+pub mod solver_error;
 pub mod solver_trait;
 pub mod base_finder;
-pub mod gate_backtrack;
\ No newline at end of file
+#[cfg(feature = "backtrack")]
+pub mod counting;
+#[cfg(feature = "backtrack")]
+pub mod gate_backtrack;
+#[cfg(feature = "backtrack")]
+pub(crate) mod nogood;
+#[cfg(feature = "backtrack")]
+pub mod gac;
+#[cfg(feature = "backtrack")]
+pub mod heuristics;
+#[cfg(feature = "backtrack")]
+pub mod parallel;
+#[cfg(feature = "evolutionary")]
+pub mod annealing;
+#[cfg(feature = "evolutionary")]
+pub mod tabu;
+#[cfg(all(feature = "backtrack", feature = "evolutionary"))]
+pub mod walksat;
+#[cfg(feature = "backtrack")]
+pub mod maxcsp;
+#[cfg(feature = "backtrack")]
+pub mod purity;
+#[cfg(feature = "backtrack")]
+pub mod ranking;
+#[cfg(feature = "backtrack")]
+pub mod tree_dp;
+#[cfg(feature = "backtrack")]
+pub mod mdd;
+#[cfg(feature = "backtrack")]
+pub mod sampling;
+#[cfg(feature = "backtrack")]
+pub mod backbone;
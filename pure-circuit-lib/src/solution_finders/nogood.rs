@@ -0,0 +1,102 @@
+use petgraph::graph::NodeIndex;
+
+use crate::solution_finders::{
+    backtracking::{BitString, Propagation, Reason, SearchState},
+    solver_error::SolverError,
+};
+
+/// Learned nogoods stop being recorded past this many, propagation cost grows with the store
+const MAX_NOGOODS: usize = 100_000;
+
+/// Combination of domain restrictions that no solution satisfies.
+/// A literal `(node, set)` holds once the node is assigned a value of `set`
+pub(crate) type Nogood = Box<[(usize, BitString)]>;
+
+/// Nogoods learned from the conflicts of the search.
+/// Every nogood is implied by the circuit, hence they stay valid in every branch
+#[derive(Debug, Clone, Default)]
+pub(crate) struct NogoodStore {
+    nogoods: Vec<Nogood>,
+    /// Nogoods mentioning each value node
+    occurrences: Vec<Vec<usize>>,
+}
+
+impl NogoodStore {
+    pub(crate) fn len(&self) -> usize {
+        self.nogoods.len()
+    }
+
+    /// Value nodes of the nogood `id`
+    pub(crate) fn nodes(&self, id: usize) -> impl Iterator<Item = usize> + '_ {
+        self.nogoods[id].iter().map(|(n, _)| *n)
+    }
+
+    pub(crate) fn learn(&mut self, nogood: Nogood) {
+        if self.nogoods.len() < MAX_NOGOODS {
+            self.block(nogood);
+        }
+    }
+
+    /// Record `nogood` regardless of the size of the store, e.g. to forbid a solution
+    pub(crate) fn block(&mut self, nogood: Nogood) {
+        let id = self.nogoods.len();
+        for (n, _) in nogood.iter() {
+            if self.occurrences.len() <= *n {
+                self.occurrences.resize_with(n + 1, Vec::new);
+            }
+            self.occurrences[*n].push(id);
+        }
+        self.nogoods.push(nogood);
+    }
+
+    /// Check the nogoods mentioning the newly assigned `node`.
+    /// A nogood with a single literal left open removes its set from the domain of that node
+    pub(crate) fn propagate(
+        &self,
+        node: NodeIndex,
+        state: &mut SearchState,
+    ) -> Result<Propagation, SolverError> {
+        let Some(ids) = self.occurrences.get(node.index()) else {
+            return Ok(Propagation::Consistent);
+        };
+        for (id, nogood) in ids.iter().map(|id| (*id, &self.nogoods[*id])) {
+            let mut open = None;
+            let mut active = true;
+            for (pos, (n, set)) in nogood.iter().enumerate() {
+                let holds = match state.sol_map[*n] {
+                    Some(v) => !set.op_inter(v.into()).is_empty(),
+                    None if open.is_none() => {
+                        let domain = state.value_map[*n].unwrap_or_default();
+                        open = Some(pos);
+                        !domain.op_inter(*set).is_empty()
+                    }
+                    None => false,
+                };
+                if !holds {
+                    active = false;
+                    break;
+                }
+            }
+            if !active {
+                continue;
+            }
+            match open {
+                None => {
+                    return Ok(Propagation::Conflict {
+                        nodes: self.nodes(id).collect(),
+                        gate: None,
+                    });
+                }
+                Some(pos) => {
+                    let (n, set) = nogood[pos];
+                    let reason = Reason::Nogood {
+                        id,
+                        assigned: state.assigned,
+                    };
+                    state.prop_node(n, set.reverse(), reason)?;
+                }
+            }
+        }
+        Ok(Propagation::Consistent)
+    }
+}
@@ -0,0 +1,133 @@
+use std::{
+    fmt::Debug,
+    sync::{
+        Arc,
+        mpsc::{self, Receiver, Sender},
+    },
+};
+
+/// Progress reported by the solvers while they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolverEvent {
+    /// The backtracker explored a branch
+    /// * `op_count` - Branches explored so far
+    /// * `depth` - Open branching points on the search stack
+    Branch { op_count: usize, depth: usize },
+    /// The backtracker found an assignment
+    /// * `found` - Assignments found so far
+    Solution { op_count: usize, found: usize },
+    /// An optimiser found an assignment violating fewer gates than the previous best
+    /// * `violations` - Gates the new best assignment violates
+    Incumbent { op_count: usize, violations: usize },
+    /// A meta-heuristic finished a generation.
+    /// `best_fitness` is the number of violated gates of the best chromosome seen so far, best
+    /// under the objective the solver optimises, if any chromosome was evaluated.
+    /// Generations restart for every species/run.
+    Generation {
+        generation: usize,
+        best_fitness: Option<isize>,
+    },
+    /// The solver stopped, either because it finished or because its budget ran out
+    Finished { interrupted: bool },
+}
+
+/// Hook called by the solvers at well-defined points of their run.
+///
+/// Observers are shared between threads (e.g. by parallel fitness evaluation), hence they
+/// take `&self` and must be `Send + Sync`.
+pub trait SolverObserver: Debug + Send + Sync {
+    fn on_event(&self, event: SolverEvent);
+}
+
+/// Observer handed to the solvers through their parameter sets
+pub type SharedObserver = Arc<dyn SolverObserver>;
+
+/// Observer forwarding the events to a channel, so that another thread can watch the run.
+#[derive(Debug, Clone)]
+pub struct ChannelObserver {
+    sender: Sender<SolverEvent>,
+    branch_interval: usize,
+}
+
+impl ChannelObserver {
+    /// Create the observer together with the receiving end of its channel
+    pub fn new() -> (Self, Receiver<SolverEvent>) {
+        let (sender, receiver) = mpsc::channel();
+        (
+            Self {
+                sender,
+                branch_interval: 1,
+            },
+            receiver,
+        )
+    }
+
+    /// Only forward every `interval`-th `Branch` event, the backtracker explores millions of them
+    pub fn with_branch_interval(self, interval: usize) -> Self {
+        Self {
+            branch_interval: interval.max(1),
+            ..self
+        }
+    }
+
+    pub fn shared(self) -> SharedObserver {
+        Arc::new(self)
+    }
+}
+
+impl SolverObserver for ChannelObserver {
+    fn on_event(&self, event: SolverEvent) {
+        let skipped = match event {
+            SolverEvent::Branch { op_count, .. } => op_count % self.branch_interval != 0,
+            _ => false,
+        };
+        if skipped {
+            return;
+        }
+        // The receiver stopped listening, the solver keeps going regardless
+        let _ = self.sender.send(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_observer() {
+        let (observer, receiver) = ChannelObserver::new();
+        let observer = observer.with_branch_interval(2).shared();
+        let handle = std::thread::spawn({
+            let observer = observer.clone();
+            move || {
+                for op_count in 1..=4 {
+                    observer.on_event(SolverEvent::Branch { op_count, depth: 1 });
+                }
+                observer.on_event(SolverEvent::Finished { interrupted: false });
+            }
+        });
+        handle.join().unwrap();
+        drop(observer);
+        assert_eq!(
+            receiver.iter().collect::<Vec<_>>(),
+            vec![
+                SolverEvent::Branch {
+                    op_count: 2,
+                    depth: 1
+                },
+                SolverEvent::Branch {
+                    op_count: 4,
+                    depth: 1
+                },
+                SolverEvent::Finished { interrupted: false },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dropped_receiver() {
+        let (observer, receiver) = ChannelObserver::new();
+        drop(receiver);
+        observer.on_event(SolverEvent::Finished { interrupted: true });
+    }
+}
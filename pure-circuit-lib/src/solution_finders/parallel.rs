@@ -0,0 +1,468 @@
+use std::{
+    collections::VecDeque,
+    num::NonZeroUsize,
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::Instant,
+};
+
+use crate::{
+    gates::Value,
+    graph::PureCircuitGraph,
+    solution_finders::{
+        backtracking::{BacktrackAlgorithm, BacktrackParamSet, BacktrackSolution, Cube},
+        observer::{SharedObserver, SolverEvent, SolverObserver},
+        solver_error::SolverError,
+        solver_trait::{BudgetMonitor, SolveStats},
+    },
+};
+
+type Solutions = Vec<Vec<Option<Value>>>;
+type CubeResult = Result<(Solutions, SolveStats), SolverError>;
+
+/// Configuration of the parallel backtracker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParallelConfig {
+    /// Worker threads
+    pub threads: usize,
+    /// Subtrees the search is split into before the workers start.
+    /// The solutions only depend on this number, not on the number of threads
+    pub cubes: usize,
+}
+
+impl Default for ParallelConfig {
+    fn default() -> Self {
+        Self {
+            threads: thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            cubes: 64,
+        }
+    }
+}
+
+impl BacktrackAlgorithm {
+    /// Enumerate the assignments of the circuit over several threads
+    /// (see `find_solution_parallel`)
+    pub fn calculate_parallel<T, G>(
+        &self,
+        pc_instance: &PureCircuitGraph<T, G>,
+        config: &ParallelConfig,
+    ) -> Result<Vec<Vec<Option<Value>>>, SolverError> {
+        self.find_solution_parallel(BacktrackParamSet::new(pc_instance)?, config)
+            .map(|sol| sol.solutions)
+    }
+
+    /// Parallel counterpart of `find_solution`, in the style of cube-and-conquer.
+    ///
+    /// The search tree is split breadth-first into `config.cubes` subtrees, which the workers
+    /// search independently. Each worker owns a queue of cubes and steals from the back of the
+    /// others once its own runs out. Solutions are merged in the order of the cubes, hence the
+    /// result is reproducible for any number of threads. It holds the same solutions as
+    /// `find_solution`, although not necessarily in the same order.
+    ///
+    /// The budget is shared by the workers. With `max_solutions`, the first solutions in the
+    /// merged order are kept.
+    ///
+    /// The observer receives the branches and solutions of every cube in the order the workers
+    /// reach them, counted over the whole run, and a single `Finished` at the end. `found` also
+    /// counts solutions beyond `max_solutions` that the merge drops.
+    pub fn find_solution_parallel(
+        &self,
+        param_set: BacktrackParamSet,
+        config: &ParallelConfig,
+    ) -> Result<BacktrackSolution, SolverError> {
+        let started = Instant::now();
+        let monitor = param_set.budget.start();
+        let limit = param_set.max_solutions.unwrap_or(usize::MAX);
+        let (cubes, mut stats) = param_set.search.cubes(config.cubes.max(1))?;
+        let split_depth = stats.max_depth;
+        let observer = param_set
+            .observer
+            .clone()
+            .map(|observer| Arc::new(CubeObserver::new(observer, stats.decisions, split_depth)));
+        let work = WorkQueues::new(cubes.len(), config.threads.clamp(1, cubes.len().max(1)));
+        let cubes = cubes
+            .into_iter()
+            .map(|c| Mutex::new(Some(c)))
+            .collect::<Vec<_>>();
+        let results = Mutex::new(vec![None; cubes.len()]);
+
+        thread::scope(|scope| {
+            for worker in 0..work.workers() {
+                let (work, cubes, results, monitor, observer) =
+                    (&work, &cubes, &results, &monitor, observer.as_ref());
+                scope.spawn(move || {
+                    while let Some(index) = work.next(worker) {
+                        let cube = lock(&cubes[index]).take();
+                        // Earlier cubes already hold enough solutions
+                        let result = match cube {
+                            Some(cube) if !prefix_complete(&lock(results), index, limit) => {
+                                run_cube(cube, monitor, limit, observer)
+                            }
+                            _ => Ok((vec![], SolveStats::default())),
+                        };
+                        lock(results)[index] = Some(result);
+                    }
+                });
+            }
+        });
+
+        let mut solutions = vec![];
+        for result in results.into_inner().unwrap_or_else(PoisonError::into_inner) {
+            let (cube_solutions, cube_stats) =
+                result.unwrap_or(Ok((vec![], SolveStats::default())))?;
+            solutions.extend(cube_solutions);
+            stats.propagations += cube_stats.propagations;
+            stats.decisions += cube_stats.decisions;
+            stats.conflicts += cube_stats.conflicts;
+            stats.restarts += cube_stats.restarts;
+            stats.max_depth = stats.max_depth.max(split_depth + cube_stats.max_depth);
+        }
+        solutions.truncate(limit);
+        stats.wall_time = started.elapsed();
+        let interrupted = monitor.was_interrupted();
+        if let Some(observer) = param_set.observer {
+            observer.on_event(SolverEvent::Finished { interrupted });
+        }
+        Ok(BacktrackSolution {
+            solutions,
+            interrupted,
+            stats,
+        })
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The finished cubes before `index` hold at least `limit` solutions
+fn prefix_complete(results: &[Option<CubeResult>], index: usize, limit: usize) -> bool {
+    let mut found = 0usize;
+    for result in &results[..index] {
+        match result {
+            Some(Ok((solutions, _))) => found += solutions.len(),
+            Some(Err(_)) => return true,
+            None => return false,
+        }
+        if found >= limit {
+            return true;
+        }
+    }
+    false
+}
+
+fn run_cube(
+    cube: Cube,
+    monitor: &BudgetMonitor,
+    limit: usize,
+    observer: Option<&Arc<CubeObserver>>,
+) -> CubeResult {
+    match cube {
+        Cube::Solved(solution) => {
+            if let Some(observer) = observer {
+                observer.solution();
+            }
+            Ok((vec![solution], SolveStats::default()))
+        }
+        Cube::Open(search) => {
+            let mut search = search.with_monitor(monitor.clone());
+            if let Some(observer) = observer {
+                search = search.with_observer(observer.clone());
+            }
+            let solutions = search.by_ref().take(limit).collect::<Result<Vec<_>, _>>()?;
+            Ok((solutions, search.stats()))
+        }
+    }
+}
+
+/// Observer of the cube searches, forwarding their events to the observer of the run.
+/// Branches and solutions are counted over every cube, `Finished` is left to the run
+#[derive(Debug)]
+struct CubeObserver {
+    observer: SharedObserver,
+    /// Depth of the cubes in the search tree
+    split_depth: usize,
+    branches: AtomicUsize,
+    found: AtomicUsize,
+}
+
+impl CubeObserver {
+    fn new(observer: SharedObserver, split_branches: usize, split_depth: usize) -> Self {
+        Self {
+            observer,
+            split_depth,
+            branches: AtomicUsize::new(split_branches),
+            found: AtomicUsize::new(0),
+        }
+    }
+
+    fn solution(&self) {
+        let found = self.found.fetch_add(1, Ordering::Relaxed) + 1;
+        self.observer.on_event(SolverEvent::Solution {
+            op_count: self.branches.load(Ordering::Relaxed),
+            found,
+        });
+    }
+}
+
+impl SolverObserver for CubeObserver {
+    fn on_event(&self, event: SolverEvent) {
+        match event {
+            SolverEvent::Branch { depth, .. } => {
+                let op_count = self.branches.fetch_add(1, Ordering::Relaxed) + 1;
+                self.observer.on_event(SolverEvent::Branch {
+                    op_count,
+                    depth: self.split_depth + depth,
+                });
+            }
+            SolverEvent::Solution { .. } => self.solution(),
+            SolverEvent::Incumbent { .. }
+            | SolverEvent::Generation { .. }
+            | SolverEvent::Finished { .. } => (),
+        }
+    }
+}
+
+/// Queues of cube indexes, one per worker.
+/// Cubes are dealt round-robin, so the workers start with the earliest cubes
+#[derive(Debug)]
+struct WorkQueues(Vec<Mutex<VecDeque<usize>>>);
+
+impl WorkQueues {
+    fn new(jobs: usize, workers: usize) -> Self {
+        let mut queues = vec![VecDeque::new(); workers];
+        for job in 0..jobs {
+            queues[job % workers].push_back(job);
+        }
+        Self(queues.into_iter().map(Mutex::new).collect())
+    }
+
+    fn workers(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Next job of `worker`, stolen from the back of another queue once its own is empty
+    fn next(&self, worker: usize) -> Option<usize> {
+        if let Some(job) = lock(&self.0[worker]).pop_front() {
+            return Some(job);
+        }
+        (1..self.0.len())
+            .map(|offset| (worker + offset) % self.0.len())
+            .find_map(|victim| lock(&self.0[victim]).pop_back())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::copy_chain,
+        gates::NodeUnitialised,
+        solution_finders::{
+            heuristics::DomWdeg,
+            observer::ChannelObserver,
+            solver_trait::{Budget, SolverTrait},
+        },
+        strategies::{CircuitParams, circuit_strategy},
+    };
+    use proptest::prelude::*;
+    use std::collections::HashSet;
+
+    fn independent(nodes: usize) -> PureCircuitGraph {
+        let mut pc = PureCircuitGraph::new();
+        for _ in 0..nodes {
+            pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+        }
+        pc
+    }
+
+    #[test]
+    fn test_work_stealing() {
+        let work = WorkQueues::new(5, 2);
+        assert_eq!(work.next(0), Some(0));
+        assert_eq!(work.next(0), Some(2));
+        assert_eq!(work.next(0), Some(4));
+        // Worker 0 runs out and steals the latest job of worker 1
+        assert_eq!(work.next(0), Some(3));
+        assert_eq!(work.next(1), Some(1));
+        assert_eq!(work.next(1), None);
+    }
+
+    #[test]
+    fn test_deterministic_merge() {
+        let pc = independent(6);
+        let run = |threads| {
+            BacktrackAlgorithm
+                .calculate_parallel(&pc, &ParallelConfig { threads, cubes: 20 })
+                .unwrap()
+        };
+        let sols = run(1);
+        assert_eq!(sols.len(), 3usize.pow(6));
+        for threads in 2..=4 {
+            assert_eq!(run(threads), sols);
+        }
+        // Without learning, the cubes follow the order of the sequential search
+        assert_eq!(sols, BacktrackAlgorithm.calculate(&pc).unwrap());
+    }
+
+    #[test]
+    fn test_solved_cubes() {
+        // The first decision completes the chain, every cube is solved by the split
+        let config = ParallelConfig {
+            threads: 2,
+            cubes: 8,
+        };
+        let sol = BacktrackAlgorithm
+            .find_solution_parallel(BacktrackParamSet::new(&copy_chain(5)).unwrap(), &config)
+            .unwrap();
+        assert_eq!(sol.solutions.len(), 3);
+        assert_eq!(sol.stats.decisions, 3);
+        assert_eq!(sol.stats.max_depth, 1);
+    }
+
+    #[test]
+    fn test_bot_bound() {
+        // Every cube is solved by the split, the all-⊥ chain exceeds the bound
+        let pc = copy_chain(5);
+        let config = ParallelConfig {
+            threads: 2,
+            cubes: 8,
+        };
+        let search = BacktrackAlgorithm
+            .solutions(&pc)
+            .unwrap()
+            .with_bot_bound(&[1; 6], 1);
+        let expected = search.clone().collect::<Result<HashSet<_>, _>>().unwrap();
+        let param_set = BacktrackParamSet {
+            search,
+            ..BacktrackParamSet::new(&pc).unwrap()
+        };
+        let sol = BacktrackAlgorithm
+            .find_solution_parallel(param_set, &config)
+            .unwrap();
+        assert_eq!(sol.solutions.len(), 2);
+        assert_eq!(sol.solutions.into_iter().collect::<HashSet<_>>(), expected);
+    }
+
+    #[test]
+    fn test_max_solutions() {
+        let pc = independent(5);
+        let param_set = BacktrackParamSet {
+            max_solutions: Some(10),
+            ..BacktrackParamSet::new(&pc).unwrap()
+        };
+        let sol = BacktrackAlgorithm
+            .find_solution_parallel(param_set, &ParallelConfig::default())
+            .unwrap();
+        let expected = BacktrackAlgorithm.calculate(&pc).unwrap();
+        assert_eq!(sol.solutions, expected[..10]);
+        assert!(!sol.interrupted);
+    }
+
+    #[test]
+    fn test_observer() {
+        let (observer, receiver) = ChannelObserver::new();
+        let param_set = BacktrackParamSet {
+            observer: Some(observer.shared()),
+            ..BacktrackParamSet::new(&independent(4)).unwrap()
+        };
+        let config = ParallelConfig {
+            threads: 3,
+            cubes: 10,
+        };
+        let sol = BacktrackAlgorithm
+            .find_solution_parallel(param_set, &config)
+            .unwrap();
+        let events = receiver.iter().collect::<Vec<_>>();
+        let found = events
+            .iter()
+            .filter_map(|e| match e {
+                SolverEvent::Solution { found, .. } => Some(*found),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+        assert_eq!(found, (1..=sol.solutions.len()).collect());
+        let branches = events
+            .iter()
+            .filter(|e| matches!(e, SolverEvent::Branch { .. }))
+            .count();
+        assert!(branches > 0 && branches <= sol.stats.decisions);
+        assert_eq!(
+            events.last(),
+            Some(&SolverEvent::Finished { interrupted: false })
+        );
+        assert_eq!(
+            events
+                .iter()
+                .filter(|e| matches!(e, SolverEvent::Finished { .. }))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_cancelled() {
+        let param_set = BacktrackParamSet::new(&independent(8)).unwrap();
+        param_set.budget.cancellation.cancel();
+        let sol = BacktrackAlgorithm
+            .find_solution_parallel(param_set, &ParallelConfig::default())
+            .unwrap();
+        assert!(sol.interrupted);
+        assert!(sol.solutions.len() < 3usize.pow(8));
+    }
+
+    #[test]
+    fn test_node_limit_is_shared() {
+        let param_set = BacktrackParamSet {
+            budget: Budget {
+                max_nodes: Some(50),
+                ..Default::default()
+            },
+            ..BacktrackParamSet::new(&independent(8)).unwrap()
+        };
+        let sol = BacktrackAlgorithm
+            .find_solution_parallel(param_set, &ParallelConfig::default())
+            .unwrap();
+        assert!(sol.interrupted);
+        // Every cube stops at the shared limit, besides the solutions found by the split
+        assert!(sol.stats.decisions < 50 + 2 * 64, "{:?}", sol.stats);
+    }
+
+    proptest! {
+        #[test]
+        fn matches_sequential(
+            pc in circuit_strategy(CircuitParams::new(1..=10, 0..=14)),
+            threads in 1..=4usize,
+            cubes in 1..=16usize,
+        ) {
+            let expected = BacktrackAlgorithm.calculate(&pc).unwrap();
+            let config = ParallelConfig { threads, cubes };
+            let sols = BacktrackAlgorithm.calculate_parallel(&pc, &config).unwrap();
+            prop_assert_eq!(sols.len(), expected.len());
+            prop_assert_eq!(
+                sols.iter().collect::<HashSet<_>>(),
+                expected.iter().collect::<HashSet<_>>()
+            );
+            let param_set = BacktrackParamSet {
+                search: BacktrackAlgorithm
+                    .solutions(&pc)
+                    .unwrap()
+                    .with_heuristic(DomWdeg::new()),
+                ..BacktrackParamSet::new(&pc).unwrap()
+            };
+            let sol = BacktrackAlgorithm.find_solution_parallel(param_set, &config).unwrap();
+            prop_assert_eq!(
+                sol.solutions.into_iter().collect::<HashSet<_>>(),
+                BacktrackAlgorithm
+                    .find_solution(BacktrackParamSet::new(&pc).unwrap())
+                    .unwrap()
+                    .solutions
+                    .into_iter()
+                    .collect::<HashSet<_>>()
+            );
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use petgraph::visit::NodeIndexable;
+
+use crate::{
+    gates::Value,
+    graph::PureCircuitGraph,
+    solution_finders::{
+        backtracking::BacktrackAlgorithm,
+        heuristics::{BotLast, DomainDegree},
+        solver_error::SolverError,
+        solver_trait::{Budget, SolveStats},
+    },
+};
+
+/// Solution returned by `BacktrackAlgorithm::purest_solution`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PurestSolution {
+    pub solution: Vec<Option<Value>>,
+    /// Weighted number of ⊥ values of the solution
+    pub bot_cost: usize,
+    /// The budget ran out before the solution was proven to be the purest
+    pub interrupted: bool,
+    pub stats: SolveStats,
+}
+
+impl BacktrackAlgorithm {
+    /// Find a solution with the fewest ⊥ values, by branch-and-bound over the backtracker.
+    ///
+    /// The search tries ⊥ last. Every solution it finds lowers the bound to its own cost, so
+    /// the next one is strictly purer, until no purer solution is left.
+    ///
+    /// # Parameters
+    /// * `weights` - Cost of ⊥ on each value node, indexed by chromosome position like
+    ///   `FitnessPureCircuit::minimising_bot`, i.e. the value nodes in index order without the
+    ///   gates. Missing positions cost 0, every node costs 1 if None
+    /// * `budget` - Stop once the budget runs out and return the purest solution so far.
+    ///   Every branch counts as a node
+    ///
+    /// # Returns
+    /// Ok(None): Circuit has no solution
+    /// # Errors
+    /// * InvalidArity: Exists gate with invalid arity
+    /// * EmptyGraph: Circuit does not contain any value nodes
+    /// * Cancelled: The budget ran out before any solution was found
+    pub fn purest_solution<T, G>(
+        &self,
+        pc_instance: &PureCircuitGraph<T, G>,
+        weights: Option<&[usize]>,
+        budget: &Budget,
+    ) -> Result<Option<PurestSolution>, SolverError> {
+        // The search indexes the weights like the solutions, by node index
+        let mut slots = vec![0; pc_instance.graph.node_bound()];
+        let values = pc_instance
+            .graph
+            .node_indices()
+            .filter(|n| !pc_instance.graph[*n].node.is_gate());
+        for (position, node) in values.enumerate() {
+            slots[node.index()] = weights.map_or(1, |w| w.get(position).copied().unwrap_or(0));
+        }
+        let weights = &slots;
+        let mut search = self
+            .solutions(pc_instance)?
+            .with_heuristic(BotLast::<DomainDegree>::default())
+            .with_bot_bound(weights, usize::MAX)
+            .with_budget(budget);
+        let mut best = None;
+        while let Some(solution) = search.next().transpose()? {
+            let bot_cost = weights
+                .iter()
+                .zip(&solution)
+                .filter(|(_, value)| **value == Some(Value::Bot))
+                .map(|(weight, _)| *weight)
+                .sum();
+            search.tighten_bot_bound(bot_cost);
+            best = Some((solution, bot_cost));
+            if bot_cost == 0 {
+                break;
+            }
+        }
+        let interrupted = search.is_interrupted();
+        match best {
+            Some((solution, bot_cost)) => Ok(Some(PurestSolution {
+                solution,
+                bot_cost,
+                interrupted,
+                stats: search.stats(),
+            })),
+            None if interrupted => Err(SolverError::Cancelled),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{not_ring, purify_loop},
+        gates::{Gate, NodeUnitialised},
+        strategies::{CircuitParams, circuit_strategy},
+    };
+    use proptest::prelude::*;
+
+    /// Weighted ⊥ count, `weights` indexed by chromosome position
+    fn cost(solution: &[Option<Value>], weights: &[usize]) -> usize {
+        weights
+            .iter()
+            .zip(solution.iter().flatten())
+            .filter(|(_, v)| **v == Value::Bot)
+            .map(|(w, _)| *w)
+            .sum()
+    }
+
+    #[test]
+    fn test_forced_bot() {
+        let sol = BacktrackAlgorithm
+            .purest_solution(&not_ring(5), None, &Budget::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(sol.bot_cost, 5);
+        assert!(!sol.interrupted);
+    }
+
+    #[test]
+    fn test_pure_solution() {
+        let pc = purify_loop();
+        let sol = BacktrackAlgorithm
+            .purest_solution(&pc, None, &Budget::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(sol.bot_cost, 0);
+        assert!(sol.solution.iter().flatten().all(|v| *v != Value::Bot));
+    }
+
+    #[test]
+    fn test_weights() {
+        // Only the first node of the ring counts
+        let sol = BacktrackAlgorithm
+            .purest_solution(&not_ring(5), Some(&[3, 0, 0, 0, 0]), &Budget::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(sol.bot_cost, 3);
+
+        // Weights skip the gates, the last value node comes after them
+        let mut pc = not_ring(2);
+        let last = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+        let g = pc.add_node(NodeUnitialised::from_gate(Gate::Not), ());
+        pc.add_edge(last, g, ()).unwrap();
+        pc.add_edge(g, last, ()).unwrap();
+        let sol = BacktrackAlgorithm
+            .purest_solution(&pc, Some(&[0, 0, 7]), &Budget::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(sol.solution[last.index()], Some(Value::Bot));
+        assert_eq!(sol.bot_cost, 7);
+    }
+
+    #[test]
+    fn test_budget() {
+        let budget = Budget::default();
+        budget.cancellation.cancel();
+        assert_eq!(
+            BacktrackAlgorithm.purest_solution(&not_ring(5), None, &budget),
+            Err(SolverError::Cancelled)
+        );
+    }
+
+    #[test]
+    fn test_bound_prunes() {
+        let pc = not_ring(7);
+        // The only solution costs 7
+        let mut search = BacktrackAlgorithm
+            .solutions(&pc)
+            .unwrap()
+            .with_bot_bound(&[1; 14], 7);
+        assert_eq!(search.next(), None);
+        let mut search = BacktrackAlgorithm
+            .solutions(&pc)
+            .unwrap()
+            .with_bot_bound(&[1; 14], 8);
+        assert!(search.next().is_some());
+    }
+
+    proptest! {
+        #[test]
+        fn finds_purest(
+            pc in circuit_strategy(CircuitParams::new(1..=8, 0..=10)),
+            weights in prop::collection::vec(0..4usize, 20),
+        ) {
+            let solutions = BacktrackAlgorithm.calculate(&pc).unwrap();
+            let expected = solutions.iter().map(|s| cost(s, &weights)).min();
+            let sol = BacktrackAlgorithm
+                .purest_solution(&pc, Some(&weights), &Budget::default())
+                .unwrap();
+            prop_assert_eq!(sol.as_ref().map(|s| s.bot_cost), expected);
+            if let Some(sol) = sol {
+                prop_assert!(solutions.contains(&sol.solution));
+                prop_assert_eq!(cost(&sol.solution, &weights), sol.bot_cost);
+            }
+        }
+    }
+}
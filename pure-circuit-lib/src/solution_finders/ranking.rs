@@ -0,0 +1,413 @@
+use std::{collections::BinaryHeap, fmt::Debug, iter::FusedIterator, time::Instant};
+
+use crate::{
+    gates::Value,
+    graph::{GraphError, PureCircuitGraph},
+    solution_finders::{
+        backtracking::{BacktrackAlgorithm, CircuitTable, Propagation, SearchState},
+        nogood::NogoodStore,
+        solver_error::SolverError,
+        solver_trait::{Budget, BudgetMonitor, SolveStats},
+    },
+};
+
+/// Score of a possibly partial assignment, indexed like the solutions. Unassigned value nodes
+/// are None.
+///
+/// The search relies on the score being admissible: assigning more nodes never lowers it.
+/// Counting something over the assigned nodes only, as `bot_count` and `hamming_distance` do,
+/// satisfies this.
+pub type SolutionScore = Box<dyn Fn(&[Option<Value>]) -> usize + Send + Sync>;
+
+/// Solution yielded by `RankedSolutions`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RankedSolution {
+    pub solution: Vec<Option<Value>>,
+    pub score: usize,
+}
+
+/// Solutions returned by `BacktrackAlgorithm::enumerate_top_k`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopSolutions {
+    /// Best solutions, lowest score first
+    pub solutions: Vec<RankedSolution>,
+    /// The budget ran out before the `k` best solutions were proven
+    pub interrupted: bool,
+    pub stats: SolveStats,
+}
+
+/// Number of ⊥ values of the assignment
+pub fn bot_count(assignment: &[Option<Value>]) -> usize {
+    assignment
+        .iter()
+        .filter(|v| **v == Some(Value::Bot))
+        .count()
+}
+
+/// Number of assigned nodes that differ from `reference`.
+/// Nodes that are None in either assignment do not count
+pub fn hamming_distance(
+    reference: Vec<Option<Value>>,
+) -> impl Fn(&[Option<Value>]) -> usize + Send + Sync {
+    move |assignment| {
+        assignment
+            .iter()
+            .zip(reference.iter())
+            .filter(|(a, r)| matches!((a, r), (Some(a), Some(r)) if a != r))
+            .count()
+    }
+}
+
+/// Partial assignment on the frontier of the search.
+/// The frontier pops the lowest bound first, then the most assigned state, then the oldest
+#[derive(Debug)]
+struct Open {
+    bound: usize,
+    seq: usize,
+    state: SearchState,
+}
+
+impl Open {
+    fn key(&self) -> (std::cmp::Reverse<usize>, usize, std::cmp::Reverse<usize>) {
+        (
+            std::cmp::Reverse(self.bound),
+            self.state.assigned,
+            std::cmp::Reverse(self.seq),
+        )
+    }
+}
+
+impl PartialEq for Open {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Open {}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Open {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+/// Iterator over the assignments of a circuit in increasing score,
+/// created by `BacktrackAlgorithm::ranked_solutions`.
+///
+/// Best-first search over the partial assignments of the backtracker, keyed on their score.
+/// Since the score is admissible, a complete assignment leaving the frontier is at least as
+/// good as every solution not yielded yet. The frontier is kept in memory, hence memory grows
+/// with the number of partial assignments explored.
+pub struct RankedSolutions {
+    table: CircuitTable,
+    score: SolutionScore,
+    frontier: BinaryHeap<Open>,
+    seq: usize,
+    stats: SolveStats,
+    monitor: Option<BudgetMonitor>,
+}
+
+impl Debug for RankedSolutions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RankedSolutions")
+            .field("frontier", &self.frontier.len())
+            .field("stats", &self.stats)
+            .finish_non_exhaustive()
+    }
+}
+
+impl BacktrackAlgorithm {
+    /// Lazily enumerate the assignments of the circuit, lowest `score` first.
+    /// Solutions with equal scores come in search order
+    ///
+    /// # Errors
+    /// * InvalidArity: Exists gate with invalid arity
+    /// * EmptyGraph: Circuit does not contain any value nodes
+    pub fn ranked_solutions<T, G>(
+        &self,
+        pc_instance: &PureCircuitGraph<T, G>,
+        score: impl Fn(&[Option<Value>]) -> usize + Send + Sync + 'static,
+    ) -> Result<RankedSolutions, SolverError> {
+        let (table, state) = CircuitTable::new(pc_instance)?;
+        if state.queue.is_empty() {
+            return Err(SolverError::EmptyGraph);
+        }
+        let mut ranked = RankedSolutions {
+            table,
+            score: Box::new(score),
+            frontier: BinaryHeap::new(),
+            seq: 0,
+            stats: SolveStats::default(),
+            monitor: None,
+        };
+        ranked.push(state);
+        Ok(ranked)
+    }
+
+    /// Find the `k` solutions of the circuit with the lowest `score`, lowest first.
+    ///
+    /// # Parameters
+    /// * `score` - Admissible score, see `SolutionScore`
+    /// * `budget` - Stop once the budget runs out and return the solutions proven so far.
+    ///   Every branch counts as a node
+    ///
+    /// # Errors
+    /// * InvalidArity: Exists gate with invalid arity
+    /// * EmptyGraph: Circuit does not contain any value nodes
+    pub fn enumerate_top_k<T, G>(
+        &self,
+        pc_instance: &PureCircuitGraph<T, G>,
+        k: usize,
+        score: impl Fn(&[Option<Value>]) -> usize + Send + Sync + 'static,
+        budget: &Budget,
+    ) -> Result<TopSolutions, SolverError> {
+        let mut search = self
+            .ranked_solutions(pc_instance, score)?
+            .with_budget(budget);
+        let solutions = search
+            .by_ref()
+            .take(k)
+            .collect::<Result<Vec<_>, SolverError>>()?;
+        Ok(TopSolutions {
+            interrupted: solutions.len() < k && search.is_interrupted(),
+            solutions,
+            stats: search.stats(),
+        })
+    }
+}
+
+impl RankedSolutions {
+    /// Stop the search once the budget runs out. Every branch counts as a node.
+    /// The frontier is kept, so calling it again with a fresh budget resumes the search
+    pub fn with_budget(self, budget: &Budget) -> Self {
+        Self {
+            monitor: Some(budget.start()),
+            ..self
+        }
+    }
+
+    /// Whether the search was stopped by its budget
+    pub fn is_interrupted(&self) -> bool {
+        self.monitor
+            .as_ref()
+            .is_some_and(BudgetMonitor::was_interrupted)
+    }
+
+    /// Counters of the search so far. The wall time only covers the time spent in `next`
+    pub fn stats(&self) -> SolveStats {
+        self.stats
+    }
+
+    fn push(&mut self, state: SearchState) {
+        self.stats.max_depth = self.stats.max_depth.max(state.level);
+        self.frontier.push(Open {
+            bound: (self.score)(&state.sol_map),
+            seq: self.seq,
+            state,
+        });
+        self.seq += 1;
+    }
+
+    /// Expand the frontier until a complete assignment reaches its top
+    fn search(&mut self) -> Option<Result<RankedSolution, SolverError>> {
+        loop {
+            let Open {
+                bound,
+                seq,
+                mut state,
+            } = self.frontier.pop()?;
+            let Some((node, priority)) = state.queue.pop() else {
+                return Some(Ok(RankedSolution {
+                    solution: state.sol_map,
+                    score: bound,
+                }));
+            };
+            let Some(domain) = state.value_map[node.index()] else {
+                self.frontier.clear();
+                return Some(Err(GraphError::NotExistentNode.into()));
+            };
+            let mut remaining = domain;
+            for value in domain.to_value_iter() {
+                if self.monitor.as_ref().is_some_and(BudgetMonitor::tick) {
+                    // Put back the values not branched on yet, for a later budget
+                    state.value_map[node.index()] = Some(remaining);
+                    state.queue.push(node, priority);
+                    self.frontier.push(Open { bound, seq, state });
+                    return None;
+                }
+                remaining = remaining.remove(value);
+                self.stats.decisions += 1;
+                let mut branch = state.clone();
+                branch.level += 1;
+                match self.table.unit_propagate(
+                    node,
+                    value,
+                    &mut branch,
+                    &NogoodStore::default(),
+                    &mut self.stats,
+                ) {
+                    Ok(Propagation::Consistent) => self.push(branch),
+                    Ok(Propagation::Conflict { .. }) => (),
+                    Err(e) => {
+                        self.frontier.clear();
+                        return Some(Err(e));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for RankedSolutions {
+    type Item = Result<RankedSolution, SolverError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let started = Instant::now();
+        let ret = self.search();
+        self.stats.wall_time += started.elapsed();
+        ret
+    }
+}
+
+impl FusedIterator for RankedSolutions {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::purify_loop,
+        strategies::{CircuitParams, circuit_strategy},
+    };
+    use proptest::prelude::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_bot_order() {
+        let pc = purify_loop();
+        let top = BacktrackAlgorithm
+            .enumerate_top_k(&pc, 10, bot_count, &Budget::default())
+            .unwrap();
+        let mut expected = BacktrackAlgorithm
+            .calculate(&pc)
+            .unwrap()
+            .iter()
+            .map(|s| bot_count(s))
+            .collect::<Vec<_>>();
+        expected.sort_unstable();
+        let scores = top.solutions.iter().map(|s| s.score).collect::<Vec<_>>();
+        assert_eq!(scores, expected);
+        assert_eq!(scores[0], 0);
+        assert!(!top.interrupted);
+    }
+
+    #[test]
+    fn test_hamming() {
+        let pc = purify_loop();
+        let solutions = BacktrackAlgorithm.calculate(&pc).unwrap();
+        let reference = solutions.last().unwrap().clone();
+        let top = BacktrackAlgorithm
+            .enumerate_top_k(
+                &pc,
+                1,
+                hamming_distance(reference.clone()),
+                &Budget::default(),
+            )
+            .unwrap();
+        assert_eq!(
+            top.solutions,
+            vec![RankedSolution {
+                solution: reference,
+                score: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn test_empty_and_zero() {
+        assert_eq!(
+            BacktrackAlgorithm
+                .enumerate_top_k(
+                    &PureCircuitGraph::<(), ()>::new(),
+                    1,
+                    bot_count,
+                    &Budget::default()
+                )
+                .unwrap_err(),
+            SolverError::EmptyGraph
+        );
+        let top = BacktrackAlgorithm
+            .enumerate_top_k(&purify_loop(), 0, bot_count, &Budget::default())
+            .unwrap();
+        assert!(top.solutions.is_empty());
+        assert!(!top.interrupted);
+    }
+
+    #[test]
+    fn test_budget() {
+        let budget = Budget::default();
+        budget.cancellation.cancel();
+        let top = BacktrackAlgorithm
+            .enumerate_top_k(&purify_loop(), 1, bot_count, &budget)
+            .unwrap();
+        assert!(top.solutions.is_empty());
+        assert!(top.interrupted);
+    }
+
+    #[test]
+    fn test_resume() {
+        let pc = purify_loop();
+        let expected = BacktrackAlgorithm
+            .ranked_solutions(&pc, bot_count)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let mut search = BacktrackAlgorithm.ranked_solutions(&pc, bot_count).unwrap();
+        let mut found = vec![];
+        // Two branches per budget, every solution is still found once, lowest score first
+        loop {
+            search = search.with_budget(&Budget {
+                max_nodes: Some(2),
+                ..Default::default()
+            });
+            found.extend(search.by_ref().map(Result::unwrap));
+            if !search.is_interrupted() {
+                break;
+            }
+        }
+        let scores = |sols: &[RankedSolution]| sols.iter().map(|s| s.score).collect::<Vec<_>>();
+        assert_eq!(scores(&found), scores(&expected));
+        let solutions = |sols: Vec<RankedSolution>| {
+            sols.into_iter().map(|s| s.solution).collect::<HashSet<_>>()
+        };
+        assert_eq!(solutions(found), solutions(expected));
+    }
+
+    proptest! {
+        #[test]
+        fn ranks_every_solution(
+            pc in circuit_strategy(CircuitParams::new(1..=8, 0..=10)),
+            k in 0..20usize,
+        ) {
+            let solutions = BacktrackAlgorithm.calculate(&pc).unwrap();
+            let mut expected = solutions.iter().map(|s| bot_count(s)).collect::<Vec<_>>();
+            expected.sort_unstable();
+            expected.truncate(k);
+            let top = BacktrackAlgorithm
+                .enumerate_top_k(&pc, k, bot_count, &Budget::default())
+                .unwrap();
+            let scores = top.solutions.iter().map(|s| s.score).collect::<Vec<_>>();
+            prop_assert_eq!(scores, expected);
+            for (i, sol) in top.solutions.iter().enumerate() {
+                prop_assert!(solutions.contains(&sol.solution));
+                prop_assert_eq!(bot_count(&sol.solution), sol.score);
+                prop_assert!(!top.solutions[..i].iter().any(|s| s.solution == sol.solution));
+            }
+        }
+    }
+}
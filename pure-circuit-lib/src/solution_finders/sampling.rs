@@ -0,0 +1,420 @@
+use std::time::Instant;
+
+use petgraph::graph::NodeIndex;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::{
+    gates::Value,
+    graph::{GraphError, PureCircuitGraph},
+    solution_finders::{
+        backtracking::{BacktrackAlgorithm, CircuitTable, Propagation, SearchState},
+        mdd::SolutionDiagram,
+        nogood::NogoodStore,
+        solver_error::SolverError,
+        solver_trait::{Budget, BudgetMonitor, SolveStats},
+    },
+};
+
+/// Parameter set of `BacktrackAlgorithm::sample_solutions`
+#[derive(Debug, Clone)]
+pub struct SamplerParamSet {
+    pub samples: usize,
+    pub seed: Option<u64>,
+    /// Nodes the decision diagram may take to compile. Larger circuits are sampled by hashing
+    pub exact_limit: usize,
+    /// Largest hash cell a sample is drawn from
+    pub pivot: usize,
+    /// Hashes drawn for a single sample before it is given up
+    pub max_attempts: usize,
+    pub budget: Budget,
+}
+
+impl Default for SamplerParamSet {
+    fn default() -> Self {
+        Self {
+            samples: 1,
+            seed: None,
+            exact_limit: 100_000,
+            pivot: 32,
+            max_attempts: 64,
+            budget: Budget::default(),
+        }
+    }
+}
+
+/// How the samples were drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleMethod {
+    /// Weighted walk down the decision diagram of the solutions, exactly uniform
+    Exact,
+    /// Uniform pick from a random cell of a hash partition, approximately uniform
+    Hashing,
+}
+
+/// Samples returned by `BacktrackAlgorithm::sample_solutions`.
+/// Samples are indexed like the solutions of the backtracker
+#[derive(Debug, Clone)]
+pub struct SampleReturn {
+    pub samples: Vec<Vec<Option<Value>>>,
+    pub method: SampleMethod,
+    /// The budget ran out, or hashing gave up on a sample, before every sample was drawn
+    pub interrupted: bool,
+    pub stats: SolveStats,
+}
+
+/// Random linear equation over the values of the nodes modulo 3.
+/// Every equation splits the solutions into three cells of about the same size
+#[derive(Debug, Clone)]
+struct Hash {
+    /// Nodes with a nonzero coefficient
+    terms: Vec<(usize, u8)>,
+    rhs: u8,
+}
+
+impl Hash {
+    fn random(nodes: &[usize], rng: &mut StdRng) -> Self {
+        Self {
+            terms: nodes
+                .iter()
+                .map(|n| (*n, rng.random_range(0..3)))
+                .filter(|(_, a)| *a > 0)
+                .collect(),
+            rhs: rng.random_range(0..3),
+        }
+    }
+}
+
+/// Search for the solutions of a hash cell
+struct CellSearch<'a> {
+    table: &'a CircuitTable,
+    monitor: &'a BudgetMonitor,
+    stats: &'a mut SolveStats,
+}
+
+impl CellSearch<'_> {
+    /// Collect up to `limit` solutions of `state` that satisfy every hash
+    fn collect(
+        &mut self,
+        state: &SearchState,
+        hashes: &[Hash],
+        limit: usize,
+        out: &mut Vec<Vec<Option<Value>>>,
+    ) -> Result<(), SolverError> {
+        let mut state = state.clone();
+        let Some((node, _)) = state.queue.pop() else {
+            out.push(state.sol_map);
+            return Ok(());
+        };
+        let domain = state.value_map[node.index()].ok_or(GraphError::NotExistentNode)?;
+        for value in domain.to_value_iter() {
+            if out.len() >= limit || self.monitor.tick() {
+                return Ok(());
+            }
+            self.stats.decisions += 1;
+            let mut branch = state.clone();
+            let propagation = self.table.unit_propagate(
+                node,
+                value,
+                &mut branch,
+                &NogoodStore::default(),
+                self.stats,
+            )?;
+            if propagation == Propagation::Consistent && self.propagate(&mut branch, hashes)? {
+                self.collect(&branch, hashes, limit, out)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fix the last unassigned node of every hash, until no hash has a single one left
+    /// # Returns
+    /// false if a gate or a hash is violated
+    fn propagate(&mut self, state: &mut SearchState, hashes: &[Hash]) -> Result<bool, SolverError> {
+        loop {
+            let mut changed = false;
+            for hash in hashes {
+                let mut sum = 0u8;
+                let mut open = None;
+                let mut open_count = 0;
+                for (n, a) in hash.terms.iter() {
+                    match state.sol_map[*n] {
+                        Some(v) => sum = (sum + a * v.index() as u8) % 3,
+                        None => {
+                            open = Some((*n, *a));
+                            open_count += 1;
+                        }
+                    }
+                }
+                match (open_count, open) {
+                    (0, _) if sum != hash.rhs => return Ok(false),
+                    (1, Some((n, a))) => {
+                        // a is its own inverse modulo 3
+                        let value = Value::ALL[((hash.rhs + 3 - sum) * a % 3) as usize];
+                        let domain = state.value_map[n].ok_or(GraphError::NotExistentNode)?;
+                        if !domain.contains(value) {
+                            return Ok(false);
+                        }
+                        let propagation = self.table.unit_propagate(
+                            NodeIndex::new(n),
+                            value,
+                            state,
+                            &NogoodStore::default(),
+                            self.stats,
+                        )?;
+                        if propagation != Propagation::Consistent {
+                            return Ok(false);
+                        }
+                        changed = true;
+                    }
+                    _ => (),
+                }
+            }
+            if !changed {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+impl BacktrackAlgorithm {
+    /// Draw random solutions of the circuit.
+    ///
+    /// Circuits whose decision diagram compiles within `exact_limit` nodes are sampled exactly,
+    /// weighting every branch by its number of solutions. Larger circuits add random linear
+    /// equations modulo 3 until a random cell holds at most `pivot` solutions, which are then
+    /// enumerated and picked from uniformly.
+    ///
+    /// # Parameters
+    /// * `budget` - Every node of the diagram and every branch of the cell searches counts
+    ///   as a node, hashing continues on what the diagram left. The samples drawn so far are
+    ///   returned once the budget runs out
+    ///
+    /// # Errors
+    /// * InvalidArity: Exists gate with invalid arity
+    /// * EmptyGraph: Circuit does not contain any value nodes
+    /// * NoSolution: Circuit has no solution
+    /// * Cancelled: The budget ran out before it was known how to sample
+    pub fn sample_solutions<T, G>(
+        &self,
+        pc_instance: &PureCircuitGraph<T, G>,
+        param_set: &SamplerParamSet,
+    ) -> Result<SampleReturn, SolverError> {
+        let started = Instant::now();
+        let mut rng = match param_set.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+        // Both phases share the budget, the nodes the diagram took are not given back
+        let monitor = param_set.budget.start();
+        // None if the diagram needs more than `exact_limit` nodes
+        let diagram =
+            SolutionDiagram::compile_within(pc_instance, &monitor, param_set.exact_limit)?;
+        if let Some(diagram) = diagram {
+            if diagram.is_empty() {
+                return Err(SolverError::NoSolution);
+            }
+            return Ok(SampleReturn {
+                samples: (0..param_set.samples)
+                    .filter_map(|_| diagram.sample(&mut rng))
+                    .collect(),
+                method: SampleMethod::Exact,
+                interrupted: false,
+                stats: SolveStats {
+                    wall_time: started.elapsed(),
+                    ..Default::default()
+                },
+            });
+        }
+
+        let (table, root) = CircuitTable::new(pc_instance)?;
+        let nodes = (0..root.value_map.len())
+            .filter(|n| root.value_map[*n].is_some())
+            .collect::<Vec<_>>();
+        let mut stats = SolveStats::default();
+        let mut search = CellSearch {
+            table: &table,
+            monitor: &monitor,
+            stats: &mut stats,
+        };
+        let mut cell = vec![];
+        search.collect(&root, &[], param_set.pivot + 1, &mut cell)?;
+        if cell.is_empty() && !monitor.was_interrupted() {
+            return Err(SolverError::NoSolution);
+        }
+        if monitor.was_interrupted() {
+            return Err(SolverError::Cancelled);
+        }
+
+        let mut samples = vec![];
+        let mut equations = 1;
+        'samples: while samples.len() < param_set.samples {
+            if cell.len() <= param_set.pivot {
+                // Few enough solutions to pick from all of them
+                samples.push(cell[rng.random_range(0..cell.len())].clone());
+                continue;
+            }
+            for _ in 0..param_set.max_attempts {
+                let hashes = (0..equations)
+                    .map(|_| Hash::random(&nodes, &mut rng))
+                    .collect::<Vec<_>>();
+                let mut state = root.clone();
+                let mut found = vec![];
+                if search.propagate(&mut state, &hashes)? {
+                    search.collect(&state, &hashes, param_set.pivot + 1, &mut found)?;
+                }
+                if monitor.was_interrupted() {
+                    break 'samples;
+                }
+                match found.len() {
+                    0 => equations = (equations - 1).max(1),
+                    n if n > param_set.pivot => equations += 1,
+                    n => {
+                        samples.push(found.swap_remove(rng.random_range(0..n)));
+                        continue 'samples;
+                    }
+                }
+            }
+            break;
+        }
+        Ok(SampleReturn {
+            interrupted: samples.len() < param_set.samples,
+            samples,
+            method: SampleMethod::Hashing,
+            stats: SolveStats {
+                wall_time: started.elapsed(),
+                ..stats
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::copy_pairs,
+        gates::{Gate, NodeUnitialised},
+        strategies::{CircuitParams, circuit_strategy},
+    };
+    use proptest::prelude::*;
+    fn hashing(samples: usize, seed: u64) -> SamplerParamSet {
+        SamplerParamSet {
+            samples,
+            seed: Some(seed),
+            exact_limit: 0,
+            pivot: 4,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_exact() {
+        let mut pc = PureCircuitGraph::new();
+        let a = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+        let b = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+        let c = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+        let g = pc.add_node(NodeUnitialised::from_gate(Gate::And), ());
+        pc.add_edge(a, g, ()).unwrap();
+        pc.add_edge(b, g, ()).unwrap();
+        pc.add_edge(g, c, ()).unwrap();
+        let params = SamplerParamSet {
+            samples: 20,
+            seed: Some(3),
+            ..Default::default()
+        };
+        let ret = BacktrackAlgorithm.sample_solutions(&pc, &params).unwrap();
+        assert_eq!(ret.method, SampleMethod::Exact);
+        assert_eq!(ret.samples.len(), 20);
+        assert!(!ret.interrupted);
+        let solutions = BacktrackAlgorithm.calculate(&pc).unwrap();
+        for sample in ret.samples.iter() {
+            assert!(solutions.contains(sample));
+            pc.from_backtrack_sol(sample).unwrap();
+        }
+        let again = BacktrackAlgorithm.sample_solutions(&pc, &params).unwrap();
+        assert_eq!(again.samples, ret.samples);
+    }
+
+    #[test]
+    fn test_hashing_spread() {
+        // 729 solutions, far more than the pivot
+        let ret = BacktrackAlgorithm
+            .sample_solutions(&copy_pairs(6), &hashing(300, 7))
+            .unwrap();
+        assert_eq!(ret.method, SampleMethod::Hashing);
+        assert_eq!(ret.samples.len(), 300);
+        for value in [Value::Bot, Value::Zero, Value::One] {
+            let hits = ret.samples.iter().filter(|s| s[0] == Some(value)).count();
+            assert!((60..=140).contains(&hits), "{value:?} drawn {hits} times");
+        }
+    }
+
+    #[test]
+    fn test_budget() {
+        let params = SamplerParamSet {
+            budget: Budget::default(),
+            ..hashing(1, 0)
+        };
+        params.budget.cancellation.cancel();
+        assert_eq!(
+            BacktrackAlgorithm
+                .sample_solutions(&copy_pairs(3), &params)
+                .unwrap_err(),
+            SolverError::Cancelled
+        );
+    }
+
+    #[test]
+    fn test_shared_budget() {
+        let params = SamplerParamSet {
+            budget: Budget {
+                max_nodes: Some(1000),
+                ..Default::default()
+            },
+            ..hashing(1, 0)
+        };
+        let ret = BacktrackAlgorithm
+            .sample_solutions(&copy_pairs(6), &params)
+            .unwrap();
+        assert_eq!(ret.method, SampleMethod::Hashing);
+        assert_eq!(ret.samples.len(), 1);
+
+        // The diagram takes the whole budget, which is not renewed for hashing
+        let params = SamplerParamSet {
+            exact_limit: 100_000,
+            ..params
+        };
+        assert_eq!(
+            BacktrackAlgorithm
+                .sample_solutions(&copy_pairs(6), &params)
+                .unwrap_err(),
+            SolverError::Cancelled
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn samples_are_solutions(
+            pc in circuit_strategy(CircuitParams::new(1..=8, 0..=10)),
+            seed in any::<u64>(),
+            exact in any::<bool>(),
+        ) {
+            let params = SamplerParamSet {
+                exact_limit: if exact { 100_000 } else { 0 },
+                ..hashing(5, seed)
+            };
+            let solutions = BacktrackAlgorithm.calculate(&pc).unwrap();
+            let ret = BacktrackAlgorithm.sample_solutions(&pc, &params);
+            if solutions.is_empty() {
+                prop_assert_eq!(ret.unwrap_err(), SolverError::NoSolution);
+                return Ok(());
+            }
+            let ret = ret.unwrap();
+            prop_assert!(!ret.interrupted);
+            prop_assert_eq!(ret.samples.len(), 5);
+            for sample in ret.samples.iter() {
+                prop_assert!(solutions.contains(sample));
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::fmt::Display;
+
+use petgraph::graph::NodeIndex;
+
+use crate::{
+    gates::{Gate, GateError},
+    graph::GraphError,
+};
+
+/// Errors reported by the solution finders
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SolverError {
+    /// Gate does not have the number of inputs/outputs its type requires
+    InvalidArity { gate_index: NodeIndex },
+    /// Assigned port values cannot satisfy the gate
+    /// The gate index is known only when the gate was reached through the graph
+    InconsistentAssignment {
+        gate: Gate,
+        gate_index: Option<NodeIndex>,
+    },
+    /// Solution is missing the value of a value node
+    MissingAssignment { node_index: NodeIndex },
+    /// Chromosome is shorter than the number of value nodes it should describe
+    InvalidAssignmentLength { expected: usize, found: usize },
+    /// Graph does not contain any value node
+    EmptyGraph,
+    /// Genotype of the meta-heuristic could not be built
+    GenotypeBuild(String),
+    /// Meta-heuristic strategy could not be built
+    StrategyBuild(String),
+    /// Solver finished without producing any candidate
+    NoSolution,
+    /// Solver was cancelled before producing any result
+    Cancelled,
+    /// Underlying graph operation failed
+    Graph(GraphError),
+    /// Gate evaluation failed
+    Gate(GateError),
+}
+
+impl Display for SolverError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidArity { gate_index } => {
+                write!(f, "Gate {} does not satisfy its arity", gate_index.index())
+            }
+            Self::InconsistentAssignment {
+                gate,
+                gate_index: Some(gate_index),
+            } => write!(
+                f,
+                "Assignment of gate {gate} ({}) is inconsistent",
+                gate_index.index()
+            ),
+            Self::InconsistentAssignment {
+                gate,
+                gate_index: None,
+            } => write!(f, "Assignment of gate {gate} is inconsistent"),
+            Self::MissingAssignment { node_index } => {
+                write!(f, "Value node {} has not been assigned", node_index.index())
+            }
+            Self::InvalidAssignmentLength { expected, found } => write!(
+                f,
+                "Assignment has {found} values but the circuit requires {expected}"
+            ),
+            Self::EmptyGraph => write!(f, "Circuit does not contain any value nodes"),
+            Self::GenotypeBuild(e) => write!(f, "Unable to build genotype: {e}"),
+            Self::StrategyBuild(e) => write!(f, "Unable to build strategy: {e}"),
+            Self::NoSolution => write!(f, "Solver did not produce any candidate"),
+            Self::Cancelled => write!(f, "Solver was cancelled"),
+            Self::Graph(e) => write!(f, "Graph error: {e}"),
+            Self::Gate(e) => write!(f, "Gate error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for SolverError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Graph(e) => Some(e),
+            Self::Gate(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<GateError> for SolverError {
+    fn from(value: GateError) -> Self {
+        Self::Gate(value)
+    }
+}
+
+impl From<GraphError> for SolverError {
+    fn from(value: GraphError) -> Self {
+        Self::Graph(value)
+    }
+}
@@ -1,4 +1,13 @@
-use anyhow::Result;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use crate::solution_finders::solver_error::SolverError;
+
 /// A trait for solvers that can find solutions given a set of parameters.
 /// 
 /// # Associated Types
@@ -17,6 +26,183 @@ pub trait SolverTrait {
     /// * `param_set` - The parameters needed to solve the problem
     /// 
     /// # Returns
-    /// Returns the solution if successful, or an error if solving fails.
-    fn find_solution(&self, param_set: Self::ParamSet) -> Result<Self::Solution>;
+    /// Returns the solution if successful, or a `SolverError` if solving fails.
+    fn find_solution(&self, param_set: Self::ParamSet) -> Result<Self::Solution, SolverError>;
+}
+
+/// Counters collected while a solver runs.
+/// Counters that do not apply to a solver are left at zero.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SolveStats {
+    /// Time spent solving
+    pub wall_time: Duration,
+    /// Values fixed by unit propagation
+    pub propagations: usize,
+    /// Branching decisions of the search
+    pub decisions: usize,
+    /// Decisions that ended in a violated gate
+    pub conflicts: usize,
+    /// Deepest branching point of the search
+    pub max_depth: usize,
+    /// Restarts of the backtracker
+    pub restarts: usize,
+    /// Generations of the meta-heuristics, summed over every run
+    pub generations: usize,
+    /// Budget nodes ticked by the meta-heuristics and local searches, one per fitness evaluation
+    /// or evaluated move
+    pub fitness_evaluations: usize,
+    /// Fitness cache hits of the meta-heuristics, component cache hits of the counter
+    pub cache_hits: usize,
+}
+
+/// Shared flag used to stop a running solver, e.g. from another thread.
+/// Clones refer to the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request every solver holding the token to stop
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Limits that solvers check cooperatively. `None` means unlimited.
+///
+/// A solver that runs out of budget returns its best partial result flagged as interrupted.
+#[derive(Debug, Clone, Default)]
+pub struct Budget {
+    pub cancellation: CancellationToken,
+    /// Wall-clock time
+    pub time_limit: Option<Duration>,
+    /// Search nodes of the backtracker, fitness evaluations of the meta-heuristics
+    pub max_nodes: Option<usize>,
+    /// Generations of the meta-heuristics
+    pub max_generations: Option<usize>,
+}
+
+impl Budget {
+    /// Start measuring the budget
+    pub fn start(&self) -> BudgetMonitor {
+        BudgetMonitor {
+            budget: self.clone(),
+            started: Instant::now(),
+            nodes: Arc::new(AtomicUsize::new(0)),
+            interrupted: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+/// Running budget of a solver. Clones share the node count, so a monitor can be handed to
+/// several workers.
+#[derive(Debug, Clone)]
+pub struct BudgetMonitor {
+    budget: Budget,
+    started: Instant,
+    nodes: Arc<AtomicUsize>,
+    interrupted: Arc<AtomicBool>,
+}
+
+impl BudgetMonitor {
+    /// Record an explored node
+    /// # Returns
+    /// true if the solver should stop
+    pub fn tick(&self) -> bool {
+        let nodes = self.nodes.fetch_add(1, Ordering::Relaxed) + 1;
+        self.check(nodes)
+    }
+
+    /// Check the budget without recording a node
+    /// # Returns
+    /// true if the solver should stop
+    pub fn is_exhausted(&self) -> bool {
+        self.check(self.nodes.load(Ordering::Relaxed))
+    }
+
+    fn check(&self, nodes: usize) -> bool {
+        if self.interrupted.load(Ordering::Relaxed) {
+            return true;
+        }
+        let exhausted = self.budget.cancellation.is_cancelled()
+            || self.budget.max_nodes.is_some_and(|m| nodes > m)
+            || self
+                .budget
+                .time_limit
+                .is_some_and(|t| self.started.elapsed() >= t);
+        if exhausted {
+            self.interrupted.store(true, Ordering::Relaxed);
+        }
+        exhausted
+    }
+
+    /// Whether the budget ran out at some point
+    pub fn was_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::Relaxed)
+    }
+
+    /// Nodes recorded so far
+    pub fn nodes(&self) -> usize {
+        self.nodes.load(Ordering::Relaxed)
+    }
+
+    pub fn budget(&self) -> &Budget {
+        &self.budget
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unlimited() {
+        let monitor = Budget::default().start();
+        assert!((0..1000).all(|_| !monitor.tick()));
+        assert_eq!(monitor.nodes(), 1000);
+        assert!(!monitor.was_interrupted());
+    }
+
+    #[test]
+    fn test_node_limit() {
+        let monitor = Budget {
+            max_nodes: Some(3),
+            ..Default::default()
+        }
+        .start();
+        assert!(!monitor.tick());
+        assert!(!monitor.tick());
+        assert!(!monitor.tick());
+        assert!(monitor.tick());
+        assert!(monitor.was_interrupted());
+    }
+
+    #[test]
+    fn test_cancellation_is_shared() {
+        let budget = Budget::default();
+        let monitor = budget.start();
+        let other = monitor.clone();
+        assert!(!monitor.is_exhausted());
+        budget.cancellation.cancel();
+        assert!(other.is_exhausted());
+        assert!(monitor.was_interrupted());
+    }
+
+    #[test]
+    fn test_time_limit() {
+        let monitor = Budget {
+            time_limit: Some(Duration::ZERO),
+            ..Default::default()
+        }
+        .start();
+        assert!(monitor.is_exhausted());
+    }
 }
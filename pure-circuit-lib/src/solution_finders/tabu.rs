@@ -0,0 +1,472 @@
+use std::time::Instant;
+
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use strum::IntoEnumIterator;
+
+use crate::{
+    gates::Value,
+    solution_finders::{
+        base_finder::FitnessPureCircuit,
+        evo_search::{Build, Instance, SolutionReturn, SolverStruct},
+        observer::{SharedObserver, SolverEvent},
+        solver_error::SolverError,
+        solver_trait::{Budget, BudgetMonitor, SolveStats, SolverTrait},
+    },
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+/// Proxy type for the tabu search algorithm
+pub struct TabuAlgorithm;
+
+pub type SolverTabu = SolverStruct<TabuAlgorithm>;
+
+#[derive(Debug, Clone)]
+/// Parameter set of the tabu search algorithm
+pub struct TabuParamSet<T> {
+    pub param_type: T,
+    /// Iterations during which a value node cannot return to the value it just left
+    pub tenure: usize,
+    /// Stop after this many iterations without improving the best score
+    pub stale_generations: usize,
+    /// Seed of the initial assignment and of the tie breaks, None draws one from the OS
+    pub seed: Option<u64>,
+    pub budget: Budget,
+    pub observer: Option<SharedObserver>,
+}
+
+impl Default for TabuParamSet<Build> {
+    fn default() -> Self {
+        Self {
+            param_type: Build,
+            tenure: 10,
+            stale_generations: 1000,
+            seed: None,
+            budget: Budget::default(),
+            observer: None,
+        }
+    }
+}
+
+impl TabuParamSet<Build> {
+    pub fn build(
+        &self,
+        instance: Instance<FitnessPureCircuit>,
+    ) -> TabuParamSet<Instance<FitnessPureCircuit>> {
+        TabuParamSet {
+            param_type: instance,
+            tenure: self.tenure,
+            stale_generations: self.stale_generations,
+            seed: self.seed,
+            budget: self.budget.clone(),
+            observer: self.observer.clone(),
+        }
+    }
+}
+
+/// Assignment of a tabu search run, with the violated gates kept up to date
+#[derive(Debug)]
+struct TabuState {
+    fitness: FitnessPureCircuit,
+    current: Vec<Value>,
+    violated: Vec<bool>,
+    errors: usize,
+    best: (Vec<Value>, usize),
+    /// First iteration at which each (node, value) pair is allowed again
+    tabu: Vec<[usize; 3]>,
+    tenure: usize,
+    iteration: usize,
+}
+
+impl TabuState {
+    fn new(
+        fitness: FitnessPureCircuit,
+        current: Vec<Value>,
+        tenure: usize,
+    ) -> Result<Self, SolverError> {
+        if current.len() < fitness.required_len() {
+            return Err(SolverError::InvalidAssignmentLength {
+                expected: fitness.required_len(),
+                found: current.len(),
+            });
+        }
+        let violated = (0..fitness.gate_count())
+            .map(|gate| fitness.violated(gate, &current))
+            .collect::<Result<Vec<_>, _>>()?;
+        let errors = violated.iter().filter(|v| **v).count();
+        Ok(Self {
+            tabu: vec![[0; 3]; current.len()],
+            best: (current.clone(), errors),
+            fitness,
+            current,
+            violated,
+            errors,
+            tenure,
+            iteration: 0,
+        })
+    }
+
+    /// Value nodes connected to a violated gate, in ascending order
+    fn candidates(&self) -> Vec<usize> {
+        let mut nodes = self
+            .violated
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| **v)
+            .flat_map(|(gate, _)| self.fitness.gate_nodes(gate))
+            .collect::<Vec<_>>();
+        nodes.sort_unstable();
+        nodes.dedup();
+        nodes
+    }
+
+    fn is_tabu(&self, node: usize, value: Value) -> bool {
+        self.tabu[node][value.index()] > self.iteration
+    }
+
+    /// Number of violated gates if `node` took `value`
+    fn errors_after(&mut self, node: usize, value: Value) -> Result<usize, SolverError> {
+        let old = std::mem::replace(&mut self.current[node], value);
+        let errors = self
+            .fitness
+            .evaluate_delta(self.errors, &[(node, old)], &self.current);
+        self.current[node] = old;
+        errors
+    }
+
+    /// Best admissible move among the candidates, ties are broken at random.
+    /// A tabu move is admissible if it beats the best score (aspiration).
+    /// If every move is tabu, the one whose tenure ends first is taken.
+    /// # Returns
+    /// None once the budget runs out, every evaluated move counts as a node
+    fn select(
+        &mut self,
+        rng: &mut StdRng,
+        monitor: &BudgetMonitor,
+    ) -> Result<Option<(usize, Value)>, SolverError> {
+        let mut best: Option<(usize, (usize, Value))> = None;
+        let mut ties = 0;
+        let mut oldest: Option<(usize, (usize, Value))> = None;
+        for node in self.candidates() {
+            let current = self.current[node];
+            for value in Value::iter().filter(|v| *v != current) {
+                if monitor.tick() {
+                    return Ok(None);
+                }
+                let errors = self.errors_after(node, value)?;
+                let aspiration = errors < self.best.1;
+                if self.is_tabu(node, value) && !aspiration {
+                    let expiry = self.tabu[node][value.index()];
+                    if oldest.is_none_or(|(e, _)| expiry < e) {
+                        oldest = Some((expiry, (node, value)));
+                    }
+                    continue;
+                }
+                match best {
+                    Some((e, _)) if errors > e => {}
+                    Some((e, _)) if errors == e => {
+                        ties += 1;
+                        if rng.random_range(0..ties) == 0 {
+                            best = Some((errors, (node, value)));
+                        }
+                    }
+                    _ => {
+                        ties = 1;
+                        best = Some((errors, (node, value)));
+                    }
+                }
+            }
+        }
+        Ok(best.map(|(_, m)| m).or(oldest.map(|(_, m)| m)))
+    }
+
+    /// Assign `value` to `node`, the value it leaves becomes tabu
+    fn apply(&mut self, node: usize, value: Value) -> Result<(), SolverError> {
+        let old = std::mem::replace(&mut self.current[node], value);
+        self.tabu[node][old.index()] = self.iteration + 1 + self.tenure;
+        self.iteration += 1;
+        for gate in self.fitness.incident_gates(node) {
+            let violated = self.fitness.violated(*gate, &self.current)?;
+            match (self.violated[*gate], violated) {
+                (false, true) => self.errors += 1,
+                (true, false) => self.errors -= 1,
+                _ => {}
+            }
+            self.violated[*gate] = violated;
+        }
+        if self.errors < self.best.1 {
+            self.best = (self.current.clone(), self.errors);
+        }
+        Ok(())
+    }
+}
+
+impl SolverTrait for SolverTabu {
+    type ParamSet = TabuParamSet<Instance<FitnessPureCircuit>>;
+
+    type Solution = SolutionReturn;
+
+    // Tabu search over the assignments, one value node next to a violated gate changes per move
+    fn find_solution(&self, param_set: Self::ParamSet) -> Result<Self::Solution, SolverError> {
+        let started = Instant::now();
+        let mut rng = match param_set.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+        let monitor = param_set.budget.start();
+        if monitor.tick() {
+            return Err(SolverError::Cancelled);
+        }
+        let values = Value::iter().collect::<Vec<_>>();
+        let initial = (0..param_set.param_type.size)
+            .map(|_| values[rng.random_range(0..values.len())])
+            .collect::<Vec<_>>();
+        let mut state = TabuState::new(param_set.param_type.func, initial, param_set.tenure)?;
+        let notify = |event| {
+            if let Some(observer) = &param_set.observer {
+                observer.on_event(event);
+            }
+        };
+
+        let mut stale = 0;
+        let mut generation_capped = false;
+        while state.best.1 > 0 && stale < param_set.stale_generations {
+            if param_set
+                .budget
+                .max_generations
+                .is_some_and(|m| state.iteration >= m)
+            {
+                generation_capped = true;
+                break;
+            }
+            let Some((node, value)) = state.select(&mut rng, &monitor)? else {
+                break;
+            };
+            let before = state.best.1;
+            state.apply(node, value)?;
+            stale = match state.best.1 < before {
+                true => 0,
+                false => stale + 1,
+            };
+            notify(SolverEvent::Generation {
+                generation: state.iteration,
+                best_fitness: Some(isize::try_from(state.best.1).unwrap_or(isize::MAX)),
+            });
+        }
+
+        let (chromosone, errors) = state.best;
+        let interrupted = monitor.was_interrupted() || (generation_capped && errors > 0);
+        notify(SolverEvent::Finished { interrupted });
+        Ok(SolutionReturn {
+            chromosone,
+            errors,
+            interrupted,
+            stats: SolveStats {
+                wall_time: started.elapsed(),
+                generations: state.iteration,
+                fitness_evaluations: monitor.nodes(),
+                ..Default::default()
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{not_chain, not_ring},
+        graph::PureCircuitGraph,
+        solution_finders::observer::ChannelObserver,
+        strategies::{CircuitParams, circuit_strategy},
+    };
+    use proptest::prelude::*;
+
+    fn params(
+        pc: &PureCircuitGraph,
+        set: TabuParamSet<Build>,
+    ) -> TabuParamSet<Instance<FitnessPureCircuit>> {
+        let instance = Instance::new(pc.to_fitness_function().unwrap(), pc.count_values());
+        set.build(instance)
+    }
+
+    fn ring_state(values: &[Value], tenure: usize) -> TabuState {
+        let fitness = not_ring(values.len()).to_fitness_function().unwrap();
+        TabuState::new(fitness, values.to_vec(), tenure).unwrap()
+    }
+
+    #[test]
+    fn test_solves_chain() {
+        let pc = not_chain(20);
+        let fitness = pc.to_fitness_function().unwrap();
+        for seed in 0..10 {
+            let sol = SolverTabu::default()
+                .find_solution(params(
+                    &pc,
+                    TabuParamSet {
+                        seed: Some(seed),
+                        ..Default::default()
+                    },
+                ))
+                .unwrap();
+            assert_eq!(sol.errors, 0);
+            assert_eq!(fitness.evaluate(&sol.chromosone), Ok(0));
+            assert!(!sol.interrupted);
+        }
+    }
+
+    #[test]
+    fn test_candidates() {
+        use Value::*;
+        // Only the gate from node 4 to node 0 is violated
+        let state = ring_state(&[Zero, One, Zero, One, Zero], 3);
+        assert_eq!(state.errors, 1);
+        assert_eq!(state.candidates(), vec![0, 4]);
+        let state = ring_state(&[Bot; 5], 3);
+        assert!(state.candidates().is_empty());
+    }
+
+    #[test]
+    fn test_tenure() {
+        use Value::*;
+        let mut state = ring_state(&[Zero, One, Zero, One, Zero], 2);
+        state.apply(0, Bot).unwrap();
+        assert!(state.is_tabu(0, Zero));
+        assert!(!state.is_tabu(0, One));
+        // The move back restores the best score and is not aspirated
+        let mut rng = StdRng::seed_from_u64(0);
+        let monitor = Budget::default().start();
+        for _ in 0..10 {
+            let mut state = ring_state(&[Zero, One, Zero, One, Zero], 2);
+            state.apply(0, Bot).unwrap();
+            assert_ne!(state.select(&mut rng, &monitor).unwrap(), Some((0, Zero)));
+        }
+        state.apply(1, Bot).unwrap();
+        assert!(state.is_tabu(0, Zero));
+        state.apply(2, Bot).unwrap();
+        assert!(!state.is_tabu(0, Zero));
+    }
+
+    #[test]
+    fn test_aspiration() {
+        use Value::*;
+        let monitor = Budget::default().start();
+        let mut rng = StdRng::seed_from_u64(0);
+        // Only the gate from node 4 to node 0 is violated, only Bot on node 4 fixes it
+        let mut state = ring_state(&[Bot, Bot, Bot, Bot, Zero], 5);
+        state.tabu = vec![[50; 3]; 5];
+        assert_eq!(state.select(&mut rng, &monitor).unwrap(), Some((4, Bot)));
+        // Without aspiration, the move whose tenure ends first is taken
+        state.best.1 = 0;
+        state.tabu[0] = [40; 3];
+        assert_eq!(
+            state.select(&mut rng, &monitor).unwrap().map(|m| m.0),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn test_seeded() {
+        let pc = not_ring(15);
+        let run = |seed| {
+            SolverTabu::default()
+                .find_solution(params(
+                    &pc,
+                    TabuParamSet {
+                        seed: Some(seed),
+                        budget: Budget {
+                            max_nodes: Some(300),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                ))
+                .unwrap()
+        };
+        let (a, b) = (run(4), run(4));
+        assert_eq!(a.chromosone, b.chromosone);
+        assert_eq!(a.stats.generations, b.stats.generations);
+    }
+
+    #[test]
+    fn test_budget() {
+        let pc = not_ring(9);
+        let budget = Budget::default();
+        budget.cancellation.cancel();
+        assert_eq!(
+            SolverTabu::default()
+                .find_solution(params(
+                    &pc,
+                    TabuParamSet {
+                        budget,
+                        ..Default::default()
+                    }
+                ))
+                .unwrap_err(),
+            SolverError::Cancelled
+        );
+
+        let sol = SolverTabu::default()
+            .find_solution(params(
+                &pc,
+                TabuParamSet {
+                    seed: Some(0),
+                    budget: Budget {
+                        max_generations: Some(1),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            ))
+            .unwrap();
+        assert!(sol.stats.generations <= 1);
+        assert!(sol.interrupted || sol.errors == 0);
+        assert_eq!(
+            pc.to_fitness_function().unwrap().evaluate(&sol.chromosone),
+            Ok(sol.errors)
+        );
+    }
+
+    #[test]
+    fn test_observer() {
+        let (observer, receiver) = ChannelObserver::new();
+        let sol = SolverTabu::default()
+            .find_solution(params(
+                &not_ring(9),
+                TabuParamSet {
+                    seed: Some(5),
+                    observer: Some(observer.shared()),
+                    ..Default::default()
+                },
+            ))
+            .unwrap();
+        let events = receiver.try_iter().collect::<Vec<_>>();
+        assert_eq!(
+            events.last(),
+            Some(&SolverEvent::Finished {
+                interrupted: sol.interrupted
+            })
+        );
+        assert_eq!(events.len(), sol.stats.generations + 1);
+    }
+
+    proptest! {
+        #[test]
+        fn errors_are_tracked(
+            pc in circuit_strategy(CircuitParams::new(1..=10, 0..=14)),
+            seed in any::<u64>(),
+        ) {
+            let fitness = pc.to_fitness_function().unwrap();
+            let sol = SolverTabu::default()
+                .find_solution(params(
+                    &pc,
+                    TabuParamSet {
+                        seed: Some(seed),
+                        stale_generations: 50,
+                        ..Default::default()
+                    },
+                ))
+                .unwrap();
+            prop_assert_eq!(fitness.evaluate(&sol.chromosone), Ok(sol.errors));
+        }
+    }
+}
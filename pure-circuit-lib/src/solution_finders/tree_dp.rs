@@ -0,0 +1,662 @@
+use std::{collections::BTreeSet, iter::FusedIterator, time::Instant};
+
+use petgraph::graph::NodeIndex;
+
+use crate::{
+    gates::Value,
+    graph::{GraphError, PureCircuitGraph},
+    solution_finders::{
+        backtracking::{BacktrackAlgorithm, CircuitTable, SolutionIter},
+        counting::SolutionCount,
+        solver_error::SolverError,
+        solver_trait::{Budget, BudgetMonitor, SolveStats},
+    },
+};
+
+/// Dynamic programming over a tree decomposition of the circuit.
+///
+/// Circuits whose decomposition is wider than `max_width` are handed to the backtracker
+/// instead, since the tables grow as 3^(width + 1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeDpAlgorithm {
+    pub max_width: usize,
+}
+
+impl Default for TreeDpAlgorithm {
+    fn default() -> Self {
+        Self { max_width: 10 }
+    }
+}
+
+/// Tree decomposition of the value nodes, built by eliminating them in min-fill order.
+///
+/// Every gate turns its ports into a clique of the incidence graph, hence the ports of every
+/// gate share a bag. Bags are indexed by elimination position: the bag of a node holds the
+/// node followed by its neighbours left when it was eliminated, and its parent is the bag of
+/// the first of those neighbours to be eliminated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeDecomposition {
+    bags: Vec<Vec<usize>>,
+    parents: Vec<Option<usize>>,
+}
+
+impl TreeDecomposition {
+    /// Largest bag size minus one
+    pub fn width(&self) -> usize {
+        self.bags
+            .iter()
+            .map(|b| b.len().saturating_sub(1))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Value nodes of every bag, the eliminated node first
+    pub fn bags(&self) -> impl Iterator<Item = Vec<NodeIndex>> + '_ {
+        self.bags
+            .iter()
+            .map(|b| b.iter().copied().map(NodeIndex::new).collect())
+    }
+
+    /// Parent of every bag, None for the roots of the forest
+    pub fn parents(&self) -> &[Option<usize>] {
+        &self.parents
+    }
+
+    fn new(table: &CircuitTable, nodes: &[usize]) -> Self {
+        let mut adjacency = vec![BTreeSet::new(); table.adjacency.len()];
+        for (_, ins, outs) in table.gates.iter() {
+            let ports = ins.iter().chain(outs.iter()).flatten().collect::<Vec<_>>();
+            for (a, b) in ports
+                .iter()
+                .flat_map(|a| ports.iter().map(move |b| (**a, **b)))
+            {
+                if a != b {
+                    adjacency[a].insert(b);
+                }
+            }
+        }
+        let fill = |adjacency: &[BTreeSet<usize>], n: usize| {
+            let neighbours = adjacency[n].iter().collect::<Vec<_>>();
+            neighbours
+                .iter()
+                .enumerate()
+                .flat_map(|(i, a)| neighbours[i + 1..].iter().map(move |b| (**a, **b)))
+                .filter(|(a, b)| !adjacency[*a].contains(b))
+                .count()
+        };
+
+        let mut remaining = nodes.iter().copied().collect::<BTreeSet<_>>();
+        let mut position = vec![usize::MAX; adjacency.len()];
+        let mut bags = Vec::with_capacity(nodes.len());
+        while let Some(node) = remaining
+            .iter()
+            .copied()
+            .min_by_key(|n| (fill(&adjacency, *n), adjacency[*n].len()))
+        {
+            remaining.remove(&node);
+            position[node] = bags.len();
+            let neighbours = std::mem::take(&mut adjacency[node]);
+            for a in neighbours.iter() {
+                adjacency[*a].remove(&node);
+                adjacency[*a].extend(neighbours.iter().filter(|b| *b != a));
+            }
+            bags.push([node].into_iter().chain(neighbours).collect::<Vec<_>>());
+        }
+        let parents = bags
+            .iter()
+            .map(|bag| bag[1..].iter().map(|n| position[*n]).min())
+            .collect();
+        Self { bags, parents }
+    }
+}
+
+/// Result of the solver with the width of the decomposition it used
+#[derive(Debug, Clone)]
+pub struct TreeDpReport<T> {
+    pub result: T,
+    pub width: usize,
+    /// The width exceeded `max_width` and the backtracker computed the result
+    pub fallback: bool,
+    pub stats: SolveStats,
+}
+
+/// Bag together with the gates checked in it and the table it sends to its parent
+#[derive(Debug, Clone)]
+struct Bucket {
+    node: usize,
+    /// Nodes of the bag other than `node`, which the table is indexed on
+    separator: Vec<usize>,
+    gates: Vec<usize>,
+    children: Vec<usize>,
+    /// Number of assignments of the subtree for every assignment of the separator
+    table: Vec<u128>,
+}
+
+/// Position of the values of `separator` in a table
+/// # Returns
+/// None if a node of the separator is unassigned
+fn table_index(separator: &[usize], assignment: &[Option<Value>]) -> Option<usize> {
+    separator
+        .iter()
+        .try_fold(0, |acc, n| Some(acc * 3 + assignment[*n]?.index()))
+}
+
+/// Tables of a decomposition, filled from the leaves up
+#[derive(Debug, Clone)]
+struct TreeTables {
+    circuit: CircuitTable,
+    buckets: Vec<Bucket>,
+    roots: Vec<usize>,
+}
+
+impl TreeTables {
+    /// # Errors
+    /// * Cancelled: The budget ran out before every table was filled
+    /// * Gate: Exists gate with invalid arity
+    fn new(
+        circuit: CircuitTable,
+        decomposition: &TreeDecomposition,
+        monitor: &BudgetMonitor,
+    ) -> Result<Self, SolverError> {
+        let mut buckets = decomposition
+            .bags
+            .iter()
+            .map(|bag| Bucket {
+                node: bag[0],
+                separator: bag[1..].to_vec(),
+                gates: vec![],
+                children: vec![],
+                table: vec![],
+            })
+            .collect::<Vec<_>>();
+        let mut position = vec![0; circuit.adjacency.len()];
+        for (pos, bucket) in buckets.iter().enumerate() {
+            position[bucket.node] = pos;
+        }
+        // The first port eliminated holds the whole gate in its bag
+        for (gate, (_, ins, outs)) in circuit.gates.iter().enumerate() {
+            if let Some(pos) = ins
+                .iter()
+                .chain(outs.iter())
+                .flatten()
+                .map(|n| position[*n])
+                .min()
+            {
+                buckets[pos].gates.push(gate);
+            }
+        }
+        let mut roots = vec![];
+        for (pos, parent) in decomposition.parents.iter().enumerate() {
+            match parent {
+                Some(parent) => buckets[*parent].children.push(pos),
+                None => roots.push(pos),
+            }
+        }
+
+        let mut tables = Self {
+            circuit,
+            buckets,
+            roots,
+        };
+        let mut assignment = vec![None; tables.circuit.adjacency.len()];
+        // Children are eliminated before their parents
+        for pos in 0..tables.buckets.len() {
+            let bucket = &tables.buckets[pos];
+            let mut table = vec![0u128; 3usize.pow(bucket.separator.len() as u32)];
+            for row in 0..table.len() * 3 {
+                if monitor.tick() {
+                    return Err(SolverError::Cancelled);
+                }
+                let mut rest = row;
+                for n in bucket.separator.iter().rev().chain([&bucket.node]) {
+                    assignment[*n] = Some(Value::ALL[rest % 3]);
+                    rest /= 3;
+                }
+                let index = table_index(&bucket.separator, &assignment)
+                    .ok_or(GraphError::NotExistentNode)?;
+                let entry = &mut table[index];
+                *entry = entry.saturating_add(tables.weight(pos, &assignment)?);
+            }
+            tables.buckets[pos].table = table;
+        }
+        Ok(tables)
+    }
+
+    /// Completions of the subtree of `pos`, given the assignment of its bag.
+    /// The tables of its children have to be filled. Counts saturate at `u128::MAX`
+    fn weight(&self, pos: usize, assignment: &[Option<Value>]) -> Result<u128, SolverError> {
+        let bucket = &self.buckets[pos];
+        for gate in bucket.gates.iter() {
+            let (g, ins, outs) = &self.circuit.gates[*gate];
+            let ins = ins
+                .iter()
+                .filter_map(|n| n.and_then(|n| assignment[n]))
+                .collect::<Vec<_>>();
+            let outs = outs
+                .iter()
+                .filter_map(|n| n.and_then(|n| assignment[n]))
+                .collect::<Vec<_>>();
+            if !g.check(&ins, &outs)? {
+                return Ok(0);
+            }
+        }
+        let mut ret = 1u128;
+        for child in bucket.children.iter().map(|c| &self.buckets[*c]) {
+            let index =
+                table_index(&child.separator, assignment).ok_or(GraphError::NotExistentNode)?;
+            ret = ret.saturating_mul(child.table[index]);
+            if ret == 0 {
+                break;
+            }
+        }
+        Ok(ret)
+    }
+
+    fn count(&self) -> SolutionCount {
+        let ret = self.roots.iter().fold(1u128, |acc, root| {
+            acc.saturating_mul(self.buckets[*root].table[0])
+        });
+        match ret == u128::MAX {
+            true => SolutionCount::LowerBound(ret),
+            false => SolutionCount::Exact(ret),
+        }
+    }
+}
+
+/// Assignments of a circuit, created by `TreeDpAlgorithm::solutions`
+#[derive(Debug, Clone)]
+pub enum TreeSolutions {
+    /// Reads the solutions off the tables, every branch extends to a solution
+    Tree(TreeSolutionIter),
+    Backtrack(Box<SolutionIter>),
+}
+
+impl Iterator for TreeSolutions {
+    type Item = Result<Vec<Option<Value>>, SolverError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Tree(iter) => iter.next(),
+            Self::Backtrack(iter) => iter.next(),
+        }
+    }
+}
+
+impl FusedIterator for TreeSolutions {}
+
+/// Enumeration over filled tables. Nodes are assigned from the roots down, trying only the
+/// values whose subtree still has a completion, hence the search never backtracks from a
+/// dead end. A forest with a root without completions yields nothing.
+#[derive(Debug, Clone)]
+pub struct TreeSolutionIter {
+    tables: TreeTables,
+    assignment: Vec<Option<Value>>,
+    /// Value index tried at every depth, the bag at depth `d` is the `d`-th from the end
+    stack: Vec<usize>,
+    started: bool,
+}
+
+impl TreeSolutionIter {
+    /// Advance the value at the top of the stack to the next one with a completion
+    fn search(&mut self) -> Result<Option<Vec<Option<Value>>>, SolverError> {
+        let len = self.tables.buckets.len();
+        loop {
+            let Some(depth) = self.stack.len().checked_sub(1) else {
+                return Ok(None);
+            };
+            let pos = len - 1 - depth;
+            let node = self.tables.buckets[pos].node;
+            let mut found = false;
+            while let Some(value) = Value::ALL.get(self.stack[depth]).copied() {
+                self.assignment[node] = Some(value);
+                if self.tables.weight(pos, &self.assignment)? > 0 {
+                    found = true;
+                    break;
+                }
+                self.stack[depth] += 1;
+            }
+            match (found, depth + 1 == len) {
+                (true, true) => return Ok(Some(self.assignment.clone())),
+                (true, false) => self.stack.push(0),
+                (false, _) => {
+                    self.assignment[node] = None;
+                    self.stack.pop();
+                    if let Some(last) = self.stack.last_mut() {
+                        *last += 1;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for TreeSolutionIter {
+    type Item = Result<Vec<Option<Value>>, SolverError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match std::mem::replace(&mut self.started, true) {
+            // The roots are independent, checking only the first one visited would enumerate
+            // the other trees before reaching a dead one
+            false if self.tables.count() == SolutionCount::Exact(0) => return None,
+            false => self.stack.push(0),
+            true => {
+                // Move past the latest solution
+                *self.stack.last_mut()? += 1;
+            }
+        }
+        let ret = self.search().transpose();
+        if matches!(ret, Some(Err(_))) {
+            self.stack.clear();
+        }
+        ret
+    }
+}
+
+impl FusedIterator for TreeSolutionIter {}
+
+/// Decomposition of the circuit, or the backtracker if it is too wide
+enum Plan {
+    Tree(TreeTables),
+    Fallback,
+}
+
+impl TreeDpAlgorithm {
+    /// Decompose the value nodes of the circuit with the min-fill heuristic
+    /// # Errors
+    /// * InvalidArity: Exists gate with invalid arity
+    /// * EmptyGraph: Circuit does not contain any value nodes
+    pub fn decompose<T, G>(
+        &self,
+        pc_instance: &PureCircuitGraph<T, G>,
+    ) -> Result<TreeDecomposition, SolverError> {
+        Self::circuit(pc_instance).map(|(_, decomposition)| decomposition)
+    }
+
+    fn circuit<T, G>(
+        pc_instance: &PureCircuitGraph<T, G>,
+    ) -> Result<(CircuitTable, TreeDecomposition), SolverError> {
+        let (circuit, state) = CircuitTable::new(pc_instance)?;
+        if state.queue.is_empty() {
+            return Err(SolverError::EmptyGraph);
+        }
+        let nodes = (0..state.value_map.len())
+            .filter(|n| state.value_map[*n].is_some())
+            .collect::<Vec<_>>();
+        let decomposition = TreeDecomposition::new(&circuit, &nodes);
+        Ok((circuit, decomposition))
+    }
+
+    /// Fill the tables, unless the decomposition is wider than `max_width`
+    fn plan<T, G>(
+        &self,
+        pc_instance: &PureCircuitGraph<T, G>,
+        budget: &Budget,
+    ) -> Result<(Plan, usize), SolverError> {
+        let (circuit, decomposition) = Self::circuit(pc_instance)?;
+        let width = decomposition.width();
+        if width > self.max_width {
+            return Ok((Plan::Fallback, width));
+        }
+        let tables = TreeTables::new(circuit, &decomposition, &budget.start())?;
+        Ok((Plan::Tree(tables), width))
+    }
+
+    /// Whether the circuit has a solution
+    ///
+    /// # Parameters
+    /// * `budget` - Every table entry, or every branch of the backtracker, counts as a node
+    ///
+    /// # Errors
+    /// * InvalidArity: Exists gate with invalid arity
+    /// * EmptyGraph: Circuit does not contain any value nodes
+    /// * Cancelled: The budget ran out before the answer was known
+    pub fn is_satisfiable<T, G>(
+        &self,
+        pc_instance: &PureCircuitGraph<T, G>,
+        budget: &Budget,
+    ) -> Result<TreeDpReport<bool>, SolverError> {
+        let started = Instant::now();
+        let mut stats = SolveStats::default();
+        let (plan, width) = self.plan(pc_instance, budget)?;
+        let (result, fallback) = match plan {
+            Plan::Tree(tables) => (tables.count().count() > 0, false),
+            Plan::Fallback => {
+                let mut search = BacktrackAlgorithm
+                    .solutions(pc_instance)?
+                    .with_budget(budget);
+                let found = search.next().transpose()?.is_some();
+                if !found && search.is_interrupted() {
+                    return Err(SolverError::Cancelled);
+                }
+                stats = search.stats();
+                (found, true)
+            }
+        };
+        Ok(TreeDpReport {
+            result,
+            width,
+            fallback,
+            stats: SolveStats {
+                wall_time: started.elapsed(),
+                ..stats
+            },
+        })
+    }
+
+    /// Count the assignments of the circuit
+    ///
+    /// # Parameters
+    /// * `budget` - Every table entry, or every branch of the backtracker, counts as a node.
+    ///   The backtracker returns the solutions counted so far once the budget runs out
+    ///
+    /// # Errors
+    /// * InvalidArity: Exists gate with invalid arity
+    /// * EmptyGraph: Circuit does not contain any value nodes
+    /// * Cancelled: The budget ran out before every table was filled
+    pub fn count_solutions<T, G>(
+        &self,
+        pc_instance: &PureCircuitGraph<T, G>,
+        budget: &Budget,
+    ) -> Result<TreeDpReport<SolutionCount>, SolverError> {
+        let started = Instant::now();
+        let mut stats = SolveStats::default();
+        let (plan, width) = self.plan(pc_instance, budget)?;
+        let (result, fallback) = match plan {
+            Plan::Tree(tables) => (tables.count(), false),
+            Plan::Fallback => {
+                let (count, backtrack_stats) =
+                    BacktrackAlgorithm.count_solutions_with_stats(pc_instance, budget)?;
+                stats = backtrack_stats;
+                (count, true)
+            }
+        };
+        Ok(TreeDpReport {
+            result,
+            width,
+            fallback,
+            stats: SolveStats {
+                wall_time: started.elapsed(),
+                ..stats
+            },
+        })
+    }
+
+    /// Enumerate the assignments of the circuit. The tables are filled before returning
+    ///
+    /// # Parameters
+    /// * `budget` - Every table entry, or every branch of the backtracker, counts as a node
+    ///
+    /// # Errors
+    /// * InvalidArity: Exists gate with invalid arity
+    /// * EmptyGraph: Circuit does not contain any value nodes
+    /// * Cancelled: The budget ran out before every table was filled
+    pub fn solutions<T, G>(
+        &self,
+        pc_instance: &PureCircuitGraph<T, G>,
+        budget: &Budget,
+    ) -> Result<TreeDpReport<TreeSolutions>, SolverError> {
+        let started = Instant::now();
+        let (plan, width) = self.plan(pc_instance, budget)?;
+        let (result, fallback) = match plan {
+            Plan::Tree(tables) => (
+                TreeSolutions::Tree(TreeSolutionIter {
+                    assignment: vec![None; tables.circuit.adjacency.len()],
+                    tables,
+                    stack: vec![],
+                    started: false,
+                }),
+                false,
+            ),
+            Plan::Fallback => (
+                TreeSolutions::Backtrack(Box::new(
+                    BacktrackAlgorithm
+                        .solutions(pc_instance)?
+                        .with_budget(budget),
+                )),
+                true,
+            ),
+        };
+        Ok(TreeDpReport {
+            result,
+            width,
+            fallback,
+            stats: SolveStats {
+                wall_time: started.elapsed(),
+                ..Default::default()
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{copy_chain, copy_pairs, dead_loop, disjoint, not_ring},
+        strategies::{CircuitParams, circuit_strategy},
+    };
+    use proptest::prelude::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_width() {
+        let algo = TreeDpAlgorithm::default();
+        assert_eq!(algo.decompose(&copy_chain(20)).unwrap().width(), 1);
+        assert_eq!(algo.decompose(&not_ring(20)).unwrap().width(), 2);
+        assert_eq!(
+            algo.decompose(&PureCircuitGraph::<(), ()>::new()),
+            Err(SolverError::EmptyGraph)
+        );
+    }
+
+    #[test]
+    fn test_long_chain() {
+        let report = TreeDpAlgorithm::default()
+            .count_solutions(&copy_chain(200), &Budget::default())
+            .unwrap();
+        assert_eq!(report.result, SolutionCount::Exact(3));
+        assert_eq!(report.width, 1);
+        assert!(!report.fallback);
+    }
+
+    #[test]
+    fn test_rings() {
+        let algo = TreeDpAlgorithm::default();
+        let odd = algo.solutions(&not_ring(7), &Budget::default()).unwrap();
+        assert_eq!(odd.result.collect::<Result<Vec<_>, _>>().unwrap().len(), 1);
+        let even = algo
+            .count_solutions(&not_ring(8), &Budget::default())
+            .unwrap();
+        assert_eq!(even.result, SolutionCount::Exact(3));
+        assert!(
+            algo.is_satisfiable(&not_ring(8), &Budget::default())
+                .unwrap()
+                .result
+        );
+    }
+
+    #[test]
+    fn test_dead_tree() {
+        // Unsatisfiable Not loop feeding a Purify, eliminated before 20 Copy pairs with 3^20
+        // solutions
+        let pc = disjoint([dead_loop(), copy_pairs(20)]);
+
+        let algo = TreeDpAlgorithm::default();
+        let report = algo.solutions(&pc, &Budget::default()).unwrap();
+        assert!(!report.fallback);
+        assert_eq!(report.result.count(), 0);
+        let report = algo.count_solutions(&pc, &Budget::default()).unwrap();
+        assert_eq!(report.result, SolutionCount::Exact(0));
+    }
+
+    #[test]
+    fn test_fallback() {
+        let algo = TreeDpAlgorithm { max_width: 1 };
+        let report = algo
+            .count_solutions(&not_ring(8), &Budget::default())
+            .unwrap();
+        assert!(report.fallback);
+        assert_eq!(report.width, 2);
+        assert_eq!(report.result, SolutionCount::Exact(3));
+        let report = algo.solutions(&not_ring(8), &Budget::default()).unwrap();
+        assert!(matches!(report.result, TreeSolutions::Backtrack(_)));
+        assert_eq!(report.result.count(), 3);
+    }
+
+    #[test]
+    fn test_budget() {
+        let budget = Budget::default();
+        budget.cancellation.cancel();
+        assert_eq!(
+            TreeDpAlgorithm::default()
+                .count_solutions(&copy_chain(5), &budget)
+                .unwrap_err(),
+            SolverError::Cancelled
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn decomposition_is_valid(pc in circuit_strategy(CircuitParams::new(1..=12, 0..=14))) {
+            let decomposition = TreeDpAlgorithm::default().decompose(&pc).unwrap();
+            let bags = decomposition.bags().collect::<Vec<_>>();
+            let (circuit, _) = CircuitTable::new(&pc).unwrap();
+            for (_, ins, outs) in circuit.gates.iter() {
+                let ports = ins.iter().chain(outs.iter()).flatten().map(|n| NodeIndex::new(*n));
+                let ports = ports.collect::<Vec<_>>();
+                prop_assert!(bags.iter().any(|b| ports.iter().all(|p| b.contains(p))));
+            }
+            // Parents come later and hold the rest of the bag
+            for (pos, parent) in decomposition.parents().iter().enumerate() {
+                match parent {
+                    Some(parent) => {
+                        prop_assert!(*parent > pos);
+                        prop_assert!(bags[pos][1..].iter().all(|n| bags[*parent].contains(n)));
+                    }
+                    None => prop_assert_eq!(bags[pos].len(), 1),
+                }
+            }
+        }
+
+        #[test]
+        fn matches_backtracker(pc in circuit_strategy(CircuitParams::new(1..=8, 0..=10))) {
+            let expected = BacktrackAlgorithm.calculate(&pc).unwrap();
+            let algo = TreeDpAlgorithm::default();
+            let count = algo.count_solutions(&pc, &Budget::default()).unwrap();
+            prop_assert_eq!(count.result, SolutionCount::Exact(expected.len() as u128));
+            let sat = algo.is_satisfiable(&pc, &Budget::default()).unwrap();
+            prop_assert_eq!(sat.result, !expected.is_empty());
+            let found = algo
+                .solutions(&pc, &Budget::default())
+                .unwrap()
+                .result
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            prop_assert_eq!(found.len(), expected.len());
+            prop_assert_eq!(
+                found.into_iter().collect::<HashSet<_>>(),
+                expected.into_iter().collect::<HashSet<_>>()
+            );
+        }
+    }
+}
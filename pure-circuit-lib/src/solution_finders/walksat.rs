@@ -0,0 +1,579 @@
+use std::time::Instant;
+
+use itertools::Itertools;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use strum::IntoEnumIterator;
+
+use crate::{
+    gates::Value,
+    graph::GraphError,
+    solution_finders::{
+        backtracking::BitString,
+        base_finder::{FitnessPureCircuit, MAX_DEGREE},
+        evo_search::{Build, Instance, SolutionReturn, SolverStruct},
+        observer::{SharedObserver, SolverEvent},
+        solver_error::SolverError,
+        solver_trait::{Budget, BudgetMonitor, SolveStats, SolverTrait},
+    },
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+/// Proxy type for the WalkSAT style local search
+pub struct WalkSatAlgorithm;
+
+pub type SolverWalkSat = SolverStruct<WalkSatAlgorithm>;
+
+/// Values assigned to chromosome positions by a repair
+type Repair = Vec<(usize, Value)>;
+
+#[derive(Debug, Clone)]
+/// Parameter set of the WalkSAT style local search
+pub struct WalkSatParamSet<T> {
+    pub param_type: T,
+    /// Probability in `[0, 1]` of a random repair when every repair breaks another gate
+    pub noise: f64,
+    /// Repairs of a single try
+    pub max_flips: usize,
+    /// Random starting assignments, every try after the first counts as a restart
+    pub tries: usize,
+    /// Seed of the starting assignments and of the random steps, None draws one from the OS
+    pub seed: Option<u64>,
+    pub budget: Budget,
+    pub observer: Option<SharedObserver>,
+}
+
+impl Default for WalkSatParamSet<Build> {
+    fn default() -> Self {
+        Self {
+            param_type: Build,
+            noise: 0.4,
+            max_flips: 10_000,
+            tries: 10,
+            seed: None,
+            budget: Budget::default(),
+            observer: None,
+        }
+    }
+}
+
+impl WalkSatParamSet<Build> {
+    pub fn build(
+        &self,
+        instance: Instance<FitnessPureCircuit>,
+    ) -> WalkSatParamSet<Instance<FitnessPureCircuit>> {
+        WalkSatParamSet {
+            param_type: instance,
+            noise: self.noise,
+            max_flips: self.max_flips,
+            tries: self.tries,
+            seed: self.seed,
+            budget: self.budget.clone(),
+            observer: self.observer.clone(),
+        }
+    }
+}
+
+/// Assignments of the ports of `gate` that satisfy it and differ from `current`.
+///
+/// Every nonempty proper subset of the ports keeps its current values, `Gate::set_value` gives
+/// the values the remaining ports can take.
+/// # Errors
+/// * Graph(NotExistentNode): The circuit has no such gate
+/// * InvalidAssignmentLength: `current` is shorter than the chromosome
+fn repairs(
+    fitness: &FitnessPureCircuit,
+    gate: usize,
+    current: &[Value],
+) -> Result<Vec<Repair>, SolverError> {
+    if current.len() < fitness.required_len() {
+        return Err(SolverError::InvalidAssignmentLength {
+            expected: fitness.required_len(),
+            found: current.len(),
+        });
+    }
+    let (g, ins, outs) = fitness
+        .gate(gate)
+        .ok_or(SolverError::Graph(GraphError::NotExistentNode))?;
+    let ports = ins
+        .iter()
+        .map(|n| (true, *n))
+        .enumerate()
+        .chain(outs.iter().map(|n| (false, *n)).enumerate())
+        .filter_map(|(slot, (is_in, node))| node.map(|node| (is_in, slot, node)))
+        .collect_vec();
+    let mut ret: Vec<Repair> = vec![];
+    for mask in 1..(1usize << ports.len()) - 1 {
+        let (mut ins_vals, mut outs_vals) = ([None; MAX_DEGREE], [None; MAX_DEGREE]);
+        for (i, (is_in, slot, node)) in ports.iter().copied().enumerate() {
+            if mask & (1 << i) == 0 {
+                continue;
+            }
+            match is_in {
+                true => ins_vals[slot] = Some(current[node]),
+                false => outs_vals[slot] = Some(current[node]),
+            }
+        }
+        let (ins_sets, outs_sets) = match g.set_value(ins_vals, outs_vals) {
+            Ok(sets) => sets,
+            Err(SolverError::InconsistentAssignment { .. }) => continue,
+            Err(e) => return Err(e),
+        };
+        let domains = ports
+            .iter()
+            .map(|(is_in, slot, _)| match is_in {
+                true => ins_sets[*slot],
+                false => outs_sets[*slot],
+            })
+            .map(|set| {
+                set.unwrap_or(BitString::all())
+                    .to_value_iter()
+                    .collect_vec()
+            });
+        for values in domains.multi_cartesian_product() {
+            let (mut ins_vals, mut outs_vals) = (vec![], vec![]);
+            let mut repair = Repair::new();
+            let mut consistent = true;
+            for ((is_in, _, node), value) in ports.iter().zip(values) {
+                match is_in {
+                    true => ins_vals.push(value),
+                    false => outs_vals.push(value),
+                }
+                match repair.iter().find(|(n, _)| n == node) {
+                    Some((_, v)) => consistent &= *v == value,
+                    None => repair.push((*node, value)),
+                }
+            }
+            repair.retain(|(n, v)| current[*n] != *v);
+            if consistent
+                && !repair.is_empty()
+                && g.check(&ins_vals, &outs_vals)?
+                && !ret.contains(&repair)
+            {
+                ret.push(repair);
+            }
+        }
+    }
+    Ok(ret)
+}
+
+/// Assignment of a try, with the violated gates kept up to date
+#[derive(Debug)]
+struct WalkState<'a> {
+    fitness: &'a FitnessPureCircuit,
+    current: Vec<Value>,
+    /// Violated gates, in no particular order
+    violated: Vec<usize>,
+    /// Position of every gate in `violated`
+    position: Vec<Option<usize>>,
+}
+
+impl<'a> WalkState<'a> {
+    fn new(fitness: &'a FitnessPureCircuit, current: Vec<Value>) -> Result<Self, SolverError> {
+        if current.len() < fitness.required_len() {
+            return Err(SolverError::InvalidAssignmentLength {
+                expected: fitness.required_len(),
+                found: current.len(),
+            });
+        }
+        let mut state = Self {
+            fitness,
+            current,
+            violated: vec![],
+            position: vec![None; fitness.gate_count()],
+        };
+        for gate in 0..fitness.gate_count() {
+            let violated = fitness.violated(gate, &state.current)?;
+            state.set_violated(gate, violated);
+        }
+        Ok(state)
+    }
+
+    fn errors(&self) -> usize {
+        self.violated.len()
+    }
+
+    fn set_violated(&mut self, gate: usize, violated: bool) {
+        match (self.position[gate], violated) {
+            (None, true) => {
+                self.position[gate] = Some(self.violated.len());
+                self.violated.push(gate);
+            }
+            (Some(pos), false) => {
+                self.violated.swap_remove(pos);
+                if let Some(moved) = self.violated.get(pos) {
+                    self.position[*moved] = Some(pos);
+                }
+                self.position[gate] = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Gates next to the nodes of the repair, besides `gate`
+    fn neighbours(&self, gate: usize, repair: &Repair) -> Vec<usize> {
+        repair
+            .iter()
+            .flat_map(|(node, _)| self.fitness.incident_gates(*node).iter().copied())
+            .filter(|g| *g != gate)
+            .unique()
+            .collect()
+    }
+
+    /// Satisfied gates besides `gate` that the repair violates
+    fn breaks(&mut self, gate: usize, repair: &Repair) -> Result<usize, SolverError> {
+        let old = repair
+            .iter()
+            .map(|(node, value)| std::mem::replace(&mut self.current[*node], *value))
+            .collect_vec();
+        let breaks = self
+            .neighbours(gate, repair)
+            .into_iter()
+            .filter(|g| self.position[*g].is_none())
+            .map(|g| self.fitness.violated(g, &self.current))
+            .fold_ok(0, |acc, v| acc + v as usize);
+        for ((node, _), value) in repair.iter().zip(old) {
+            self.current[*node] = value;
+        }
+        breaks
+    }
+
+    fn apply(&mut self, repair: &Repair) -> Result<(), SolverError> {
+        for (node, value) in repair {
+            self.current[*node] = *value;
+        }
+        let gates = repair
+            .iter()
+            .flat_map(|(node, _)| self.fitness.incident_gates(*node).iter().copied())
+            .unique()
+            .collect_vec();
+        for gate in gates {
+            let violated = self.fitness.violated(gate, &self.current)?;
+            self.set_violated(gate, violated);
+        }
+        Ok(())
+    }
+
+    /// Repair of `gate` to apply.
+    /// A repair that breaks no other gate is taken if there is one, otherwise a random repair
+    /// with probability `noise`, otherwise one of the repairs that break the fewest gates
+    /// # Returns
+    /// None once the budget runs out, every evaluated repair counts as a node
+    fn choose(
+        &mut self,
+        gate: usize,
+        rng: &mut StdRng,
+        noise: f64,
+        monitor: &BudgetMonitor,
+    ) -> Result<Option<Repair>, SolverError> {
+        let mut repairs = repairs(self.fitness, gate, &self.current)?;
+        if repairs.is_empty() {
+            // The random step is the only candidate
+            if monitor.tick() {
+                return Ok(None);
+            }
+            let nodes = self.fitness.gate_nodes(gate).collect_vec();
+            let node = nodes[rng.random_range(0..nodes.len())];
+            let values = Value::iter()
+                .filter(|v| *v != self.current[node])
+                .collect_vec();
+            let value = values[rng.random_range(0..values.len())];
+            return Ok(Some(vec![(node, value)]));
+        }
+        let mut breaks = Vec::with_capacity(repairs.len());
+        for repair in repairs.iter() {
+            if monitor.tick() {
+                return Ok(None);
+            }
+            breaks.push(self.breaks(gate, repair)?);
+        }
+        let min = breaks.iter().copied().min().unwrap_or(0);
+        let pool = match min > 0 && rng.random_bool(noise.clamp(0.0, 1.0)) {
+            true => (0..repairs.len()).collect_vec(),
+            false => (0..repairs.len()).filter(|i| breaks[*i] == min).collect(),
+        };
+        let pick = pool[rng.random_range(0..pool.len())];
+        Ok(Some(repairs.swap_remove(pick)))
+    }
+}
+
+impl SolverTrait for SolverWalkSat {
+    type ParamSet = WalkSatParamSet<Instance<FitnessPureCircuit>>;
+
+    type Solution = SolutionReturn;
+
+    // Focused local search: repair a random violated gate at every step, restarting from a
+    // random assignment after `max_flips` repairs
+    fn find_solution(&self, param_set: Self::ParamSet) -> Result<Self::Solution, SolverError> {
+        let started = Instant::now();
+        let mut rng = match param_set.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+        let monitor = param_set.budget.start();
+        if monitor.tick() {
+            return Err(SolverError::Cancelled);
+        }
+        let fitness = &param_set.param_type.func;
+        let notify = |event| {
+            if let Some(observer) = &param_set.observer {
+                observer.on_event(event);
+            }
+        };
+        let values = Value::iter().collect_vec();
+
+        let mut best: Option<(Vec<Value>, usize)> = None;
+        let (mut flips, mut tries) = (0, 0);
+        let mut generation_capped = false;
+        'tries: while tries < param_set.tries.max(1) {
+            let initial = (0..param_set.param_type.size)
+                .map(|_| values[rng.random_range(0..values.len())])
+                .collect_vec();
+            let mut state = WalkState::new(fitness, initial)?;
+            tries += 1;
+            for flip in 0..=param_set.max_flips {
+                if best.as_ref().is_none_or(|(_, b)| state.errors() < *b) {
+                    best = Some((state.current.clone(), state.errors()));
+                }
+                if state.errors() == 0 {
+                    break 'tries;
+                }
+                if flip == param_set.max_flips {
+                    break;
+                }
+                if param_set.budget.max_generations.is_some_and(|m| flips >= m) {
+                    generation_capped = true;
+                    break 'tries;
+                }
+                let gate = state.violated[rng.random_range(0..state.errors())];
+                let Some(repair) = state.choose(gate, &mut rng, param_set.noise, &monitor)? else {
+                    break 'tries;
+                };
+                state.apply(&repair)?;
+                flips += 1;
+                notify(SolverEvent::Generation {
+                    generation: flips,
+                    best_fitness: best.as_ref().map(|(_, b)| {
+                        isize::try_from((*b).min(state.errors())).unwrap_or(isize::MAX)
+                    }),
+                });
+            }
+        }
+
+        let (chromosone, errors) = best.unwrap_or_default();
+        let interrupted = monitor.was_interrupted() || (generation_capped && errors > 0);
+        notify(SolverEvent::Finished { interrupted });
+        Ok(SolutionReturn {
+            chromosone,
+            errors,
+            interrupted,
+            stats: SolveStats {
+                wall_time: started.elapsed(),
+                restarts: tries.saturating_sub(1),
+                generations: flips,
+                fitness_evaluations: monitor.nodes(),
+                ..Default::default()
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::not_ring,
+        gates::{Gate, NodeUnitialised},
+        graph::PureCircuitGraph,
+        strategies::{CircuitParams, circuit_strategy, enum_strategy},
+    };
+    use proptest::prelude::*;
+    use rstest::rstest;
+
+    /// Single gate over fresh value nodes
+    fn single_gate(gate: Gate) -> PureCircuitGraph {
+        let mut pc = PureCircuitGraph::new();
+        let g = pc.add_node(NodeUnitialised::from_gate(gate), ());
+        let (ins, outs) = gate.arity();
+        for _ in 0..ins {
+            let n = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+            pc.add_edge(n, g, ()).unwrap();
+        }
+        for _ in 0..outs {
+            let n = pc.add_node(NodeUnitialised::from_value(Value::Bot), ());
+            pc.add_edge(g, n, ()).unwrap();
+        }
+        pc
+    }
+
+    fn params(
+        pc: &PureCircuitGraph,
+        set: WalkSatParamSet<Build>,
+    ) -> WalkSatParamSet<Instance<FitnessPureCircuit>> {
+        let instance = Instance::new(pc.to_fitness_function().unwrap(), pc.count_values());
+        set.build(instance)
+    }
+
+    #[test]
+    fn test_repairs() {
+        use Value::*;
+        let fitness = single_gate(Gate::Not).to_fitness_function().unwrap();
+        let found = repairs(&fitness, 0, &[One, One]).unwrap();
+        assert_eq!(found.len(), 2);
+        assert!(found.contains(&vec![(0, Zero)]));
+        assert!(found.contains(&vec![(1, Zero)]));
+        // Both inputs of the And gate change to reach a Bot output
+        let fitness = single_gate(Gate::And).to_fitness_function().unwrap();
+        let found = repairs(&fitness, 0, &[Zero, Zero, One]).unwrap();
+        assert!(found.contains(&vec![(2, Zero)]));
+        assert!(found.contains(&vec![(0, One), (1, One)]));
+        assert!(found.iter().all(|r| r.len() < 3));
+    }
+
+    #[test]
+    fn test_zero_break() {
+        use Value::*;
+        // Copy chain 0 -> 1 -> 2, only the first gate is violated.
+        // Changing node 0 repairs it without breaking the second gate
+        let mut pc = PureCircuitGraph::new();
+        let nodes = (0..3)
+            .map(|_| pc.add_node(NodeUnitialised::from_value(Bot), ()))
+            .collect_vec();
+        for w in nodes.windows(2) {
+            let g = pc.add_node(NodeUnitialised::from_gate(Gate::Copy), ());
+            pc.add_edge(w[0], g, ()).unwrap();
+            pc.add_edge(g, w[1], ()).unwrap();
+        }
+        let fitness = pc.to_fitness_function().unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let monitor = Budget::default().start();
+        for _ in 0..10 {
+            let mut state = WalkState::new(&fitness, vec![Zero, One, One]).unwrap();
+            assert_eq!(state.violated, vec![0]);
+            assert_eq!(
+                state.choose(0, &mut rng, 1.0, &monitor).unwrap(),
+                Some(vec![(0, One)])
+            );
+        }
+    }
+
+    #[rstest]
+    #[case(not_ring(4))]
+    #[case(not_ring(9))]
+    fn test_solves(#[case] pc: PureCircuitGraph) {
+        let fitness = pc.to_fitness_function().unwrap();
+        for seed in 0..5 {
+            let sol = SolverWalkSat::default()
+                .find_solution(params(
+                    &pc,
+                    WalkSatParamSet {
+                        seed: Some(seed),
+                        ..Default::default()
+                    },
+                ))
+                .unwrap();
+            assert_eq!(sol.errors, 0);
+            assert_eq!(fitness.evaluate(&sol.chromosone), Ok(0));
+            assert!(!sol.interrupted);
+        }
+    }
+
+    #[test]
+    fn test_restarts() {
+        let pc = not_ring(15);
+        let run = || {
+            SolverWalkSat::default()
+                .find_solution(params(
+                    &pc,
+                    WalkSatParamSet {
+                        max_flips: 1,
+                        tries: 3,
+                        seed: Some(2),
+                        ..Default::default()
+                    },
+                ))
+                .unwrap()
+        };
+        let sol = run();
+        assert!(sol.errors > 0);
+        assert_eq!(sol.stats.restarts, 2);
+        assert_eq!(sol.stats.generations, 3);
+        assert!(!sol.interrupted);
+        assert_eq!(run().chromosone, sol.chromosone);
+    }
+
+    #[test]
+    fn test_budget() {
+        let pc = not_ring(9);
+        let budget = Budget::default();
+        budget.cancellation.cancel();
+        assert_eq!(
+            SolverWalkSat::default()
+                .find_solution(params(
+                    &pc,
+                    WalkSatParamSet {
+                        budget,
+                        ..Default::default()
+                    }
+                ))
+                .unwrap_err(),
+            SolverError::Cancelled
+        );
+
+        let sol = SolverWalkSat::default()
+            .find_solution(params(
+                &not_ring(15),
+                WalkSatParamSet {
+                    seed: Some(0),
+                    budget: Budget {
+                        max_generations: Some(2),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            ))
+            .unwrap();
+        assert!(sol.stats.generations <= 2);
+        assert!(sol.interrupted || sol.errors == 0);
+    }
+
+    proptest! {
+        #[test]
+        fn repairs_satisfy_gate(
+            gate in enum_strategy::<Gate>(),
+            values in prop::array::uniform3(enum_strategy::<Value>()),
+        ) {
+            let fitness = single_gate(gate).to_fitness_function().unwrap();
+            let len = fitness.required_len();
+            let violated = fitness.violated(0, &values[..len]).unwrap();
+            let found = repairs(&fitness, 0, &values[..len]).unwrap();
+            prop_assert!(!violated || !found.is_empty());
+            for repair in found {
+                let mut repaired = values[..len].to_vec();
+                for (node, value) in repair {
+                    repaired[node] = value;
+                }
+                prop_assert_eq!(fitness.violated(0, &repaired), Ok(false));
+            }
+        }
+
+        #[test]
+        fn errors_are_tracked(
+            pc in circuit_strategy(CircuitParams::new(1..=10, 0..=14)),
+            seed in any::<u64>(),
+        ) {
+            let fitness = pc.to_fitness_function().unwrap();
+            let sol = SolverWalkSat::default()
+                .find_solution(params(
+                    &pc,
+                    WalkSatParamSet {
+                        seed: Some(seed),
+                        max_flips: 50,
+                        tries: 2,
+                        ..Default::default()
+                    },
+                ))
+                .unwrap();
+            prop_assert_eq!(fitness.evaluate(&sol.chromosone), Ok(sol.errors));
+        }
+    }
+}
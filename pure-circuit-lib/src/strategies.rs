@@ -0,0 +1,195 @@
+//! Proptest strategies for the *PureCircuit* types.
+//!
+//! Enabled through the `proptest` cargo feature so that downstream crates can
+//! generate values, gates and arity-valid circuits without redefining them.
+use itertools::Itertools;
+use proptest::{
+    arbitrary::Arbitrary,
+    prelude::*,
+    sample::{Index, Select},
+};
+use std::{fmt::Debug, ops::RangeInclusive};
+use strum::IntoEnumIterator;
+
+use crate::{
+    gates::{Gate, NodeUnitialised, Value},
+    graph::PureCircuitGraph,
+    solution_finders::base_finder::MAX_DEGREE,
+};
+
+/// Uniformly select any variant of an enum. Shrinks towards the first variant.
+pub fn enum_strategy<T: IntoEnumIterator + Debug + Clone + 'static>() -> Select<T> {
+    prop::sample::select(T::iter().collect_vec())
+}
+
+impl Arbitrary for Value {
+    type Parameters = ();
+    type Strategy = Select<Value>;
+
+    fn arbitrary_with(_args: Self::Parameters) -> Self::Strategy {
+        enum_strategy::<Value>()
+    }
+}
+
+impl Arbitrary for Gate {
+    type Parameters = ();
+    type Strategy = Select<Gate>;
+
+    fn arbitrary_with(_args: Self::Parameters) -> Self::Strategy {
+        enum_strategy::<Gate>()
+    }
+}
+
+/// Size parameters of the generated circuits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CircuitParams {
+    /// Number of value nodes. A circuit with gates always has at least one value node
+    pub value_nodes: RangeInclusive<usize>,
+    /// Number of gate nodes
+    pub gate_nodes: RangeInclusive<usize>,
+}
+
+impl Default for CircuitParams {
+    fn default() -> Self {
+        Self {
+            value_nodes: 1..=10,
+            gate_nodes: 0..=10,
+        }
+    }
+}
+
+impl CircuitParams {
+    pub fn new(value_nodes: RangeInclusive<usize>, gate_nodes: RangeInclusive<usize>) -> Self {
+        Self {
+            value_nodes,
+            gate_nodes,
+        }
+    }
+}
+
+/// Gate type with the ports it connects to.
+/// Only the first `gate.arity()` entries of each port array are used, hence
+/// shrinking the gate or its indices can never break the arity.
+type GateSpec = (Gate, [Index; MAX_DEGREE], [Index; MAX_DEGREE]);
+
+fn gate_spec_strategy() -> impl Strategy<Value = GateSpec> {
+    (
+        any::<Gate>(),
+        prop::array::uniform2(any::<Index>()),
+        prop::array::uniform2(any::<Index>()),
+    )
+}
+
+fn build_circuit(values: Vec<Value>, gates: Vec<GateSpec>) -> PureCircuitGraph {
+    let mut pc = PureCircuitGraph::new();
+    let val_idx = values
+        .into_iter()
+        .map(|v| pc.add_node(NodeUnitialised::from_value(v), ()))
+        .collect_vec();
+    for (gate, ins, outs) in gates {
+        let gate_idx = pc.add_node(NodeUnitialised::from_gate(gate), ());
+        let (in_count, out_count) = gate.arity();
+        for i in &ins[..in_count] {
+            pc.add_edge(val_idx[i.index(val_idx.len())], gate_idx, ())
+                .expect("Value to gate edges are heterogeneous");
+        }
+        for i in &outs[..out_count] {
+            pc.add_edge(gate_idx, val_idx[i.index(val_idx.len())], ())
+                .expect("Gate to value edges are heterogeneous");
+        }
+    }
+    pc
+}
+
+/// Generate circuits where every gate satisfies its arity.
+/// Value nodes start with arbitrary values, hence gates can still be `InvalidValues`.
+///
+/// Shrinking removes whole gates (and value nodes), so failing cases reduce to minimal circuits.
+pub fn circuit_strategy(params: CircuitParams) -> impl Strategy<Value = PureCircuitGraph> {
+    let (val_start, val_end) = params.value_nodes.into_inner();
+    (
+        prop::collection::vec(any::<Value>(), val_start.max(1)..=val_end.max(1)),
+        prop::collection::vec(gate_spec_strategy(), params.gate_nodes),
+    )
+        .prop_map(|(values, gates)| build_circuit(values, gates))
+}
+
+/// Generate an assignment for the value nodes of the graph.
+/// The assignment follows the order of `PureCircuitGraph::to_chromosone`.
+pub fn assignment_strategy<T, G>(
+    graph: &PureCircuitGraph<T, G>,
+) -> impl Strategy<Value = Vec<Value>> + use<T, G> {
+    prop::collection::vec(any::<Value>(), graph.count_values())
+}
+
+/// Generate an arity-valid circuit together with an assignment for its value nodes.
+pub fn circuit_with_assignment_strategy(
+    params: CircuitParams,
+) -> impl Strategy<Value = (PureCircuitGraph, Vec<Value>)> {
+    circuit_strategy(params).prop_flat_map(|pc| {
+        let assignment = assignment_strategy(&pc);
+        (Just(pc), assignment)
+    })
+}
+
+impl Arbitrary for PureCircuitGraph {
+    type Parameters = CircuitParams;
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(args: Self::Parameters) -> Self::Strategy {
+        circuit_strategy(args).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gates::{GateStatus, NodeValue};
+    use proptest::test_runner::{Config, TestError, TestRunner};
+
+    fn gate_count(pc: &PureCircuitGraph) -> usize {
+        pc.graph.node_weights().filter(|n| n.node.is_gate()).count()
+    }
+
+    proptest! {
+        #[test]
+        fn circuits_have_valid_arity(pc in any::<PureCircuitGraph>()) {
+            for n in pc.graph.node_weights() {
+                if let NodeValue::GateNode { state_type, .. } = n.into_node() {
+                    prop_assert_ne!(state_type, GateStatus::InvalidArity);
+                }
+            }
+            prop_assert!(pc.to_fitness_function().is_ok());
+        }
+
+        #[test]
+        fn circuits_respect_params(pc in any_with::<PureCircuitGraph>(CircuitParams::new(3..=5, 2..=4))) {
+            prop_assert!((3..=5).contains(&pc.count_values()));
+            prop_assert!((2..=4).contains(&gate_count(&pc)));
+        }
+
+        #[test]
+        fn assignments_match_graph((pc, assignment) in circuit_with_assignment_strategy(CircuitParams::default())) {
+            prop_assert_eq!(assignment.len(), pc.count_values());
+            prop_assert!(pc.to_fitness_function().unwrap().evaluate(&assignment).is_ok());
+        }
+    }
+
+    #[test]
+    fn shrinks_to_minimal_circuit() {
+        let mut runner = TestRunner::new(Config::default());
+        let result = runner.run(
+            &circuit_strategy(CircuitParams::new(1..=20, 0..=20)),
+            |pc| {
+                prop_assert!(gate_count(&pc) < 3);
+                Ok(())
+            },
+        );
+        let Err(TestError::Fail(_, pc)) = result else {
+            panic!("Expected a failing case");
+        };
+        assert_eq!(gate_count(&pc), 3);
+        assert_eq!(pc.count_values(), 1);
+        assert!(pc.to_fitness_function().is_ok());
+    }
+}